@@ -1,6 +1,9 @@
 // cli: Command line interface parsing
 #![forbid(unsafe_code)]
 #![deny(missing_docs)]
+use crate::collectors;
+use crate::jailconf::DEFAULT_JAIL_CONF;
+use crate::options::DEFAULT_PREFIX;
 use clap::{
     crate_description,
     crate_name,
@@ -9,9 +12,18 @@ use clap::{
     ArgAction,
     ArgMatches,
     Command,
+    value_parser,
 };
+use std::path::PathBuf;
 use tracing::debug;
 
+#[cfg(feature = "jail_manager")]
+use crate::manager::{
+    DEFAULT_BASTILLE_ROOT,
+    DEFAULT_IOCAGE_ROOT,
+    DEFAULT_POT_ROOT,
+};
+
 mod validator;
 
 // Create a clap app
@@ -22,6 +34,61 @@ fn create_app() -> Command {
         .version(crate_version!())
         .about(crate_description!())
         .term_width(80)
+        .arg(
+            Arg::new("COLLECTOR_JAIL_CONF")
+                .action(ArgAction::Set)
+                .default_value(DEFAULT_JAIL_CONF)
+                .env("COLLECTOR_JAIL_CONF")
+                .help("jail.conf(5) to read configured jails from.")
+                .hide_env_values(true)
+                .long("collector.jail-conf")
+                .value_name("FILE")
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            Arg::new("COLLECTOR_JAIL_EXCLUDE")
+                .action(ArgAction::Append)
+                .env("COLLECTOR_JAIL_EXCLUDE")
+                .help("Don't export jails matching [PARAM=]REGEX. May be \
+                       given multiple times.")
+                .hide_env_values(true)
+                .long("collector.jail-exclude")
+                .value_name("[PARAM=]REGEX")
+                .value_parser(validator::is_valid_jail_matcher)
+        )
+        .arg(
+            Arg::new("COLLECTOR_JAIL_INCLUDE")
+                .action(ArgAction::Append)
+                .env("COLLECTOR_JAIL_INCLUDE")
+                .help("Only export jails matching [PARAM=]REGEX. May be \
+                       given multiple times.")
+                .hide_env_values(true)
+                .long("collector.jail-include")
+                .value_name("[PARAM=]REGEX")
+                .value_parser(validator::is_valid_jail_matcher)
+        )
+        .arg(
+            Arg::new("COLLECTOR_REAP_AFTER")
+                .action(ArgAction::Set)
+                .default_value("0")
+                .env("COLLECTOR_REAP_AFTER")
+                .help("Scrapes, or duration such as 5m, to keep the series \
+                       of vanished jails for.")
+                .hide_env_values(true)
+                .long("collector.reap-after")
+                .value_name("N|DURATION")
+                .value_parser(validator::is_valid_reap_after)
+        )
+        .arg(
+            Arg::new("CONFIG_FILE")
+                .action(ArgAction::Set)
+                .env("CONFIG_FILE")
+                .help("YAML configuration file, holding relabeling rules.")
+                .hide_env_values(true)
+                .long("config.file")
+                .value_name("FILE")
+                .value_parser(validator::is_valid_config_file_path)
+        )
         .arg(
             Arg::new("OUTPUT_FILE_PATH")
                 .action(ArgAction::Set)
@@ -32,6 +99,17 @@ fn create_app() -> Command {
                 .help("File to output metrics to.")
                 .value_parser(validator::is_valid_output_file_path)
         )
+        .arg(
+            Arg::new("WEB_CONST_LABEL")
+                .action(ArgAction::Append)
+                .env("WEB_CONST_LABEL")
+                .help("Constant KEY=VALUE label to add to every time series. \
+                       May be given multiple times.")
+                .hide_env_values(true)
+                .long("web.const-label")
+                .value_name("KEY=VALUE")
+                .value_parser(validator::is_valid_const_label)
+        )
         .arg(
             Arg::new("WEB_LISTEN_ADDRESS")
                 .action(ArgAction::Set)
@@ -43,6 +121,29 @@ fn create_app() -> Command {
                 .value_name("[ADDR:PORT]")
                 .value_parser(validator::is_valid_socket_addr)
         )
+        .arg(
+            Arg::new("WEB_METRIC_PREFIX")
+                .action(ArgAction::Set)
+                .default_value(DEFAULT_PREFIX)
+                .env("WEB_METRIC_PREFIX")
+                .help("Prefix of the names of the exported time series.")
+                .hide_env_values(true)
+                .long("web.metric-prefix")
+                .value_name("PREFIX")
+                .value_parser(validator::is_valid_metric_prefix)
+        )
+        .arg(
+            Arg::new("WEB_NAMING_SCHEME")
+                .action(ArgAction::Set)
+                .default_value("legacy")
+                .env("WEB_NAMING_SCHEME")
+                .help("Naming scheme of the rctl time series, legacy or \
+                       conventional.")
+                .hide_env_values(true)
+                .long("web.naming-scheme")
+                .value_name("SCHEME")
+                .value_parser(validator::is_valid_naming_scheme)
+        )
         .arg(
             Arg::new("WEB_TELEMETRY_PATH")
                 .action(ArgAction::Set)
@@ -67,6 +168,66 @@ fn create_app() -> Command {
             .value_parser(validator::is_valid_basic_auth_config_path)
     );
 
+    #[cfg(feature = "jail_manager")]
+    let app = app
+        .arg(
+            Arg::new("COLLECTOR_JAIL_MANAGER_BASTILLE_ROOT")
+                .action(ArgAction::Set)
+                .default_value(DEFAULT_BASTILLE_ROOT)
+                .env("COLLECTOR_JAIL_MANAGER_BASTILLE_ROOT")
+                .help("Root directory of Bastille.")
+                .hide_env_values(true)
+                .long("collector.jail-manager.bastille-root")
+                .value_name("DIR")
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            Arg::new("COLLECTOR_JAIL_MANAGER_IOCAGE_ROOT")
+                .action(ArgAction::Set)
+                .default_value(DEFAULT_IOCAGE_ROOT)
+                .env("COLLECTOR_JAIL_MANAGER_IOCAGE_ROOT")
+                .help("Root directory of iocage.")
+                .hide_env_values(true)
+                .long("collector.jail-manager.iocage-root")
+                .value_name("DIR")
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            Arg::new("COLLECTOR_JAIL_MANAGER_POT_ROOT")
+                .action(ArgAction::Set)
+                .default_value(DEFAULT_POT_ROOT)
+                .env("COLLECTOR_JAIL_MANAGER_POT_ROOT")
+                .help("Root directory of pot.")
+                .hide_env_values(true)
+                .long("collector.jail-manager.pot-root")
+                .value_name("DIR")
+                .value_parser(value_parser!(PathBuf))
+        );
+
+    // Flags enabling and disabling each collector. Those of the individual
+    // rctl resources, and the flags disabling collectors, are only shown in
+    // the long help.
+    let app = collectors::all().fold(app, |app, collector| {
+        app.arg(
+            Arg::new(collector.id)
+                .action(ArgAction::SetTrue)
+                .env(collector.id)
+                .help(collector.help)
+                .hide_env_values(true)
+                .hide_short_help(collector.group.is_some())
+                .long(collector.long)
+                .overrides_with(collector.no_id)
+        )
+        .arg(
+            Arg::new(collector.no_id)
+                .action(ArgAction::SetTrue)
+                .help(format!("Disable the {} collector.", collector.name))
+                .hide_short_help(true)
+                .long(collector.no_long)
+                .overrides_with(collector.id)
+        )
+    });
+
     #[cfg(feature = "bcrypt_cmd")]
     let app = {
         let bcrypt = Command::new("bcrypt")
@@ -132,11 +293,18 @@ pub fn parse_args() -> ArgMatches {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::filter::JailMatcher;
+    use crate::options::{
+        ConstLabel,
+        NamingScheme,
+    };
+    use crate::reaper::ReapAfter;
     use parking_lot::Mutex;
     use pretty_assertions::assert_eq;
     use std::env;
     use std::panic;
     use std::sync::LazyLock;
+    use std::time::Duration;
 
     // Used during env_tests
     static LOCK: LazyLock<Mutex<i8>> = LazyLock::new(|| Mutex::new(0));
@@ -159,6 +327,276 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn default_collector_jail_conf() {
+        // Must lock since we're still testing env vars here even though we're
+        // not setting one.
+        let _locked = LOCK.lock();
+
+        let argv = vec!["jail_exporter"];
+        let matches = create_app().get_matches_from(argv);
+        let jail_conf = matches.get_one::<PathBuf>("COLLECTOR_JAIL_CONF");
+
+        assert_eq!(jail_conf, Some(&PathBuf::from("/etc/jail.conf")));
+    }
+
+    #[test]
+    fn default_collector_jail_filter() {
+        // Must lock since we're still testing env vars here even though we're
+        // not setting one.
+        let _locked = LOCK.lock();
+
+        let argv = vec!["jail_exporter"];
+        let matches = create_app().get_matches_from(argv);
+
+        assert!(matches.get_many::<JailMatcher>("COLLECTOR_JAIL_EXCLUDE").is_none());
+        assert!(matches.get_many::<JailMatcher>("COLLECTOR_JAIL_INCLUDE").is_none());
+    }
+
+    #[test]
+    fn cli_set_collector_jail_include() {
+        // Must lock since we're still testing env vars here even though we're
+        // not setting one.
+        let _locked = LOCK.lock();
+
+        let argv = vec![
+            "jail_exporter",
+            "--collector.jail-include=^prod-",
+            "--collector.jail-include=path=^/jails/",
+        ];
+        let matches = create_app().get_matches_from(argv);
+        let include: Vec<String> = matches
+            .get_many::<JailMatcher>("COLLECTOR_JAIL_INCLUDE")
+            .unwrap()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(include, vec!["name=^prod-", "path=^/jails/"]);
+    }
+
+    #[test]
+    fn env_set_collector_jail_exclude() {
+        env_test("COLLECTOR_JAIL_EXCLUDE", "host.hostname=^ci-", || {
+            let argv = vec!["jail_exporter"];
+            let matches = create_app().get_matches_from(argv);
+            let exclude: Vec<String> = matches
+                .get_many::<JailMatcher>("COLLECTOR_JAIL_EXCLUDE")
+                .unwrap()
+                .map(ToString::to_string)
+                .collect();
+
+            assert_eq!(exclude, vec!["host.hostname=^ci-"]);
+        });
+    }
+
+    #[cfg(feature = "jail_manager")]
+    #[test]
+    fn default_collector_jail_manager() {
+        // Must lock since we're still testing env vars here even though we're
+        // not setting one.
+        let _locked = LOCK.lock();
+
+        let argv = vec!["jail_exporter"];
+        let matches = create_app().get_matches_from(argv);
+
+        assert!(!matches.get_flag("COLLECTOR_JAIL_MANAGER"));
+        assert_eq!(
+            matches.get_one::<PathBuf>("COLLECTOR_JAIL_MANAGER_IOCAGE_ROOT"),
+            Some(&PathBuf::from("/iocage")),
+        );
+    }
+
+    #[cfg(feature = "jail_manager")]
+    #[test]
+    fn env_set_collector_jail_manager() {
+        env_test("COLLECTOR_JAIL_MANAGER", "true", || {
+            let argv = vec!["jail_exporter"];
+            let matches = create_app().get_matches_from(argv);
+
+            assert!(matches.get_flag("COLLECTOR_JAIL_MANAGER"));
+        });
+    }
+
+    #[test]
+    fn default_collector_network() {
+        // Must lock since we're still testing env vars here even though we're
+        // not setting one.
+        let _locked = LOCK.lock();
+
+        let argv = vec!["jail_exporter"];
+        let matches = create_app().get_matches_from(argv);
+
+        assert!(!matches.get_flag("COLLECTOR_NETWORK"));
+    }
+
+    #[test]
+    fn env_set_collector_network() {
+        env_test("COLLECTOR_NETWORK", "true", || {
+            let argv = vec!["jail_exporter"];
+            let matches = create_app().get_matches_from(argv);
+
+            assert!(matches.get_flag("COLLECTOR_NETWORK"));
+        });
+    }
+
+    #[test]
+    fn default_collector_processes() {
+        // Must lock since we're still testing env vars here even though we're
+        // not setting one.
+        let _locked = LOCK.lock();
+
+        let argv = vec!["jail_exporter"];
+        let matches = create_app().get_matches_from(argv);
+
+        assert!(!matches.get_flag("COLLECTOR_PROCESSES"));
+    }
+
+    #[test]
+    fn env_set_collector_processes() {
+        env_test("COLLECTOR_PROCESSES", "true", || {
+            let argv = vec!["jail_exporter"];
+            let matches = create_app().get_matches_from(argv);
+
+            assert!(matches.get_flag("COLLECTOR_PROCESSES"));
+        });
+    }
+
+    #[test]
+    fn default_collector_reap_after() {
+        // Must lock since we're still testing env vars here even though we're
+        // not setting one.
+        let _locked = LOCK.lock();
+
+        let argv = vec!["jail_exporter"];
+        let matches = create_app().get_matches_from(argv);
+        let reap_after = matches.get_one::<ReapAfter>("COLLECTOR_REAP_AFTER");
+
+        assert_eq!(reap_after, Some(&ReapAfter::Scrapes(0)));
+    }
+
+    #[test]
+    fn cli_set_collector_reap_after() {
+        // Must lock since we're still testing env vars here even though we're
+        // not setting one.
+        let _locked = LOCK.lock();
+
+        let argv = vec![
+            "jail_exporter",
+            "--collector.reap-after=5m",
+        ];
+        let matches = create_app().get_matches_from(argv);
+        let reap_after = matches.get_one::<ReapAfter>("COLLECTOR_REAP_AFTER");
+        let ok = ReapAfter::Duration(Duration::from_secs(300));
+
+        assert_eq!(reap_after, Some(&ok));
+    }
+
+    #[test]
+    fn default_collector_zfs() {
+        // Must lock since we're still testing env vars here even though we're
+        // not setting one.
+        let _locked = LOCK.lock();
+
+        let argv = vec!["jail_exporter"];
+        let matches = create_app().get_matches_from(argv);
+
+        assert!(!matches.get_flag("COLLECTOR_ZFS"));
+    }
+
+    #[test]
+    fn env_set_collector_zfs() {
+        env_test("COLLECTOR_ZFS", "true", || {
+            let argv = vec!["jail_exporter"];
+            let matches = create_app().get_matches_from(argv);
+
+            assert!(matches.get_flag("COLLECTOR_ZFS"));
+        });
+    }
+
+    #[test]
+    fn env_set_config_file() {
+        env_test("CONFIG_FILE", "test-data/config/config.yaml", || {
+            let argv = vec!["jail_exporter"];
+            let matches = create_app().get_matches_from(argv);
+            let config_file = matches.get_one::<PathBuf>("CONFIG_FILE");
+            let ok = PathBuf::from("test-data/config/config.yaml");
+
+            assert_eq!(config_file, Some(&ok));
+        });
+    }
+
+    #[test]
+    fn cli_set_web_const_label() {
+        // Must lock since we're still testing env vars here even though we're
+        // not setting one.
+        let _locked = LOCK.lock();
+
+        let argv = vec![
+            "jail_exporter",
+            "--web.const-label=datacenter=dc1",
+            "--web.const-label=host_role=web",
+        ];
+        let matches = create_app().get_matches_from(argv);
+        let labels: Vec<ConstLabel> = matches
+            .get_many::<ConstLabel>("WEB_CONST_LABEL")
+            .unwrap()
+            .cloned()
+            .collect();
+
+        let ok = vec![
+            ConstLabel::new("datacenter", "dc1").unwrap(),
+            ConstLabel::new("host_role", "web").unwrap(),
+        ];
+
+        assert_eq!(labels, ok);
+    }
+
+    #[test]
+    fn env_set_web_const_label() {
+        env_test("WEB_CONST_LABEL", "datacenter=dc1", || {
+            let argv = vec!["jail_exporter"];
+            let matches = create_app().get_matches_from(argv);
+            let label = matches.get_one::<ConstLabel>("WEB_CONST_LABEL");
+            let ok = ConstLabel::new("datacenter", "dc1").unwrap();
+
+            assert_eq!(label, Some(&ok));
+        });
+    }
+
+    #[test]
+    fn cli_set_no_collector() {
+        // Must lock since we're still testing env vars here even though we're
+        // not setting one.
+        let _locked = LOCK.lock();
+
+        let argv = vec![
+            "jail_exporter",
+            "--no-collector.rctl",
+            "--collector.rctl.pcpu",
+            "--no-collector.rctl.pcpu",
+            "--no-collector.limits",
+            "--collector.limits",
+        ];
+        let matches = create_app().get_matches_from(argv);
+
+        // The last of the flags for each collector wins.
+        assert!(matches.get_flag("NO_COLLECTOR_RCTL"));
+        assert!(matches.get_flag("NO_COLLECTOR_RCTL_PCPU"));
+        assert!(!matches.get_flag("COLLECTOR_RCTL_PCPU"));
+        assert!(matches.get_flag("COLLECTOR_LIMITS"));
+        assert!(!matches.get_flag("NO_COLLECTOR_LIMITS"));
+    }
+
+    #[test]
+    fn env_set_collector_rctl_resource() {
+        env_test("COLLECTOR_RCTL_NMSGQ", "true", || {
+            let argv = vec!["jail_exporter"];
+            let matches = create_app().get_matches_from(argv);
+
+            assert!(matches.get_flag("COLLECTOR_RCTL_NMSGQ"));
+        });
+    }
+
     #[test]
     fn default_web_listen_address() {
         // Must lock since we're still testing env vars here even though we're
@@ -172,6 +610,59 @@ mod tests {
         assert_eq!(listen_address, Some(&"127.0.0.1:9452".into()));
     }
 
+    #[test]
+    fn default_web_metric_prefix() {
+        // Must lock since we're still testing env vars here even though we're
+        // not setting one.
+        let _locked = LOCK.lock();
+
+        let argv = vec!["jail_exporter"];
+        let matches = create_app().get_matches_from(argv);
+        let prefix = matches.get_one::<String>("WEB_METRIC_PREFIX");
+
+        assert_eq!(prefix, Some(&"jail".into()));
+    }
+
+    #[test]
+    fn cli_set_web_metric_prefix() {
+        // Must lock since we're still testing env vars here even though we're
+        // not setting one.
+        let _locked = LOCK.lock();
+
+        let argv = vec![
+            "jail_exporter",
+            "--web.metric-prefix=freebsd_jail",
+        ];
+        let matches = create_app().get_matches_from(argv);
+        let prefix = matches.get_one::<String>("WEB_METRIC_PREFIX");
+
+        assert_eq!(prefix, Some(&"freebsd_jail".into()));
+    }
+
+    #[test]
+    fn default_web_naming_scheme() {
+        // Must lock since we're still testing env vars here even though we're
+        // not setting one.
+        let _locked = LOCK.lock();
+
+        let argv = vec!["jail_exporter"];
+        let matches = create_app().get_matches_from(argv);
+        let naming = matches.get_one::<NamingScheme>("WEB_NAMING_SCHEME");
+
+        assert_eq!(naming, Some(&NamingScheme::Legacy));
+    }
+
+    #[test]
+    fn env_set_web_naming_scheme() {
+        env_test("WEB_NAMING_SCHEME", "conventional", || {
+            let argv = vec!["jail_exporter"];
+            let matches = create_app().get_matches_from(argv);
+            let naming = matches.get_one::<NamingScheme>("WEB_NAMING_SCHEME");
+
+            assert_eq!(naming, Some(&NamingScheme::Conventional));
+        });
+    }
+
     #[test]
     fn default_web_telemetry_path() {
         // Must lock since we're still testing env vars here even though we're
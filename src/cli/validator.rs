@@ -2,14 +2,22 @@
 #![forbid(unsafe_code)]
 #![deny(missing_docs)]
 use crate::file::FileExporterOutput;
+use crate::filter::JailMatcher;
+use crate::options::{
+    self,
+    ConstLabel,
+    NamingScheme,
+};
+use crate::reaper::ReapAfter;
 use std::net::SocketAddr;
-use std::path::Path;
+use std::path::{
+    Path,
+    PathBuf,
+};
 use std::str::FromStr;
+use std::time::Duration;
 use tracing::debug;
 
-#[cfg(feature = "auth")]
-use std::path::PathBuf;
-
 #[cfg(feature = "auth")]
 // Basic checks for valid filesystem path for web.auth-config existing.
 pub fn is_valid_basic_auth_config_path(s: &str) -> Result<PathBuf, String> {
@@ -60,6 +68,55 @@ pub fn is_valid_length(s: &str) -> Result<usize, String> {
     Ok(length)
 }
 
+// Basic checks for valid filesystem path for config.file existing.
+pub fn is_valid_config_file_path(s: &str) -> Result<PathBuf, String> {
+    debug!("Ensuring that config.file is valid");
+
+    let path = Path::new(&s);
+
+    if !path.is_file() {
+        return Err("config.file doesn't exist".to_owned());
+    }
+
+    Ok(path.to_path_buf())
+}
+
+// Parses web.const-label, a key=value pair.
+pub fn is_valid_const_label(s: &str) -> Result<ConstLabel, String> {
+    debug!("Ensuring that web.const-label is valid");
+
+    s.parse::<ConstLabel>()
+        .map_err(|e| format!("web.const-label {e}"))
+}
+
+// Parses collector.jail-include and collector.jail-exclude, a regex optionally
+// prefixed with the jail parameter to match against.
+pub fn is_valid_jail_matcher(s: &str) -> Result<JailMatcher, String> {
+    debug!("Ensuring that jail matcher is valid");
+
+    s.parse::<JailMatcher>()
+        .map_err(|e| format!("'{s}' is not a valid jail matcher: {e}"))
+}
+
+// Checks that web.metric-prefix is valid at the start of a metric name.
+pub fn is_valid_metric_prefix(s: &str) -> Result<String, String> {
+    debug!("Ensuring that web.metric-prefix is valid");
+
+    if !options::is_valid_prefix(s) {
+        return Err(format!("'{s}' is not a valid web.metric-prefix"));
+    }
+
+    Ok(s.into())
+}
+
+// Parses web.naming-scheme, either legacy or conventional.
+pub fn is_valid_naming_scheme(s: &str) -> Result<NamingScheme, String> {
+    debug!("Ensuring that web.naming-scheme is valid");
+
+    s.parse::<NamingScheme>()
+        .map_err(|e| format!("web.naming-scheme {e}"))
+}
+
 // Basic checks for valid filesystem path for .prom output file
 pub fn is_valid_output_file_path(s: &str) -> Result<FileExporterOutput, String> {
     debug!("Ensuring that output.file-path is valid");
@@ -123,6 +180,38 @@ pub fn is_valid_password(s: &str) -> Result<String, String> {
    Ok(s.to_string())
 }
 
+// Parses collector.reap-after, either a number of scrapes or a duration with
+// an s, m or h suffix.
+pub fn is_valid_reap_after(s: &str) -> Result<ReapAfter, String> {
+    debug!("Ensuring that collector.reap-after is valid");
+
+    let invalid = || {
+        format!("collector.reap-after must be a number of scrapes or a \
+                 duration such as 30s, 5m or 1h, got '{s}'")
+    };
+
+    let (number, multiplier) = match s.char_indices().last() {
+        Some((i, 's')) => (&s[..i], Some(1)),
+        Some((i, 'm')) => (&s[..i], Some(60)),
+        Some((i, 'h')) => (&s[..i], Some(3600)),
+        _              => (s, None),
+    };
+
+    let Ok(number) = number.parse::<u32>() else {
+        return Err(invalid());
+    };
+
+    let reap_after = match multiplier {
+        Some(multiplier) => {
+            let secs = u64::from(number) * multiplier;
+            ReapAfter::Duration(Duration::from_secs(secs))
+        },
+        None => ReapAfter::Scrapes(number),
+    };
+
+    Ok(reap_after)
+}
+
 // Used as a validator for the argument parsing.
 // We validate the parse to SocketAddr here but still continue to return a
 // string. HttpServer::bind is fine with taking a string there.
@@ -164,6 +253,70 @@ pub fn is_valid_telemetry_path(s: &str) -> Result<String, String> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn is_valid_config_file_path_missing() {
+        let res = is_valid_config_file_path("test-data/config/missing.yaml");
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn is_valid_config_file_path_ok() {
+        let res = is_valid_config_file_path("test-data/config/config.yaml");
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn is_valid_const_label_ok() {
+        let res = is_valid_const_label("datacenter=dc1");
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn is_valid_const_label_invalid() {
+        for input in ["datacenter", "name=www", "data-center=dc1"] {
+            assert!(is_valid_const_label(input).is_err());
+        }
+    }
+
+    #[test]
+    fn is_valid_jail_matcher_ok() {
+        for input in ["^www$", "path=^/jails/", "host.hostname=example"] {
+            assert!(is_valid_jail_matcher(input).is_ok());
+        }
+    }
+
+    #[test]
+    fn is_valid_jail_matcher_invalid() {
+        for input in ["(", "path=[a-"] {
+            assert!(is_valid_jail_matcher(input).is_err());
+        }
+    }
+
+    #[test]
+    fn is_valid_metric_prefix_ok() {
+        let res = is_valid_metric_prefix("freebsd_jail");
+        assert_eq!(res, Ok("freebsd_jail".into()));
+    }
+
+    #[test]
+    fn is_valid_metric_prefix_invalid() {
+        for input in ["", "1jail", "jail-stats", "__jail"] {
+            assert!(is_valid_metric_prefix(input).is_err());
+        }
+    }
+
+    #[test]
+    fn is_valid_naming_scheme_ok() {
+        let res = is_valid_naming_scheme("conventional");
+        assert_eq!(res, Ok(NamingScheme::Conventional));
+    }
+
+    #[test]
+    fn is_valid_naming_scheme_invalid() {
+        let res = is_valid_naming_scheme("modern");
+        assert!(res.is_err());
+    }
+
     #[test]
     fn is_valid_output_file_path_absolute_path() {
         let res = is_valid_output_file_path("tmp/metrics.prom");
@@ -212,6 +365,28 @@ mod tests {
         assert!(res.is_ok());
     }
 
+    #[test]
+    fn is_valid_reap_after_ok() {
+        let tests = [
+            ("0", ReapAfter::Scrapes(0)),
+            ("3", ReapAfter::Scrapes(3)),
+            ("30s", ReapAfter::Duration(Duration::from_secs(30))),
+            ("5m", ReapAfter::Duration(Duration::from_secs(300))),
+            ("1h", ReapAfter::Duration(Duration::from_secs(3600))),
+        ];
+
+        for (input, ok) in tests {
+            assert_eq!(is_valid_reap_after(input), Ok(ok));
+        }
+    }
+
+    #[test]
+    fn is_valid_reap_after_invalid() {
+        for input in ["", "s", "-1", "5d", "1.5m", "nope"] {
+            assert!(is_valid_reap_after(input).is_err());
+        }
+    }
+
     #[test]
     fn is_valid_socket_addr_ipv4_with_port() {
         let res = is_valid_socket_addr("127.0.0.1:9452");
@@ -0,0 +1,274 @@
+// collectors: This module handles enabling and disabling the collectors,
+//             the groups of time series that the exporter can export.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::rctltypes::Resource;
+use crate::rusage::{
+    self,
+    RESOURCES,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// A collector of time series, which can be enabled or disabled with the
+/// `--collector.<name>` and `--no-collector.<name>` flags, or by name in the
+/// configuration file.
+#[derive(Debug)]
+pub struct Collector {
+    /// Name of the collector.
+    pub name: &'static str,
+
+    /// Help for the `--collector.<name>` flag.
+    pub help: &'static str,
+
+    /// Whether the collector is enabled when it isn't configured.
+    pub default: bool,
+
+    /// The group that the collector belongs to, which it follows when it
+    /// hasn't been configured.
+    pub group: Option<&'static str>,
+
+    /// ID of the `--collector.<name>` flag, also its environment variable.
+    pub id: &'static str,
+
+    /// Long name of the `--collector.<name>` flag.
+    pub long: &'static str,
+
+    /// ID of the `--no-collector.<name>` flag.
+    pub no_id: &'static str,
+
+    /// Long name of the `--no-collector.<name>` flag.
+    pub no_long: &'static str,
+}
+
+// Defines a collector. The ID is the upper case name, with the characters
+// that can't be used in environment variables replaced by _.
+macro_rules! collector {
+    ($name:literal, $id:literal, $default:expr, $help:literal $(,)?) => {
+        Collector {
+            name:    $name,
+            help:    $help,
+            default: $default,
+            group:   None,
+            id:      concat!("COLLECTOR_", $id),
+            long:    concat!("collector.", $name),
+            no_id:   concat!("NO_COLLECTOR_", $id),
+            no_long: concat!("no-collector.", $name),
+        }
+    };
+}
+
+/// Name of the group of the collectors of individual rctl resources.
+pub const RCTL: &str = "rctl";
+
+// Collectors of groups of time series. Those requiring the exporter to run
+// other commands are disabled by default.
+const GROUPS: [Collector; 9] = [
+    collector!(
+        "filesystem",
+        "FILESYSTEM",
+        true,
+        "Export the usage of the filesystems holding the jails.",
+    ),
+    collector!(
+        "hierarchy",
+        "HIERARCHY",
+        true,
+        "Export the parents and child jail counts of jails.",
+    ),
+    collector!(
+        "lifecycle",
+        "LIFECYCLE",
+        true,
+        "Export the starts, stops and restarts of jails.",
+    ),
+    collector!(
+        "limits",
+        "LIMITS",
+        true,
+        "Export the rctl(8) rules set on jails.",
+    ),
+    collector!(
+        "network",
+        "NETWORK",
+        false,
+        "Export network interface counters for VNET jails.",
+    ),
+    collector!(
+        "processes",
+        "PROCESSES",
+        false,
+        "Export process states of jails.",
+    ),
+    collector!(
+        "rctl",
+        "RCTL",
+        true,
+        "Export the rctl(8) resource usage of jails. Individual resources \
+         are enabled with --collector.rctl.<resource>.",
+    ),
+    collector!(
+        "start-time",
+        "START_TIME",
+        true,
+        "Export the start times of jails.",
+    ),
+    collector!(
+        "zfs",
+        "ZFS",
+        false,
+        "Export ZFS dataset metrics for jail root datasets.",
+    ),
+];
+
+// Reading jail metadata is only available with the jail_manager feature.
+#[cfg(feature = "jail_manager")]
+const JAIL_MANAGER: [Collector; 1] = [
+    collector!(
+        "jail-manager",
+        "JAIL_MANAGER",
+        false,
+        "Enable reading jail metadata from jail managers.",
+    ),
+];
+
+#[cfg(not(feature = "jail_manager"))]
+const JAIL_MANAGER: [Collector; 0] = [];
+
+/// Returns every collector, the groups first, followed by those of the
+/// individual rctl resources.
+pub fn all() -> impl Iterator<Item = &'static Collector> {
+    GROUPS.iter()
+        .chain(JAIL_MANAGER.iter())
+        .chain(RESOURCES.iter().map(|metric| &metric.collector))
+}
+
+// Returns the named collector.
+fn find(name: &str) -> Option<&'static Collector> {
+    all().find(|collector| collector.name == name)
+}
+
+/// The collectors that have been enabled or disabled, falling back to the
+/// default of each collector.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(try_from = "BTreeMap<String, bool>")]
+pub struct Collectors {
+    settings: BTreeMap<&'static str, bool>,
+}
+
+impl TryFrom<BTreeMap<String, bool>> for Collectors {
+    type Error = String;
+
+    fn try_from(settings: BTreeMap<String, bool>) -> Result<Self, Self::Error> {
+        let mut collectors = Self::default();
+
+        for (name, enabled) in settings {
+            collectors.set(&name, enabled)?;
+        }
+
+        Ok(collectors)
+    }
+}
+
+impl Collectors {
+    /// Enables or disables the named collector.
+    pub fn set(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        let Some(collector) = find(name) else {
+            return Err(format!("unknown collector '{name}'"));
+        };
+
+        self.insert(collector, enabled);
+
+        Ok(())
+    }
+
+    /// Enables or disables the given collector.
+    pub fn insert(&mut self, collector: &'static Collector, enabled: bool) {
+        self.settings.insert(collector.name, enabled);
+    }
+
+    /// Returns true if the named collector is enabled.
+    ///
+    /// Collectors that haven't been configured follow their group, such as
+    /// those of the individual rctl resources following the rctl group.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        if let Some(enabled) = self.settings.get(name) {
+            return *enabled;
+        }
+
+        let Some(collector) = find(name) else {
+            return false;
+        };
+
+        if let Some(group) = collector.group {
+            return self.is_enabled(group);
+        }
+
+        collector.default
+    }
+
+    /// Returns true if the collector of the given rctl `resource` is enabled.
+    pub fn is_resource_enabled(&self, resource: Resource) -> bool {
+        rusage::find(resource)
+            .is_some_and(|metric| self.is_enabled(metric.collector.name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn collectors_defaults() {
+        let collectors = Collectors::default();
+
+        assert!(collectors.is_enabled("rctl"));
+        assert!(collectors.is_enabled("rctl.nmsgq"));
+        assert!(collectors.is_enabled("limits"));
+        assert!(!collectors.is_enabled("network"));
+        assert!(!collectors.is_enabled("processes"));
+        assert!(collectors.is_enabled("start-time"));
+        assert!(!collectors.is_enabled("unknown"));
+        assert!(collectors.is_resource_enabled(Resource::NMsgq));
+    }
+
+    #[test]
+    fn collectors_rctl_group() {
+        let mut collectors = Collectors::default();
+        collectors.set("rctl", false).unwrap();
+        collectors.set("rctl.pcpu", true).unwrap();
+
+        assert!(!collectors.is_resource_enabled(Resource::CpuTime));
+        assert!(collectors.is_resource_enabled(Resource::PercentCpu));
+    }
+
+    #[test]
+    fn collectors_set_unknown() {
+        let mut collectors = Collectors::default();
+        let res = collectors.set("rctl.unknown", false);
+
+        assert_eq!(res, Err("unknown collector 'rctl.unknown'".into()));
+    }
+
+    #[test]
+    fn collectors_deserialize() {
+        let collectors: Collectors = serde_yaml::from_str(
+            "network: true\nrctl.nmsgq: false",
+        ).unwrap();
+
+        assert!(collectors.is_enabled("network"));
+        assert!(!collectors.is_resource_enabled(Resource::NMsgq));
+        assert!(serde_yaml::from_str::<Collectors>("other: true").is_err());
+    }
+
+    #[test]
+    fn collectors_flags() {
+        let collector = find("rctl.pcpu").unwrap();
+
+        assert_eq!(collector.id, "COLLECTOR_RCTL_PCPU");
+        assert_eq!(collector.long, "collector.rctl.pcpu");
+        assert_eq!(collector.no_id, "NO_COLLECTOR_RCTL_PCPU");
+        assert_eq!(collector.no_long, "no-collector.rctl.pcpu");
+    }
+}
@@ -0,0 +1,37 @@
+// command: This module runs external commands on behalf of the collectors
+//          that gather their data from FreeBSD utilities.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::errors::ExporterError;
+use std::process::Command;
+use tracing::debug;
+
+/// Runs external commands, allowing their output to be faked during tests.
+pub trait CommandRunner {
+    /// Runs `program` with `args`, returning its standard output.
+    fn run(&self, program: &str, args: &[&str]) -> Result<String, ExporterError>;
+}
+
+/// A `CommandRunner` that executes commands on the running system.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemCommandRunner;
+
+impl CommandRunner for SystemCommandRunner {
+    fn run(&self, program: &str, args: &[&str]) -> Result<String, ExporterError> {
+        debug!("Running: {program} {}", args.join(" "));
+
+        let output = Command::new(program).args(args).output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+
+            return Err(ExporterError::CommandFailed(format!(
+                "{program}: {}: {}",
+                output.status,
+                stderr.trim(),
+            )));
+        }
+
+        Ok(String::from_utf8(output.stdout)?)
+    }
+}
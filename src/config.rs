@@ -0,0 +1,101 @@
+// config: This module handles the exporter configuration file, holding the
+//         settings that are awkward to give on the command line.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::collectors::Collectors;
+use crate::errors::ExporterError;
+use crate::filter::JailMatcher;
+use crate::meta::MetaConfig;
+use crate::options::ConstLabels;
+use crate::relabel::Relabeler;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use tracing::debug;
+
+/// Exporter configuration, read from a YAML file.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Collectors to enable or disable.
+    #[serde(default)]
+    pub collectors: Collectors,
+
+    /// Constant labels added to every time series.
+    #[serde(default)]
+    pub const_labels: ConstLabels,
+
+    /// Jails that aren't exported.
+    #[serde(default)]
+    pub jail_exclude: Vec<JailMatcher>,
+
+    /// Jails to export, all of them if empty.
+    #[serde(default)]
+    pub jail_include: Vec<JailMatcher>,
+
+    /// Labels taken from the meta and env parameters of each jail.
+    #[serde(default)]
+    pub meta_labels: MetaConfig,
+
+    /// Relabeling rules applied to the labels of each jail.
+    #[serde(default)]
+    pub relabel_configs: Relabeler,
+}
+
+impl Config {
+    /// Loads the YAML configuration at `path`.
+    pub fn from_yaml(path: &Path) -> Result<Self, ExporterError> {
+        debug!("Loading configuration from: {}", path.display());
+
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        let config: Self = serde_yaml::from_reader(reader)?;
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jailsource::Jail;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn config_from_yaml() {
+        let path = Path::new("test-data/config/config.yaml");
+        let config = Config::from_yaml(path).unwrap();
+
+        let labels = config.relabel_configs
+            .relabel(&Jail::new(1, "prod-www"))
+            .unwrap();
+
+        assert_eq!(labels.name, "www");
+        assert_eq!(config.meta_labels.keys.len(), 2);
+        assert!(!config.meta_labels.all_series);
+        assert_ne!(config.const_labels, ConstLabels::default());
+        assert!(!config.collectors.is_enabled("rctl.nmsgq"));
+
+        let matchers: Vec<String> = config.jail_exclude.iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(matchers, vec!["name=^ci-", "path=^/tmp/"]);
+        assert!(config.jail_include.is_empty());
+    }
+
+    #[test]
+    fn config_jail_matcher_invalid() {
+        let config = serde_yaml::from_str::<Config>("jail_include: [\"(\"]");
+
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn config_from_yaml_invalid() {
+        let path = Path::new("test-data/config/invalid.yaml");
+
+        assert!(Config::from_yaml(path).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+// created: Counters exported along with the OpenMetrics _created sample of
+//          their series.
+//          prometheus_client has no way of encoding _created samples, so
+//          these counters encode it as a bare sample following their _total
+//          sample, which is named once the registry has been encoded.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use prometheus_client::encoding::{
+    EncodeMetric,
+    MetricEncoder,
+    NoLabelSet,
+};
+use prometheus_client::metrics::{
+    counter::Counter,
+    MetricType,
+    TypedMetric,
+};
+use std::fmt;
+use std::sync::atomic::AtomicU64;
+use std::time::{
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+/// A counter of a value kept by the OS, along with the time that its series
+/// was created.
+///
+/// The series is created when the counter is first requested from its
+/// family. The counters kept by the OS reset when a jail restarts, so the
+/// counters of a jail restarted under a new jail ID are removed, and created
+/// again.
+#[derive(Clone, Debug)]
+pub struct CreatedCounter {
+    counter: Counter,
+
+    // Seconds since the Unix epoch.
+    created: f64,
+}
+
+impl Default for CreatedCounter {
+    fn default() -> Self {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |now| now.as_secs_f64());
+
+        Self {
+            counter: Counter::default(),
+            created: created,
+        }
+    }
+}
+
+impl CreatedCounter {
+    /// Returns the value of the counter.
+    pub fn get(&self) -> u64 {
+        self.counter.get()
+    }
+
+    /// Returns the atomic holding the value of the counter, so that the
+    /// value from the OS can be stored directly.
+    pub fn inner(&self) -> &AtomicU64 {
+        self.counter.inner()
+    }
+}
+
+impl TypedMetric for CreatedCounter {
+    const TYPE: MetricType = MetricType::Counter;
+}
+
+impl EncodeMetric for CreatedCounter {
+    fn encode(&self, mut encoder: MetricEncoder) -> Result<(), fmt::Error> {
+        encoder.encode_counter::<NoLabelSet, _, u64>(&self.get(), None)?;
+
+        // This is encoded without a suffix, and named by `name_created`.
+        encoder.encode_gauge(&self.created)
+    }
+
+    fn metric_type(&self) -> MetricType {
+        Self::TYPE
+    }
+}
+
+// Returns the metric name of an encoded sample line, along with the rest of
+// the line.
+fn split_name(line: &str) -> (&str, &str) {
+    let end = line.find(['{', ' ']).unwrap_or(line.len());
+
+    line.split_at(end)
+}
+
+/// Returns the encoded `output` with the _created samples of the
+/// `CreatedCounter`s named.
+///
+/// These are the samples directly following a _total sample, named as its
+/// metric without the suffix. Nothing else is encoded like this.
+pub fn name_created(output: &str) -> String {
+    let mut encoded = String::with_capacity(output.len());
+    let mut metric = None;
+
+    for line in output.lines() {
+        let (name, rest) = split_name(line);
+
+        if metric == Some(name) {
+            encoded.push_str(name);
+            encoded.push_str("_created");
+            encoded.push_str(rest);
+        }
+        else {
+            encoded.push_str(line);
+        }
+
+        encoded.push('\n');
+
+        // The metric that a _created sample on the next line would belong
+        // to.
+        metric = name.strip_suffix("_total")
+            .filter(|_| !line.starts_with('#'));
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use prometheus_client::encoding::text::encode;
+    use prometheus_client::metrics::family::Family;
+    use prometheus_client::registry::{
+        Registry,
+        Unit,
+    };
+
+    #[test]
+    fn created_counter_encode() {
+        let mut registry = Registry::default();
+        let family = Family::<Vec<(String, String)>, CreatedCounter>::default();
+        registry.register_with_unit(
+            "cputime",
+            "CPU time",
+            Unit::Seconds,
+            family.clone(),
+        );
+
+        let labels = vec![("name".to_string(), "a".to_string())];
+        let counter = family.get_or_create(&labels).clone();
+        counter.inner().store(10, std::sync::atomic::Ordering::Relaxed);
+
+        let mut output = String::new();
+        encode(&mut output, &registry).unwrap();
+        let output = name_created(&output);
+
+        assert!(output.contains(
+            "cputime_seconds_total{name=\"a\"} 10\n\
+             cputime_seconds_created{name=\"a\"} "
+        ));
+
+        let created = output.lines()
+            .find_map(|line| line.strip_prefix("cputime_seconds_created{"))
+            .and_then(|line| line.rsplit_once(' '))
+            .map(|(_, value)| value.parse::<f64>().unwrap());
+
+        assert_eq!(created, Some(counter.created));
+
+        // The creation time is kept for as long as the series is.
+        let created = family.get_or_create(&labels).created;
+        assert_eq!(created, counter.created);
+        assert!(created > 0.0);
+    }
+
+    #[test]
+    fn name_created_only_after_total() {
+        let output = "\
+# HELP jail_starts Jail starts.
+# TYPE jail_starts counter
+jail_starts_total{name=\"a\"} 1
+jail_starts_total{name=\"b\"} 1
+# HELP jail_id Jail ID.
+# TYPE jail_id gauge
+jail_id{name=\"a\"} 1
+# EOF
+";
+
+        assert_eq!(name_created(output), output);
+    }
+}
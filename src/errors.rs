@@ -25,6 +25,18 @@ pub enum ExporterError {
     #[error("HttpdError: {0}")]
     HttpdError(#[from] crate::httpd::HttpdError),
 
+    /// Raised if an external command fails.
+    #[error("Command failed: {0}")]
+    CommandFailed(String),
+
+    /// Raised if the configuration can't be used as given.
+    #[error("Invalid configuration: {0}")]
+    ConfigError(String),
+
+    /// Raised if an rctl(8) rule could not be parsed.
+    #[error("Invalid rctl rule: {0}")]
+    InvalidRctlRule(String),
+
     #[cfg(feature = "auth")]
     /// Raised if a configured username is invalid
     #[error("Invalid username: {0}")]
@@ -34,10 +46,24 @@ pub enum ExporterError {
     #[error("std::io::Error")]
     IoError(#[from] std::io::Error),
 
+    #[cfg(feature = "jail_manager")]
+    /// Raised if there is an issue reading a JSON jail manager configuration
+    #[error("Failed to read JSON configuration")]
+    JsonError(#[from] serde_json::Error),
+
+    /// Raised if jail.conf(5) could not be parsed.
+    #[error("jail.conf error: {0}")]
+    JailConfError(String),
+
+    #[cfg(target_os = "freebsd")]
     /// Raised if there are errors originating within the `jail` crate.
     #[error("could not get jail name")]
     JailError(jail::JailError),
 
+    /// Raised if the output of netstat(1) could not be parsed.
+    #[error("netstat error: {0}")]
+    NetstatError(String),
+
     /// Raised if the `jail_exporter` is not running as root.
     #[error("jail_exporter must be run as root")]
     NotRunningAsRoot,
@@ -51,10 +77,21 @@ pub enum ExporterError {
     #[error("error occurred while persisting metrics")]
     PersistError(#[from] tempfile::PersistError),
 
+    #[cfg(target_os = "freebsd")]
+    /// Raised if the process table could not be read.
+    #[error("Process table error: {0}")]
+    ProcessError(String),
+
     // /// Raised if there are errors originating within the `prometheus` crate.
     //#[error("error within Prometheus library")]
     //PrometheusError(#[from] prometheus::Error),
 
+    #[cfg(target_os = "freebsd")]
+    /// Raised if there are errors originating within the `rctl` crate.
+    #[error("RCTL error: {0}")]
+    RctlError(#[from] rctl::Error),
+
+    #[cfg(target_os = "freebsd")]
     /// Raised if there are issues with RACCT/RCTL support.
     #[error("RACCT/RCTL: {0}")]
     RctlUnavailable(String),
@@ -63,13 +100,17 @@ pub enum ExporterError {
     #[error("Failed to convert UTF-8 to String")]
     Utf8Error(#[from] std::string::FromUtf8Error),
 
-    #[cfg(feature = "auth")]
+    /// Raised if the output of zfs(8) could not be parsed.
+    #[error("ZFS error: {0}")]
+    ZfsError(String),
+
     /// Raised if there is an issue reading the YAML configuration
-    #[error("Failed to read YAML configuration")]
+    #[error("Failed to read YAML configuration: {0}")]
     YamlError(#[from] serde_yaml::Error),
 }
 
 // There is no as_dyn_error for jail::JailError, so we manually implement From
+#[cfg(target_os = "freebsd")]
 impl From<jail::JailError> for ExporterError {
     fn from(e: jail::JailError) -> Self {
         Self::JailError(e)
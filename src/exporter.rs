@@ -6,37 +6,185 @@ use crate::{
     register_gauge_with_registry,
     register_info_with_registry,
 };
+use crate::collectors::{
+    Collectors,
+    RCTL,
+};
+use crate::command::CommandRunner;
+use crate::created;
 use crate::errors::ExporterError;
 use crate::httpd::{
     Collector,
     HttpdError,
 };
-use jail::RunningJail;
+use crate::filesystem::{
+    FilesystemMetrics,
+    StatvfsReader,
+};
+use crate::filter::JailFilter;
+use crate::hierarchy::{
+    self,
+    HierarchyMetrics,
+    JailNames,
+};
+use crate::info::InfoFamily;
+use crate::jailconf::{
+    JailConf,
+    DEFAULT_JAIL_CONF,
+};
+use crate::jailsource::{
+    Jail,
+    JailSource,
+    Rusage,
+    Stage,
+    SystemJailSource,
+};
+use crate::lifecycle::LifecycleMetrics;
+use crate::limits::{
+    self,
+    LimitMetrics,
+};
+use crate::meta::{
+    MetaConfig,
+    MetaMetrics,
+};
+use crate::network::{
+    InterfaceReader,
+    NetworkMetrics,
+};
+use crate::options::ExporterOptions;
+use crate::processes::{
+    Process,
+    ProcessMetrics,
+    ProcessReader,
+    StartTimeMetrics,
+};
+use crate::relabel::{
+    JailLabelSet,
+    Relabeler,
+};
+use crate::rctlstate::RctlState;
+use crate::reaper::{
+    ReapAfter,
+    Reaper,
+};
+use crate::rusage::RusageMetrics;
+use crate::scrape::{
+    CollectorTimes,
+    ScrapeMetrics,
+};
+use crate::zfs::ZfsMetrics;
 use parking_lot::Mutex;
 use prometheus_client::encoding::EncodeLabelSet;
-use prometheus_client::encoding::text::encode;
+use prometheus_client::encoding::text::encode_registry;
 use prometheus_client::metrics::{
     counter::Counter,
     family::Family,
     gauge::Gauge,
 };
-use prometheus_client::registry::{
-    Registry,
-    Unit,
-};
-use rctl::Resource;
+use prometheus_client::registry::Registry;
 use std::collections::{
     HashMap,
     HashSet,
 };
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::sync::atomic::Ordering;
-use tracing::debug;
+use std::time::{
+    Instant,
+    SystemTime,
+    UNIX_EPOCH,
+};
+use tracing::{
+    debug,
+    warn,
+};
+
+#[cfg(feature = "jail_manager")]
+use crate::manager::{
+    JailManagers,
+    ManagerLabels,
+};
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, EncodeLabelSet)]
+struct JailInfoLabels {
+    // Jail name, along with the labels added to the jail.
+    #[prometheus(flatten)]
+    jail: JailLabelSet,
+
+    // Jail ID.
+    jid: String,
+
+    // Hostname of the jail.
+    hostname: String,
+
+    // Root directory of the jail.
+    path: String,
+
+    // Release reported to processes within the jail.
+    osrelease: String,
+
+    // Comma separated IPv4 addresses of the jail.
+    ip4: String,
+
+    // Comma separated IPv6 addresses of the jail.
+    ip6: String,
+
+    // Whether the jail has its own virtual network stack.
+    vnet: String,
+
+    // Whether the jail persists without any processes.
+    persist: String,
+}
+
+impl JailInfoLabels {
+    fn new(jail: &Jail, labels: &JailLabelSet) -> Self {
+        let param = |name: &str| {
+            jail.param(name).unwrap_or_default().to_string()
+        };
+
+        // vnet is a jailsys parameter, persist is a boolean. Render these in
+        // the same way that jls(8) does.
+        let vnet = match param("vnet").as_str() {
+            "0" => "disable".to_string(),
+            "1" => "new".to_string(),
+            "2" => "inherit".to_string(),
+            other => other.to_string(),
+        };
+
+        let persist = match param("persist").as_str() {
+            "0" => "false".to_string(),
+            "1" => "true".to_string(),
+            other => other.to_string(),
+        };
+
+        Self {
+            jail:      labels.clone(),
+            jid:       jail.jid.to_string(),
+            hostname:  param("host.hostname"),
+            path:      param("path"),
+            osrelease: param("osrelease"),
+            ip4:       param("ip4.addr"),
+            ip6:       param("ip6.addr"),
+            vnet:      vnet,
+            persist:   persist,
+        }
+    }
+}
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
-struct NameLabel {
-    // Jail name.
+struct ErrorLabels {
+    // Jail name, or the jail ID if the name couldn't be read. Empty for the
+    // stages that aren't specific to a jail.
     name: String,
+
+    // Stage of collecting the jails that failed.
+    stage: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct StateLabel {
+    // RACCT/RCTL state of the kernel.
+    state: String,
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
@@ -48,303 +196,469 @@ struct VersionLabels {
     version: String,
 }
 
-/// Type alias for our resource usage metrics coming from the rctl library.
-type Rusage = HashMap<Resource, usize>;
+// Hands out the registry that the families of each collector are registered
+// with. Every collector is still built, but the families of disabled
+// collectors, and those of the collectors querying rctl(8) when RACCT/RCTL
+// isn't available, are registered with a registry that's never encoded, so
+// they aren't exported.
+struct Registries<'a> {
+    registry:   &'a mut Registry,
+    disabled:   Registry,
+    collectors: &'a Collectors,
+    rctl:       bool,
+}
+
+impl Registries<'_> {
+    // Returns the registry for the named collector.
+    fn collector(&mut self, name: &str) -> &mut Registry {
+        if self.collectors.is_enabled(name) {
+            self.registry
+        }
+        else {
+            &mut self.disabled
+        }
+    }
+
+    // Returns the registry for the rctl(8) resource usage, whose resources
+    // are each enabled or disabled by RusageMetrics.
+    fn rusage(&mut self) -> &mut Registry {
+        if self.rctl {
+            self.registry
+        }
+        else {
+            &mut self.disabled
+        }
+    }
+
+    // Returns the registry for the named collector querying rctl(8).
+    fn rctl(&mut self, name: &str) -> &mut Registry {
+        if self.rctl {
+            self.collector(name)
+        }
+        else {
+            &mut self.disabled
+        }
+    }
+}
 
 /// Set of String representing jails that we have seen during the current
 /// scrape.
 type SeenJails = HashSet<String>;
 
+/// The resource usage of a jail read from rctl(8), along with its rules if
+/// the limits are exported.
+type RctlUsage = (Rusage, Option<Vec<String>>);
+
 /// Exporter structure containing the time series that are being tracked.
-pub struct Exporter {
+///
+/// The exporter is generic over the `JailSource` it collects from, defaulting
+/// to the running system.
+pub struct Exporter<S = SystemJailSource> {
     // Exporter Registry
     registry: Registry,
 
-    // Prometheus time series
-    // These come from rctl
-    coredumpsize:    Family<NameLabel, Gauge>,
-    cputime:         Family<NameLabel, Counter>,
-    datasize:        Family<NameLabel, Gauge>,
-    memorylocked:    Family<NameLabel, Gauge>,
-    memoryuse:       Family<NameLabel, Gauge>,
-    msgqsize:        Family<NameLabel, Gauge>,
-    maxproc:         Family<NameLabel, Gauge>,
-    msgqqueued:      Family<NameLabel, Gauge>,
-    nmsgq:           Family<NameLabel, Gauge>,
-    nsem:            Family<NameLabel, Gauge>,
-    nsemop:          Family<NameLabel, Gauge>,
-    nshm:            Family<NameLabel, Gauge>,
-    nthr:            Family<NameLabel, Gauge>,
-    openfiles:       Family<NameLabel, Gauge>,
-    pcpu_used:       Family<NameLabel, Gauge>,
-    pseudoterminals: Family<NameLabel, Gauge>,
-    readbps:         Family<NameLabel, Gauge>,
-    readiops:        Family<NameLabel, Gauge>,
-    shmsize:         Family<NameLabel, Gauge>,
-    stacksize:       Family<NameLabel, Gauge>,
-    swapuse:         Family<NameLabel, Gauge>,
-    vmemoryuse:      Family<NameLabel, Gauge>,
-    wallclock:       Family<NameLabel, Counter>,
-    writebps:        Family<NameLabel, Gauge>,
-    writeiops:       Family<NameLabel, Gauge>,
+    // Source of jails and their resource usage.
+    source: S,
+
+    // Collectors enabled, only these are updated, and the families of the
+    // others are registered with a registry that's never encoded.
+    collectors: Collectors,
+
+    // Whether rctl(8) is queried for the resource usage and rules of the
+    // jails, which requires RACCT/RCTL.
+    rctl: bool,
+
+    // Time series of the rctl resource usage of the jails.
+    rusage: RusageMetrics,
 
     // Metrics this library generates
-    jail_id:  Family<NameLabel, Gauge>,
-    jail_num: Gauge,
+    jail_id:   Family<JailLabelSet, Gauge>,
+    jail_info: InfoFamily<JailInfoLabels>,
+    jail_num:  Gauge,
+    jail_up:   Family<JailLabelSet, Gauge>,
+
+    // Failures to read each jail. These survive reaping, as the jails that
+    // fail to be read are often those that have just stopped.
+    collection_errors: Family<ErrorLabels, Counter>,
+
+    // Whether the named jail was running on the last run. Jails that have
+    // disappeared are kept at 0 until they're reaped.
+    jail_present: Family<JailLabelSet, Gauge>,
+
+    // The jail_info label set of each jail, kept so that we can remove the
+    // old label set if the jail parameters change.
+    jail_info_labels: Mutex<HashMap<String, JailInfoLabels>>,
+
+    // rctl rules set on the jails.
+    limits: LimitMetrics,
 
-    // This keeps a record of which jails we saw on the last run. We use this
-    // to reap old jails (remove their label sets).
-    jail_names: Arc<Mutex<HashSet<String>>>,
+    // Parents and children of the jails.
+    hierarchy: HierarchyMetrics,
+
+    // Usage of the filesystems holding the jails.
+    filesystem: FilesystemMetrics,
+
+    // Usage of the ZFS datasets holding the jails.
+    zfs: ZfsMetrics,
+
+    // Network interfaces of the VNET jails.
+    network: NetworkMetrics,
+
+    // States of the processes within the jails.
+    processes: ProcessMetrics,
+
+    // Start times of the jails.
+    start_time: StartTimeMetrics,
+
+    // Reads the process table for the processes and start times of the
+    // jails.
+    process_reader: Option<Box<dyn ProcessReader + Send + Sync>>,
+
+    // Labels taken from the meta and env parameters of the jails.
+    meta: MetaMetrics,
+
+    // Starts, stops and restarts of the jails. These survive reaping.
+    lifecycle: LifecycleMetrics,
+
+    // Durations and results of the scrapes, kept in their own registry.
+    scrape: ScrapeMetrics,
+
+    // jail.conf(5) to read configured jails from, and the label sets of the
+    // jails configured on the last run.
+    jail_conf:        Option<PathBuf>,
+    configured_jails: Mutex<HashMap<String, JailLabelSet>>,
+
+    // Metadata read from the configuration of jail managers.
+    #[cfg(feature = "jail_manager")]
+    jail_manager_info: InfoFamily<ManagerLabels>,
+
+    #[cfg(feature = "jail_manager")]
+    jail_managers: Option<JailManagers>,
+
+    // This keeps a record of which jails we saw on the last run, along with
+    // their label sets. We use this to reap old jails (remove their label
+    // sets).
+    jail_names: Arc<Mutex<HashMap<String, JailLabelSet>>>,
+
+    // Decides when the jails that have disappeared are reaped.
+    reaper: Mutex<Reaper>,
+
+    // Decides which jails are exported.
+    filter: JailFilter,
+
+    // Relabels each jail, giving the labels that are added to the time
+    // series of the jails.
+    relabeler: Relabeler,
 }
 
 impl Default for Exporter {
-    // Descriptions of these metrics are taken from rctl(8) where possible.
-    #![allow(clippy::too_many_lines)]
     fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Exporter implementation
+impl Exporter {
+    /// Return a new Exporter instance.
+    ///
+    /// This will create the initial time series and return a metrics struct.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let exporter = jail_exporter::Exporter::new();
+    /// ```
+    pub fn new() -> Self {
+        Self::with_source(SystemJailSource::default())
+            .jail_conf(DEFAULT_JAIL_CONF)
+    }
+
+    /// Return a new Exporter instance, registering its time series according
+    /// to the given `options`.
+    ///
+    /// This will create the initial time series and return a metrics struct.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let options = jail_exporter::ExporterOptions::new();
+    /// let exporter = jail_exporter::Exporter::with_options(&options);
+    /// ```
+    pub fn with_options(options: &ExporterOptions) -> Self {
+        Self::with_source_and_options(SystemJailSource::default(), options)
+            .jail_conf(DEFAULT_JAIL_CONF)
+    }
+}
+
+impl<S: JailSource> Exporter<S> {
+    /// Return a new Exporter instance collecting from the given `source`.
+    ///
+    /// This will create the initial time series and return a metrics struct.
+    pub fn with_source(source: S) -> Self {
+        Self::with_source_and_options(source, &ExporterOptions::default())
+    }
+
+    /// Return a new Exporter instance collecting from the given `source`,
+    /// registering its time series according to the given `options`.
+    // Descriptions of these metrics are taken from rctl(8) where possible.
+    #[allow(clippy::too_many_lines)]
+    pub fn with_source_and_options(source: S, options: &ExporterOptions)
+    -> Self {
         // We want to set this as a field in the returned struct, as well as
-        // pass it to the macros.
-        let mut registry = Registry::with_prefix("jail");
+        // pass it to the macros. Constant labels set here apply to every
+        // time series, including those of the sub-registries.
+        let mut root = Registry::with_labels(options.registry_labels());
+        let prefix = options.metric_prefix();
+        let naming = options.naming_scheme();
+        let collectors = options.enabled_collectors().clone();
+
+        // Info metrics have "_info" appended to their names, so jail_info is
+        // registered under the bare prefix in the unprefixed root registry.
+        let jail_info = register_info_with_registry!(
+            prefix,
+            "Information about the named jail, taken from its parameters",
+            Family<JailInfoLabels>,
+            root,
+        );
+
+        // Everything else lives in the prefixed registry, other than the
+        // time series of disabled collectors.
+        let mut registries = Registries {
+            registry:   root.sub_registry_with_prefix(prefix),
+            disabled:   Registry::default(),
+            collectors: &collectors,
+            rctl:       options.rctl_enabled(),
+        };
 
         let version_labels = VersionLabels {
             rustversion: env!("RUSTC_VERSION").to_string(),
             version: env!("CARGO_PKG_VERSION").to_string(),
          };
 
+        #[cfg(feature = "jail_manager")]
+        let jail_manager_info = register_info_with_registry!(
+            "manager",
+            "Metadata about the named jail from its jail manager",
+            Family<ManagerLabels>,
+            registries.collector("jail-manager"),
+        );
+
         // Static info metric, doesn't need to be in the struct.
         register_info_with_registry!(
             "exporter_build",
             "A metric with constant '1' value labelled by version \
              from which jail_exporter was built",
             version_labels,
-            registry,
+            registries.registry,
         );
 
-        Self {
-            coredumpsize: register_gauge_with_registry!(
-                "coredumpsize",
-                "core dump size, in bytes",
-                NameLabel,
-                Unit::Bytes,
-                registry,
-            ),
-
-            cputime: register_counter_with_registry!(
-                "cputime",
-                "CPU time, in seconds",
-                NameLabel,
-                Unit::Seconds,
-                registry,
-            ),
+        // Static RACCT/RCTL state, with a time series for each state.
+        let racct_state = register_gauge_with_registry!(
+            "exporter_racct_state",
+            "RACCT/RCTL state of the kernel, 1 for the current state. The \
+             resource usage of jails is only exported while it's enabled",
+            StateLabel,
+            registries.registry,
+        );
 
-            datasize: register_gauge_with_registry!(
-                "datasize",
-                "data size, in bytes",
-                NameLabel,
-                Unit::Bytes,
-                registry,
-            ),
+        for state in RctlState::ALL {
+            let labels = &StateLabel {
+                state: state.as_str().to_string(),
+            };
+            let current = state == options.racct_state();
 
-            maxproc: register_gauge_with_registry!(
-                "maxproc",
-                "number of processes",
-                NameLabel,
-                registry,
-            ),
+            racct_state.get_or_create(labels).set(i64::from(current));
+        }
 
-            memorylocked: register_gauge_with_registry!(
-                "memorylocked",
-                "locked memory, in bytes",
-                NameLabel,
-                Unit::Bytes,
-                registry,
+        Self {
+            rusage: RusageMetrics::new(
+                registries.rusage(),
+                naming,
+                &collectors,
             ),
 
-            memoryuse: register_gauge_with_registry!(
-                "memoryuse",
-                "resident set size, in bytes",
-                NameLabel,
-                Unit::Bytes,
-                registry,
+            // Metrics created by the exporter
+            jail_id: register_gauge_with_registry!(
+                "id",
+                "ID of the named jail",
+                JailLabelSet,
+                registries.registry,
             ),
 
-            msgqqueued: register_gauge_with_registry!(
-                "msgqqueued",
-                "number of queued SysV messages",
-                NameLabel,
-                registry,
+            jail_num: register_gauge_with_registry!(
+                "num",
+                "Current number of running jails",
+                registries.registry,
             ),
 
-            msgqsize: register_gauge_with_registry!(
-                "msgqsize",
-                "SysV message queue size, in bytes",
-                NameLabel,
-                Unit::Bytes,
-                registry,
+            jail_up: register_gauge_with_registry!(
+                "up",
+                "Whether the named jail from jail.conf(5) is running",
+                JailLabelSet,
+                registries.registry,
             ),
 
-            nmsgq: register_gauge_with_registry!(
-                "nmsgq",
-                "number of SysV message queues",
-                NameLabel,
-                registry,
+            jail_present: register_gauge_with_registry!(
+                "present",
+                "Whether the named jail was running on the last scrape, 0 \
+                 while the series of a vanished jail are kept",
+                JailLabelSet,
+                registries.registry,
             ),
 
-            nsem: register_gauge_with_registry!(
-                "nsem",
-                "number of SysV semaphores",
-                NameLabel,
-                registry,
-            ),
+            jail_info: jail_info,
 
-            nsemop: register_gauge_with_registry!(
-                "nsemop",
-                "number of SysV semaphores modified in a single semop(2) call",
-                NameLabel,
-                registry,
+            collection_errors: register_counter_with_registry!(
+                "exporter_collection_errors",
+                "Number of times that reading the named jail failed, by the \
+                 stage of collection that failed",
+                ErrorLabels,
+                registries.registry,
             ),
 
-            nshm: register_gauge_with_registry!(
-                "nshm",
-                "number of SysV shared memory segments",
-                NameLabel,
-                registry,
-            ),
+            limits: LimitMetrics::new(registries.rctl("limits")),
 
-            nthr: register_gauge_with_registry!(
-                "nthr",
-                "number of threads",
-                NameLabel,
-                registry,
-            ),
+            hierarchy: HierarchyMetrics::new(registries.collector("hierarchy")),
 
-            openfiles: register_gauge_with_registry!(
-                "openfiles",
-                "file descriptor table size",
-                NameLabel,
-                registry,
+            filesystem: FilesystemMetrics::new(
+                registries.collector("filesystem"),
+                StatvfsReader,
             ),
 
-            pcpu_used: register_gauge_with_registry!(
-                "pcpu_used",
-                "%CPU, in percents of a single CPU core",
-                NameLabel,
-                registry,
-            ),
+            zfs: ZfsMetrics::new(registries.collector("zfs")),
 
-            pseudoterminals: register_gauge_with_registry!(
-                "pseudoterminals",
-                "number of PTYs",
-                NameLabel,
-                registry,
-            ),
+            network: NetworkMetrics::new(registries.collector("network")),
 
-            readbps: register_gauge_with_registry!(
-                "readbps",
-                "filesystem reads, in bytes per second",
-                NameLabel,
-                registry,
-            ),
+            processes: ProcessMetrics::new(registries.collector("processes")),
 
-            readiops: register_gauge_with_registry!(
-                "readiops",
-                "filesystem reads, in operations per second",
-                NameLabel,
-                registry,
+            start_time: StartTimeMetrics::new(
+                registries.collector("start-time"),
             ),
 
-            shmsize: register_gauge_with_registry!(
-                "shmsize",
-                "SysV shared memory size, in bytes",
-                NameLabel,
-                Unit::Bytes,
-                registry,
-            ),
+            process_reader: None,
 
-            stacksize: register_gauge_with_registry!(
-                "stacksize",
-                "stack size, in bytes",
-                NameLabel,
-                Unit::Bytes,
-                registry,
-            ),
+            meta: MetaMetrics::new(registries.registry),
 
-            swapuse: register_gauge_with_registry!(
-                "swapuse",
-                "swap space that may be reserved or used, in bytes",
-                NameLabel,
-                Unit::Bytes,
-                registry,
-            ),
+            lifecycle: LifecycleMetrics::new(registries.collector("lifecycle")),
 
-            vmemoryuse: register_gauge_with_registry!(
-                "vmemoryuse",
-                "address space limit, in bytes",
-                NameLabel,
-                Unit::Bytes,
-                registry,
-            ),
 
-            wallclock: register_counter_with_registry!(
-                "wallclock",
-                "wallclock time, in seconds",
-                NameLabel,
-                Unit::Seconds,
-                registry,
-            ),
+            scrape: ScrapeMetrics::new(prefix, options.registry_labels()),
 
-            writebps: register_gauge_with_registry!(
-                "writebps",
-                "filesystem writes, in bytes per second",
-                NameLabel,
-                registry,
-            ),
+            // Registry must be added after the macros making use of it
+            registry:   root,
+            source:     source,
+            collectors: collectors,
+            rctl:       options.rctl_enabled(),
 
-            writeiops: register_gauge_with_registry!(
-                "writeiops",
-                "filesystem writes, in operations per second",
-                NameLabel,
-                registry,
-            ),
+            jail_info_labels: Mutex::new(HashMap::new()),
 
-            // Metrics created by the exporter
-            jail_id: register_gauge_with_registry!(
-                "id",
-                "ID of the named jail",
-                NameLabel,
-                registry,
-            ),
+            jail_conf:        None,
+            configured_jails: Mutex::new(HashMap::new()),
 
-            jail_num: register_gauge_with_registry!(
-                "num",
-                "Current number of running jails",
-                registry,
-            ),
+            #[cfg(feature = "jail_manager")]
+            jail_manager_info: jail_manager_info,
 
-            // Registry must be added after the macros making use of it
-            registry: registry,
+            #[cfg(feature = "jail_manager")]
+            jail_managers: None,
 
             // Jail name tracking
-            // We keep a set of jails that we saw on the run, so that on the
+            // We keep a map of jails that we saw on the run, so that on the
             // next run, we can tell which jails have disappeared (if any) and
             // delete those metric families.
-            jail_names: Arc::new(Mutex::new(HashSet::new())),
+            jail_names: Arc::new(Mutex::new(HashMap::new())),
+
+            reaper: Mutex::new(Reaper::default()),
+
+            filter: JailFilter::default(),
+
+            relabeler: Relabeler::default(),
         }
     }
-}
 
-/// Exporter implementation
-impl Exporter {
-    /// Return a new Exporter instance.
-    ///
-    /// This will create the initial time series and return a metrics struct.
-    ///
-    /// # Example
-    ///
-    /// ```
-    /// let exporter = jail_exporter::Exporter::new();
-    /// ```
-    pub fn new() -> Self {
-        Self::default()
+    /// Read configured jails from the jail.conf(5) at `path`, exporting
+    /// whether each of them is running.
+    #[must_use]
+    pub fn jail_conf<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.jail_conf = Some(path.into());
+        self
+    }
+
+    /// Export the usage of the ZFS datasets holding the jails, listing the
+    /// datasets with zfs(8) via the given `runner`.
+    #[must_use]
+    pub fn zfs<R>(mut self, runner: R) -> Self
+    where
+        R: CommandRunner + Send + Sync + 'static,
+    {
+        self.zfs.set_runner(runner);
+        self
+    }
+
+    /// Export the network interface counters of VNET jails, reading them via
+    /// the given `reader`.
+    #[must_use]
+    pub fn network<R>(mut self, reader: R) -> Self
+    where
+        R: InterfaceReader + Send + Sync + 'static,
+    {
+        self.network.set_reader(reader);
+        self
+    }
+
+    /// Read the process table via the given `reader`, for the number of
+    /// processes in each state within the jails and the start times of the
+    /// jails.
+    #[cfg(any(target_os = "freebsd", test))]
+    #[must_use]
+    pub fn processes<R>(mut self, reader: R) -> Self
+    where
+        R: ProcessReader + Send + Sync + 'static,
+    {
+        self.process_reader = Some(Box::new(reader));
+        self
+    }
+
+    /// Read jail metadata from the on-disk configuration of the given jail
+    /// managers.
+    #[cfg(feature = "jail_manager")]
+    #[must_use]
+    pub fn jail_managers(mut self, managers: JailManagers) -> Self {
+        self.jail_managers = Some(managers);
+        self
+    }
+
+    /// Keep the time series of jails that have disappeared until `reap_after`
+    /// has passed, rather than removing them on the first scrape that the
+    /// jail is missing from.
+    #[must_use]
+    pub fn reap_after(self, reap_after: ReapAfter) -> Self {
+        debug!("Setting reap after to: {reap_after}");
+
+        *self.reaper.lock() = Reaper::new(reap_after);
+        self
+    }
+
+    /// Only export the jails matching the given `filter`. Jails that stop
+    /// matching are reaped as if they had disappeared.
+    #[must_use]
+    pub fn jail_filter(mut self, filter: JailFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Apply the given relabeling rules to the labels of each jail.
+    #[must_use]
+    pub fn relabel(mut self, relabeler: Relabeler) -> Self {
+        self.relabeler = relabeler;
+        self
+    }
+
+    /// Export labels taken from the meta and env parameters of the jails,
+    /// as configured by `config`.
+    #[must_use]
+    pub fn meta_labels(mut self, config: MetaConfig) -> Self {
+        self.meta.set_config(config);
+        self
     }
 
     /// Collect and export the rctl metrics.
@@ -359,131 +673,80 @@ impl Exporter {
     /// let output = exporter.export();
     /// ```
     pub fn export(&self) -> Result<String, ExporterError> {
-        // Collect metrics
-        self.get_jail_metrics()?;
+        // Collect metrics, timing the scrape and each collector.
+        let start = Instant::now();
+        let mut times = CollectorTimes::default();
+        let res = self.get_jail_metrics(&mut times);
+
+        self.scrape.observe(start, &times, res.is_ok());
+        res?;
 
         // Collect them in a buffer
         let mut buffer = String::new(); //vec![];
-        encode(&mut buffer, &self.registry).expect("encode");
+        encode_registry(&mut buffer, &self.registry).expect("encode");
+
+        // Name the _created samples, that prometheus_client can't encode.
+        let mut buffer = created::name_created(&buffer);
+
+        // The scrape time series come last, ending the output.
+        buffer.push_str(&self.scrape.encode());
 
         // Return the exported metrics
         Ok(buffer)
     }
 
     /// Processes the Rusage setting the appripriate time series.
-    fn process_rusage(&self, name: &str, metrics: &Rusage) {
+    fn process_rusage(&self, labels: &JailLabelSet, metrics: &Rusage) {
         debug!("process_metrics_hash");
 
-        // Add the jail name to seen jails.
-        self.add_seen_jail(name);
-
-        // Convenience variable
-        let labels = &NameLabel {
-            name: name.to_string(),
-        };
+        self.rusage.update(labels, metrics);
+    }
 
-        for (key, value) in metrics {
-            // The original value comes in as a usize. Convert it to a u64 for
-            // our Counter values, and clamp it down to an i64 for our Gauge
-            // values.
-            let unsigned = *value as u64;
+    // Reads the resource usage of the jail from rctl(8), along with its rules
+    // if the limits are exported. Without RACCT/RCTL, neither is read.
+    fn read_rctl(&self, jail: &Jail)
+    -> Result<RctlUsage, (Stage, ExporterError)> {
+        if !self.rctl {
+            return Ok((Rusage::new(), None));
+        }
 
-            // Since we come from a usize, we can't get any negative values,
-            // so clamping to a 0 min is fine.
-            let signed = i64::try_from(unsigned.clamp(0, i64::MAX as u64))
-                .expect("i64 from u64");
+        let rusage = self.source.rusage(jail)
+            .map_err(|e| (Stage::Rusage, e))?;
 
-            match key {
-                Resource::CoreDumpSize => {
-                    self.coredumpsize.get_or_create(labels).set(signed);
-                },
-                Resource::CpuTime => {
-                    // CPU time should only ever increase. Store the value from
-                    // the OS directly.
-                    self.cputime
-                        .get_or_create(labels)
-                        .inner()
-                        .store(unsigned, Ordering::Relaxed);
-                },
-                Resource::DataSize => {
-                    self.datasize.get_or_create(labels).set(signed);
-                },
-                Resource::MaxProcesses => {
-                    self.maxproc.get_or_create(labels).set(signed);
-                },
-                Resource::MemoryLocked => {
-                    self.memorylocked.get_or_create(labels).set(signed);
-                },
-                Resource::MemoryUse => {
-                    self.memoryuse.get_or_create(labels).set(signed);
-                },
-                Resource::MsgqQueued => {
-                    self.msgqqueued.get_or_create(labels).set(signed);
-                },
-                Resource::MsgqSize => {
-                    self.msgqsize.get_or_create(labels).set(signed);
-                },
-                Resource::NMsgq => {
-                    self.nmsgq.get_or_create(labels).set(signed);
-                },
-                Resource::Nsem => {
-                    self.nsem.get_or_create(labels).set(signed);
-                },
-                Resource::NSemop => {
-                    self.nsemop.get_or_create(labels).set(signed);
-                },
-                Resource::NShm => {
-                    self.nshm.get_or_create(labels).set(signed);
-                },
-                Resource::NThreads => {
-                    self.nthr.get_or_create(labels).set(signed);
-                },
-                Resource::OpenFiles => {
-                    self.openfiles.get_or_create(labels).set(signed);
-                },
-                Resource::PercentCpu => {
-                    self.pcpu_used.get_or_create(labels).set(signed);
-                },
-                Resource::PseudoTerminals => {
-                    self.pseudoterminals.get_or_create(labels).set(signed);
-                },
-                Resource::ReadBps => {
-                    self.readbps.get_or_create(labels).set(signed);
-                },
-                Resource::ReadIops => {
-                    self.readiops.get_or_create(labels).set(signed);
-                },
-                Resource::ShmSize => {
-                    self.shmsize.get_or_create(labels).set(signed);
-                },
-                Resource::StackSize => {
-                    self.stacksize.get_or_create(labels).set(signed);
-                },
-                Resource::SwapUse => {
-                    self.swapuse.get_or_create(labels).set(signed);
-                },
-                Resource::VMemoryUse => {
-                    self.vmemoryuse.get_or_create(labels).set(signed);
-                },
-                Resource::Wallclock => {
-                    // Wallclock should only ever increase, store the value
-                    // from the OS directly.
-                    self.wallclock
-                        .get_or_create(labels)
-                        .inner()
-                        .store(unsigned, Ordering::Relaxed);
-                },
-                Resource::WriteBps => {
-                    self.writebps.get_or_create(labels).set(signed);
-                },
-                Resource::WriteIops => {
-                    self.writeiops.get_or_create(labels).set(signed);
-                },
-            }
+        if !self.collectors.is_enabled("limits") {
+            return Ok((rusage, None));
         }
+
+        let rules = self.source.rules(jail)
+            .map_err(|e| (Stage::Rules, e))?;
+
+        Ok((rusage, Some(rules)))
+    }
+
+    // Reads the process table, if there's a reader for it. A process table
+    // that can't be read is logged, and the scrape continues without it.
+    fn read_processes(&self) -> Option<Vec<Process>> {
+        let reader = self.process_reader.as_ref()?;
+
+        reader.processes()
+            .inspect_err(|e| warn!("Couldn't read the process table: {e}"))
+            .ok()
+    }
+
+    // Logs and counts a failure to read the named jail at the given `stage`.
+    fn collection_error(&self, name: &str, stage: Stage, e: &ExporterError) {
+        warn!("Failed to read jail {name} ({stage}): {e}");
+
+        let labels = &ErrorLabels {
+            name:  name.to_string(),
+            stage: stage.to_string(),
+        };
+
+        self.collection_errors.get_or_create(labels).inc();
     }
 
-    fn get_jail_metrics(&self) -> Result<(), ExporterError> {
+    fn get_jail_metrics(&self, times: &mut CollectorTimes)
+    -> Result<(), ExporterError> {
         debug!("get_jail_metrics");
 
         // Set jail_total to zero before gathering.
@@ -492,101 +755,376 @@ impl Exporter {
         // Get a new vec of seen jails.
         let mut seen = SeenJails::new();
 
+        // Jails that couldn't be read are skipped, the others are still
+        // exported.
+        let mut all_jails = Vec::new();
+
+        for jail in self.source.jails()? {
+            match jail {
+                Ok(jail) => all_jails.push(jail),
+                Err(failure) => {
+                    self.collection_error(
+                        &failure.name,
+                        Stage::Jail,
+                        &failure.error,
+                    );
+                },
+            }
+        }
+
+        // The jails to export, under their relabeled names and along with
+        // their label sets. The source is still queried with the original
+        // jails.
+        let (originals, jails): (Vec<&Jail>, Vec<(Jail, JailLabelSet)>) = self
+            .relabel_jails(&all_jails)
+            .into_iter()
+            .unzip();
+
+        // Map of jail IDs to names, for finding the parent of child jails.
+        // This includes filtered jails, as they can still be parents.
+        let jail_names: JailNames = all_jails.iter()
+            .chain(jails.iter().map(|(jail, _)| jail))
+            .map(|jail| (jail.jid, jail.name.as_str()))
+            .collect();
+
         // Loop over jails.
-        for jail in RunningJail::all() {
-            let name = jail.name()?;
-            let rusage = jail.racct_statistics()?;
+        for (original, (jail, labels)) in originals.into_iter().zip(&jails) {
+            let name = &jail.name;
 
             debug!("JID: {}, Name: {:?}", jail.jid, name);
 
-            // Add to our vec of seen jails.
+            // Add to our vec of seen jails, and to those that we'll reap
+            // once they're gone.
             seen.insert(name.clone());
+            self.add_seen_jail(labels);
 
-            // Process rusage for the named jail, setting time series.
-            self.process_rusage(&name, &rusage);
+            // The counters kept by the OS reset when a jail restarts under
+            // a new jail ID, so their series are created again.
+            let restarted = self.jail_id.get(labels)
+                .is_some_and(|jid| jid.get() != i64::from(jail.jid));
 
-            let labels = &NameLabel {
-                name: name,
-            };
+            if restarted {
+                self.rusage.remove(labels);
+                self.network.remove(labels);
+            }
+
+            // Jails may exit while we're reading them. If the usage or rules
+            // can't be read, the jail keeps its previous rusage and limits
+            // for this run, and the rest of it is still exported.
+            match times.time(RCTL, || self.read_rctl(original)) {
+                Ok((rusage, rules)) => {
+                    // Process rusage for the named jail, setting time series.
+                    times.time(RCTL, || self.process_rusage(labels, &rusage));
+
+                    // Process the rctl rules set on the jail, along with the
+                    // resource utilisation against those rules.
+                    if let Some(rules) = rules {
+                        times.time("limits", || {
+                            let limits = limits::parse_rules(&rules);
+                            self.limits.update(labels, &limits, &rusage);
+                        });
+                    }
+                },
+                Err((stage, e)) => self.collection_error(name, stage, &e),
+            }
+
+            self.process_info(jail, labels);
+            self.meta.update(jail, labels);
+
+            if self.collectors.is_enabled("hierarchy") {
+                times.time("hierarchy", || {
+                    let parent = hierarchy::parent_name(jail, &jail_names);
+                    self.hierarchy.update(jail, labels, parent);
+                });
+            }
+
+            if self.collectors.is_enabled("filesystem") {
+                times.time("filesystem", || {
+                    self.filesystem.update(jail, labels);
+                });
+            }
+
+            if self.collectors.is_enabled("network") {
+                times.time("network", || self.network.update(jail, labels));
+            }
 
             self.jail_id.get_or_create(labels).set(i64::from(jail.jid));
+            self.jail_present.get_or_create(labels).set(1);
             self.jail_num.set(self.jail_num.get() + 1);
         }
 
-        // Get a list of dead jails based on what we've seen, and reap them.
+        // Get a list of missing jails based on what we've seen, and reap
+        // those that have been missing for long enough.
         // Performed in two steps due to Mutex locking issues.
-        let dead = self.dead_jails(&seen);
+        let missing = self.dead_jails(&seen);
+        let dead = self.reaper.lock().update(&missing, Instant::now());
+
+        // The remaining missing jails keep their last known values.
+        for name in &missing - &dead {
+            if let Some(labels) = self.jail_names.lock().get(&name) {
+                self.jail_present.get_or_create(labels).set(0);
+            }
+        }
+
         self.reap(dead);
 
+        if self.collectors.is_enabled("lifecycle") {
+            times.time("lifecycle", || self.lifecycle.update(&jails));
+        }
+
+        // The process table is read once, by the first enabled collector
+        // that uses it.
+        let processes = ["processes", "start-time"].into_iter()
+            .find(|name| self.collectors.is_enabled(name))
+            .and_then(|name| times.time(name, || self.read_processes()));
+
+        if self.collectors.is_enabled("processes") {
+            times.time("processes", || {
+                self.processes.update(&jails, processes.as_deref());
+            });
+        }
+
+        if self.collectors.is_enabled("start-time") {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| now.as_secs())
+                .try_into()
+                .unwrap_or(i64::MAX);
+
+            times.time("start-time", || {
+                self.start_time.update(&jails, processes.as_deref(), now);
+            });
+        }
+
+        if self.collectors.is_enabled("zfs") {
+            times.time("zfs", || self.zfs.update(&jails));
+        }
+
+        self.process_jail_conf(&jails);
+
+        #[cfg(feature = "jail_manager")]
+        if self.collectors.is_enabled("jail-manager") {
+            times.time("jail-manager", || self.process_jail_managers());
+        }
+
         Ok(())
     }
 
-    fn add_seen_jail(&self, seen: &str) {
-        let mut names = self.jail_names.lock();
-        names.insert(seen.to_string());
+    // Returns the jails to export, filtered and renamed by the relabeling
+    // rules, along with the original jails. Each renamed jail comes with its
+    // label set, holding the labels that relabeling adds to the jail.
+    fn relabel_jails<'a>(&self, jails: &'a [Jail])
+    -> Vec<(&'a Jail, (Jail, JailLabelSet))> {
+        let mut relabeled: Vec<(&Jail, (Jail, JailLabelSet))> = Vec::new();
+
+        for jail in jails.iter().filter(|jail| self.filter.matches(jail)) {
+            let Some(labels) = self.relabeler.relabel(jail) else {
+                continue;
+            };
+
+            let taken = relabeled.iter()
+                .any(|(_, (other, _))| other.name == labels.name);
+
+            if taken {
+                warn!(
+                    "Not exporting jail {}, relabeled to the name of another \
+                     jail: {}",
+                    jail.name,
+                    labels.name,
+                );
+
+                continue;
+            }
+
+            // Labels from relabeling take precedence over the meta labels.
+            let mut extra = labels.labels;
+
+            if self.meta.all_series() {
+                for (name, value) in self.meta.labels(jail) {
+                    extra.entry(name).or_insert(value);
+                }
+            }
+
+            let label_set = JailLabelSet::new(&labels.name, extra);
+
+            let renamed = Jail {
+                name: labels.name,
+                ..jail.clone()
+            };
+
+            relabeled.push((jail, (renamed, label_set)));
+        }
+
+        relabeled
+    }
+
+    // Sets jail_up for the jails configured in jail.conf(5). These are kept
+    // separately from the other metrics, as they must survive the reaping of
+    // a jail that has stopped.
+    // A jail.conf(5) that can't be read leaves jail_up as it was, rather than
+    // failing the scrape.
+    fn process_jail_conf(&self, running: &[(Jail, JailLabelSet)]) {
+        let Some(path) = &self.jail_conf else {
+            return;
+        };
+
+        let conf = match JailConf::load(path) {
+            Ok(conf) => conf,
+            Err(e) => {
+                warn!(
+                    "Failed to read {}, keeping the previous jail_up: {e}",
+                    path.display(),
+                );
+
+                let labels = &ErrorLabels {
+                    name:  String::new(),
+                    stage: Stage::JailConf.to_string(),
+                };
+                self.collection_errors.get_or_create(labels).inc();
+
+                return;
+            },
+        };
+        let running: HashMap<&str, &JailLabelSet> = running.iter()
+            .map(|(jail, labels)| (jail.name.as_str(), labels))
+            .collect();
+
+        let configured: HashMap<String, JailLabelSet> = conf.jails()
+            .filter(|(name, params)| self.filter.matches_conf(name, params))
+            .filter_map(|(name, params)| {
+                self.relabeler.relabel_conf(name, params)
+            })
+            .map(|labels| {
+                // Running jails take their labels from their parameters.
+                let label_set = match running.get(labels.name.as_str()) {
+                    Some(label_set) => (*label_set).clone(),
+                    None => JailLabelSet::new(&labels.name, labels.labels),
+                };
+
+                (labels.name, label_set)
+            })
+            .collect();
+
+        let mut previous = self.configured_jails.lock();
+
+        // Remove jails that are no longer configured, or whose labels have
+        // changed.
+        for (name, old) in &*previous {
+            if configured.get(name) != Some(old) {
+                self.jail_up.remove(old);
+            }
+        }
+
+        for (name, labels) in &configured {
+            let up = i64::from(running.contains_key(name.as_str()));
+            self.jail_up.get_or_create(labels).set(up);
+        }
+
+        *previous = configured;
     }
 
-    fn remove_dead_jails(&self, dead: &SeenJails) {
+    // Sets jail_manager_info for the jails found in the jail manager
+    // configuration. As with jail_up, these include jails that aren't running.
+    #[cfg(feature = "jail_manager")]
+    fn process_jail_managers(&self) {
+        let Some(managers) = &self.jail_managers else {
+            return;
+        };
+
+        self.jail_manager_info.replace(managers.read());
+    }
+
+    // Sets the jail_info for the jail, removing the old label set if the jail
+    // parameters have changed since the last run.
+    fn process_info(&self, jail: &Jail, labels: &JailLabelSet) {
+        let labels = JailInfoLabels::new(jail, labels);
+        let mut info_labels = self.jail_info_labels.lock();
+
+        if let Some(old) = info_labels.get(&jail.name) {
+            if *old != labels {
+                self.jail_info.remove(old);
+            }
+        }
+
+        self.jail_info.insert(labels.clone());
+        info_labels.insert(jail.name.clone(), labels);
+    }
+
+    // Records the label set of a jail that we've seen. If the labels of the
+    // jail have changed, its time series under the old label set are
+    // removed.
+    fn add_seen_jail(&self, labels: &JailLabelSet) {
+        let old = self.jail_names.lock()
+            .insert(labels.name().to_string(), labels.clone());
+
+        if let Some(old) = old.filter(|old| old != labels) {
+            self.remove_jail_series(&old);
+        }
+    }
+
+    // Forgets the dead jails, returning their label sets.
+    fn remove_dead_jails(&self, dead: &SeenJails) -> Vec<JailLabelSet> {
         let mut names = self.jail_names.lock();
-        *names = &*names - dead;
+
+        dead.iter()
+            .filter_map(|name| names.remove(name))
+            .collect()
     }
 
     // Loop over jail names from the previous run, as determined by book
     // keeping, and create a vector of jail names that no longer exist.
     fn dead_jails(&self, seen: &SeenJails) -> HashSet<String> {
         let names = self.jail_names.lock();
-        &*names - seen
+
+        names.keys()
+            .filter(|name| !seen.contains(*name))
+            .cloned()
+            .collect()
     }
 
     // Loop over dead jails removing old labels and killing old book keeping.
     fn reap(&self, dead: SeenJails) {
-        self.remove_dead_jails(&dead);
-
-        for name in dead {
-            self.remove_jail_metrics(&name);
+        for labels in self.remove_dead_jails(&dead) {
+            self.remove_jail_metrics(&labels);
         }
     }
 
-    fn remove_jail_metrics(&self, name: &str) {
-        // Convenience variable
-        let labels = &NameLabel {
-            name: name.to_string(),
-        };
-
+    // Removes the time series of a jail under the given label set, other
+    // than its lifecycle counters, which follow the jail to a new label set.
+    fn remove_jail_series(&self, labels: &JailLabelSet) {
         // Remove the jail metrics
-        self.coredumpsize.remove(labels);
-        self.cputime.remove(labels);
-        self.datasize.remove(labels);
-        self.maxproc.remove(labels);
-        self.memorylocked.remove(labels);
-        self.memoryuse.remove(labels);
-        self.msgqqueued.remove(labels);
-        self.msgqsize.remove(labels);
-        self.nmsgq.remove(labels);
-        self.nsem.remove(labels);
-        self.nsemop.remove(labels);
-        self.nshm.remove(labels);
-        self.nthr.remove(labels);
-        self.openfiles.remove(labels);
-        self.pcpu_used.remove(labels);
-        self.pseudoterminals.remove(labels);
-        self.readbps.remove(labels);
-        self.readiops.remove(labels);
-        self.shmsize.remove(labels);
-        self.stacksize.remove(labels);
-        self.swapuse.remove(labels);
-        self.vmemoryuse.remove(labels);
-        self.wallclock.remove(labels);
-        self.writebps.remove(labels);
-        self.writeiops.remove(labels);
+        self.rusage.remove(labels);
 
         //// Reset metrics we generated.
         self.jail_id.remove(labels);
+        self.jail_present.remove(labels);
+        self.limits.remove(labels);
+        self.hierarchy.remove(labels);
+        self.filesystem.remove(labels);
+        self.zfs.remove(labels);
+        self.network.remove(labels);
+        self.processes.remove(labels);
+        self.start_time.remove(labels);
+
+        let info = self.jail_info_labels.lock().remove(labels.name());
+
+        if let Some(info) = info {
+            self.jail_info.remove(&info);
+        }
+    }
+
+    fn remove_jail_metrics(&self, labels: &JailLabelSet) {
+        self.remove_jail_series(labels);
+        self.meta.remove(labels);
+
+        // The lifecycle time series are kept until the stop of the jail has
+        // been exported.
+        self.lifecycle.remove(labels);
     }
 }
 
 /// Implements the Collector trait used by the Httpd component.
-impl Collector for Exporter {
+impl<S: JailSource> Collector for Exporter<S> {
     fn collect(&self) -> Result<String, HttpdError> {
         self.export()
             .map_err(|e| HttpdError::CollectorError(e.to_string()))
@@ -598,50 +1136,76 @@ impl Collector for Exporter {
 mod tests {
     // We need some of the main functions.
     use super::*;
+    use crate::jailsource::FixtureJailSource;
+    use crate::collectors::Collectors;
+    use crate::config::Config;
+    use crate::network::{
+        InterfaceStats,
+        NetstatReader,
+    };
+    use crate::options::NamingScheme;
+    use crate::processes::{
+        Process,
+        ProcessReader,
+        ProcessState,
+    };
+    use crate::rctltypes::Resource;
     use pretty_assertions::assert_eq;
+    use prometheus_client::encoding::text::encode;
+    use std::path::Path;
 
-    #[test]
-    fn cputime_counter_increase() {
-        let names = ["test", "test2"];
-        let exporter = Exporter::new();
-
-        for name in names {
-            let mut hash = Rusage::new();
-
-            let labels = &NameLabel {
-                name: name.to_string(),
-            };
-
-            // Initial check, should be zero. We didn't set anything yet.
-            assert_eq!(exporter.cputime.get_or_create(labels).get(), 0);
+    // Returns an exporter collecting from a fixture, along with a handle on
+    // that fixture for altering the jails between scrapes.
+    fn fixture_exporter() -> (Exporter<FixtureJailSource>, FixtureJailSource) {
+        let source = FixtureJailSource::new();
+        let exporter = Exporter::with_source(source.clone());
 
-            // First run, adds 1000, total 1000.
-            hash.insert(Resource::CpuTime, 1000);
-            exporter.process_rusage(name, &hash);
-            assert_eq!(exporter.cputime.get_or_create(labels).get(), 1000);
+        (exporter, source)
+    }
 
-            // Second, adds 20, total 1020
-            hash.insert(Resource::CpuTime, 1020);
-            exporter.process_rusage(name, &hash);
-            assert_eq!(exporter.cputime.get_or_create(labels).get(), 1020);
+    // Returns a fixture exporter with the named collector enabled.
+    fn fixture_exporter_with(collector: &str)
+    -> (Exporter<FixtureJailSource>, FixtureJailSource) {
+        let mut collectors = Collectors::default();
+        collectors.set(collector, true).unwrap();
+
+        let options = ExporterOptions::new().collectors(collectors);
+        let source = FixtureJailSource::new();
+        let exporter = Exporter::with_source_and_options(
+            source.clone(),
+            &options,
+        );
 
-            // Third, counter was reset. Adds 10, total 1030.
-            hash.insert(Resource::CpuTime, 10);
-            exporter.process_rusage(name, &hash);
-            assert_eq!(exporter.cputime.get_or_create(labels).get(), 10);
+        (exporter, source)
+    }
 
-            // Fourth, adds 40, total 1070.
-            hash.insert(Resource::CpuTime, 50);
-            exporter.process_rusage(name, &hash);
-            assert_eq!(exporter.cputime.get_or_create(labels).get(), 50);
+    // Returns a synthetic process table.
+    struct FixtureProcesses(Vec<Process>);
 
-            // Fifth, add 0, total 1070
-            hash.insert(Resource::CpuTime, 50);
-            exporter.process_rusage(name, &hash);
-            assert_eq!(exporter.cputime.get_or_create(labels).get(), 50);
+    impl ProcessReader for FixtureProcesses {
+        fn processes(&self) -> Result<Vec<Process>, ExporterError> {
+            Ok(self.0.clone())
         }
     }
 
+    // Returns the time series for the named jail from the exporter output,
+    // other than the lifecycle counters which are kept until the jail's stop
+    // has been exported.
+    fn jail_series<'a>(output: &'a str, name: &str) -> Vec<&'a str> {
+        let lifecycle = [
+            "jail_restarts_total{",
+            "jail_starts_total{",
+            "jail_stops_total{",
+        ];
+        let label = format!("name=\"{name}\"");
+
+        output.lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter(|line| line.contains(&label))
+            .filter(|line| !lifecycle.iter().any(|m| line.starts_with(m)))
+            .collect()
+    }
+
     #[test]
     fn dead_jails_ok() {
         let names = ["test_a", "test_b", "test_c"];
@@ -651,7 +1215,9 @@ mod tests {
         for name in &names {
             let mut hash = Rusage::new();
             hash.insert(Resource::CpuTime, 1000);
-            exporter.process_rusage(name, &hash);
+            let labels = &JailLabelSet::from(*name);
+            exporter.process_rusage(labels, &hash);
+            exporter.add_seen_jail(labels);
         }
 
         // Now, create a seen array containing only a and c.
@@ -669,6 +1235,1079 @@ mod tests {
         assert_eq!(ok, dead);
     }
 
+    #[test]
+    fn export_counts_jails() {
+        let (exporter, source) = fixture_exporter();
+
+        source.add_jail(Jail::new(1, "test_a"), Rusage::new());
+        source.add_jail(Jail::new(2, "test_b"), Rusage::new());
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_num 2\n"));
+        assert!(output.contains("jail_id{name=\"test_a\"} 1\n"));
+        assert!(output.contains("jail_id{name=\"test_b\"} 2\n"));
+
+        source.remove_jail("test_a");
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_num 1\n"));
+    }
+
+    #[test]
+    fn export_jail_filter() {
+        let filter = JailFilter::new()
+            .include("path=^/jails/".parse().unwrap())
+            .exclude("^test$".parse().unwrap());
+
+        let (exporter, source) = fixture_exporter();
+        let exporter = exporter.jail_filter(filter);
+
+        let jails = [
+            Jail::with_params(1, "test", &[("path", "/jails/test")]),
+            Jail::with_params(2, "www", &[("path", "/jails/www")]),
+            Jail::with_params(3, "ci", &[("path", "/ci/1234")]),
+        ];
+
+        for jail in jails {
+            source.add_jail(jail, Rusage::new());
+        }
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_num 1\n"));
+        assert!(output.contains("jail_id{name=\"www\"} 2\n"));
+        assert!(jail_series(&output, "test").is_empty());
+        assert!(jail_series(&output, "ci").is_empty());
+    }
+
+    #[test]
+    fn export_jail_filter_jail_up() {
+        let source = FixtureJailSource::new();
+        let exporter = Exporter::with_source(source)
+            .jail_conf("test-data/jailconf/jail.conf")
+            .jail_filter(JailFilter::new().exclude("path=/www$".parse().unwrap()));
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_up{name=\"db\"} 0\n"));
+        assert!(!output.contains("jail_up{name=\"www\"}"));
+    }
+
+    #[test]
+    fn export_relabel() {
+        let config = Config::from_yaml(
+            Path::new("test-data/config/config.yaml"),
+        ).unwrap();
+
+        let (exporter, source) = fixture_exporter();
+        let exporter = exporter.relabel(config.relabel_configs);
+
+        let jail = Jail::with_params(1, "prod-www", &[
+            ("host.hostname", "www.web.example.org"),
+        ]);
+
+        let rusage = Rusage::from([(Resource::CpuTime, 1000)]);
+        source.add_jail(jail, rusage);
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_id{name=\"www\",team=\"web\"} 1\n"));
+        assert!(output.contains(
+            "jail_cputime_seconds_total{name=\"www\",team=\"web\"} 1000\n"
+        ));
+        assert!(output.contains(
+            "jail_cputime_seconds_created{name=\"www\",team=\"web\"} "
+        ));
+        assert!(jail_series(&output, "prod-www").is_empty());
+    }
+
+    #[test]
+    fn export_relabel_label_sets() {
+        let config = Config::from_yaml(
+            Path::new("test-data/config/config.yaml"),
+        ).unwrap();
+
+        let (exporter, source) = fixture_exporter_with("processes");
+        let exporter = exporter
+            .relabel(config.relabel_configs)
+            .processes(FixtureProcesses(vec![]));
+
+        // The team label is taken from the hostname.
+        let jail = |jid, name, team: &str| {
+            let hostname = format!("db.{team}.example.org");
+            Jail::with_params(jid, name, &[("host.hostname", &hostname)])
+        };
+
+        // Jail names may hold characters that need escaping.
+        source.add_jail(jail(1, "d\"b", "web"), Rusage::new());
+        source.add_jail(jail(2, "db", "web"), Rusage::new());
+        source.fail_jail("db", Stage::Rusage);
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_id{name=\"d\\\"b\",team=\"web\"} 1\n"));
+        assert!(output.contains(
+            "jail_processes{name=\"d\\\"b\",team=\"web\",state=\"zombie\"} 0\n"
+        ));
+
+        // The time series that aren't those of a jail are left alone.
+        assert!(output.contains(
+            "jail_exporter_collection_errors_total{name=\"db\",\
+             stage=\"rusage\"} 1\n"
+        ));
+
+        // Series move to the new label set when the labels of a jail change.
+        source.add_jail(jail(2, "db", "ops"), Rusage::new());
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_id{name=\"db\",team=\"ops\"} 2\n"));
+        assert!(!output.contains("jail_id{name=\"db\",team=\"web\"}"));
+        assert!(output.contains(
+            "jail_starts_total{name=\"db\",team=\"ops\"} 0\n"
+        ));
+        assert!(!output.contains(
+            "jail_starts_total{name=\"db\",team=\"web\"}"
+        ));
+
+        // Jails are reaped under the label set they were exported with.
+        source.remove_jail("d\"b");
+
+        let output = exporter.export().unwrap();
+        assert!(!output.contains("jail_id{name=\"d\\"));
+        assert!(!output.contains("jail_processes{name=\"d\\"));
+    }
+
+    #[test]
+    fn export_meta_labels() {
+        let config: MetaConfig = serde_yaml::from_str(
+            "keys: [owner, tier]\nall_series: true",
+        ).unwrap();
+
+        let (exporter, source) = fixture_exporter();
+        let exporter = exporter.meta_labels(config);
+
+        let jail = Jail::with_params(1, "www", &[
+            ("meta", "owner=alice\nsecret=x"),
+            ("env", "tier=gold"),
+        ]);
+
+        source.add_jail(jail, Rusage::new());
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains(
+            "jail_meta_info{name=\"www\",owner=\"alice\",tier=\"gold\"} 1\n"
+        ));
+        assert!(output.contains(
+            "jail_id{name=\"www\",owner=\"alice\",tier=\"gold\"} 1\n"
+        ));
+        assert!(!output.contains("secret"));
+
+        source.remove_jail("www");
+
+        let output = exporter.export().unwrap();
+        assert!(!output.contains("jail_meta_info{"));
+    }
+
+    #[test]
+    fn export_const_labels() {
+        let config = Config::from_yaml(
+            Path::new("test-data/config/config.yaml"),
+        ).unwrap();
+
+        let options = ExporterOptions::new()
+            .const_labels(config.const_labels);
+
+        let source = FixtureJailSource::new();
+        let exporter = Exporter::with_source_and_options(
+            source.clone(),
+            &options,
+        ).relabel(config.relabel_configs);
+
+        let rusage = Rusage::from([(Resource::CpuTime, 1000)]);
+        source.add_jail(Jail::new(1, "prod-www"), rusage);
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_num{datacenter=\"dc1\"} 1\n"));
+        assert!(output.contains(
+            "jail_exporter_build_info{datacenter=\"dc1\","
+        ));
+        assert!(output.contains(
+            "jail_cputime_seconds_total{datacenter=\"dc1\",name=\"www\"} 1000\n"
+        ));
+        assert!(output.contains(
+            "jail_cputime_seconds_created{datacenter=\"dc1\",name=\"www\"} "
+        ));
+
+        // Every sample carries the constant label.
+        let unlabelled = output.lines()
+            .filter(|line| !line.starts_with('#'))
+            .find(|line| !line.contains("datacenter=\"dc1\""));
+
+        assert_eq!(unlabelled, None);
+    }
+
+    #[test]
+    fn export_metric_prefix() {
+        let options = ExporterOptions::new()
+            .prefix("freebsd_jail");
+
+        let source = FixtureJailSource::new();
+        let exporter = Exporter::with_source_and_options(
+            source.clone(),
+            &options,
+        );
+
+        let rusage = Rusage::from([(Resource::CpuTime, 1000)]);
+        source.add_jail(Jail::new(1, "test"), rusage);
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("freebsd_jail_num 1\n"));
+        assert!(output.contains("freebsd_jail_info{"));
+        assert!(output.contains("freebsd_jail_exporter_build_info{"));
+        assert!(output.contains(
+            "freebsd_jail_cputime_seconds_created{name=\"test\"} "
+        ));
+
+        // Every time series has the prefix.
+        let unprefixed = output.lines()
+            .filter(|line| !line.starts_with('#'))
+            .find(|line| !line.starts_with("freebsd_jail_"));
+
+        assert_eq!(unprefixed, None);
+    }
+
+    #[test]
+    fn export_naming_scheme_conventional() {
+        let options = ExporterOptions::new()
+            .naming(NamingScheme::Conventional);
+
+        let source = FixtureJailSource::new();
+        let exporter = Exporter::with_source_and_options(
+            source.clone(),
+            &options,
+        );
+
+        let rusage = Rusage::from([
+            (Resource::CpuTime, 1000),
+            (Resource::MemoryUse, 2048),
+            (Resource::PercentCpu, 150),
+            (Resource::ReadBps, 4096),
+        ]);
+        source.add_jail(Jail::new(1, "test"), rusage);
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_cpu_seconds_total{name=\"test\"} 1000\n"));
+        assert!(output.contains("jail_cpu_seconds_created{name=\"test\"} "));
+        assert!(output.contains(
+            "jail_memory_resident_bytes{name=\"test\"} 2048\n"
+        ));
+        assert!(output.contains("jail_cpu_usage_ratio{name=\"test\"} 1.5\n"));
+        assert!(output.contains(
+            "jail_read_bytes_per_second{name=\"test\"} 4096\n"
+        ));
+
+        // None of the legacy names are registered.
+        assert!(!output.contains("jail_cputime_seconds"));
+        assert!(!output.contains("jail_pcpu_used"));
+        assert!(!output.contains("jail_readbps"));
+
+        // Reaping removes the ratio.
+        source.remove_jail("test");
+
+        let output = exporter.export().unwrap();
+        assert!(!output.contains("jail_cpu_usage_ratio{"));
+    }
+
+    #[test]
+    fn export_collectors_disabled() {
+        let mut collectors = Collectors::default();
+        collectors.set("rctl", false).unwrap();
+        collectors.set("rctl.cputime", true).unwrap();
+        collectors.set("limits", false).unwrap();
+
+        let options = ExporterOptions::new().collectors(collectors);
+        let source = FixtureJailSource::new();
+        let exporter = Exporter::with_source_and_options(
+            source.clone(),
+            &options,
+        );
+
+        let rusage = Rusage::from([
+            (Resource::CpuTime, 1000),
+            (Resource::MemoryUse, 2048),
+        ]);
+        source.add_jail(Jail::new(1, "test"), rusage);
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains(
+            "jail_cputime_seconds_total{name=\"test\"} 1000\n"
+        ));
+        assert!(output.contains("jail_id{name=\"test\"} 1\n"));
+
+        // The families of disabled collectors aren't exported at all, not
+        // even their HELP and TYPE lines.
+        assert!(!output.contains("jail_memoryuse_bytes"));
+        assert!(!output.contains("jail_nmsgq"));
+        assert!(!output.contains("jail_limit"));
+        assert!(!output.contains("jail_resource_utilisation"));
+        assert!(!output.contains("jail_network_"));
+    }
+
+    #[test]
+    fn export_collection_errors() {
+        let (exporter, source) = fixture_exporter();
+
+        let rusage = Rusage::from([(Resource::MemoryUse, 2048)]);
+        for (jid, name) in [
+            (1, "test_a"),
+            (2, "test_b"),
+            (3, "test_c"),
+            (4, "test_d"),
+        ] {
+            source.add_jail(Jail::new(jid, name), rusage.clone());
+        }
+
+        source.fail_jail("test_a", Stage::Jail);
+        source.fail_jail("test_b", Stage::Rusage);
+        source.fail_jail("test_c", Stage::Rules);
+
+        // The scrape succeeds, exporting the jails that could be listed,
+        // without the usage of those whose rctl(8) reads failed.
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_num 3\n"));
+        assert!(output.contains("jail_memoryuse_bytes{name=\"test_d\"} 2048\n"));
+        assert!(!output.contains("jail_id{name=\"test_a\"}"));
+
+        for (name, stage) in [
+            ("test_a", "jail"),
+            ("test_b", "rusage"),
+            ("test_c", "rules"),
+        ] {
+            let series = format!(
+                "jail_exporter_collection_errors_total{{name=\"{name}\",\
+                 stage=\"{stage}\"}} 1\n",
+            );
+
+            assert!(output.contains(&series), "{series}");
+            assert!(!output.contains(
+                &format!("jail_memoryuse_bytes{{name=\"{name}\"}}"),
+            ));
+        }
+
+        // The errors are counted on each run that fails.
+        let output = exporter.export().unwrap();
+        assert!(output.contains(
+            "jail_exporter_collection_errors_total{name=\"test_b\",\
+             stage=\"rusage\"} 2\n"
+        ));
+
+        // Collectors run after the jails are read skip the jails that
+        // couldn't be listed, but not those whose usage couldn't be read.
+        let (exporter, source) = fixture_exporter_with("processes");
+        let exporter = exporter.processes(FixtureProcesses(Vec::new()));
+
+        source.add_jail(Jail::new(1, "test_a"), Rusage::new());
+        source.add_jail(Jail::new(2, "test_b"), Rusage::new());
+        source.fail_jail("test_a", Stage::Jail);
+        source.fail_jail("test_b", Stage::Rusage);
+
+        let output = exporter.export().unwrap();
+        assert!(!output.contains("jail_processes{name=\"test_a\""));
+        assert!(output.contains(
+            "jail_processes{name=\"test_b\",state=\"runnable\"} 0\n"
+        ));
+    }
+
+    #[test]
+    fn export_rusage_failure_keeps_jail() {
+        let (exporter, source) = fixture_exporter();
+
+        let rusage = Rusage::from([(Resource::MemoryUse, 2048)]);
+        source.add_jail(Jail::new(1, "test"), rusage);
+        exporter.export().unwrap();
+
+        // The jail is still running, so it's still exported, keeping its
+        // previous usage.
+        source.fail_jail("test", Stage::Rusage);
+        let output = exporter.export().unwrap();
+
+        assert!(output.contains("jail_present{name=\"test\"} 1\n"));
+        assert!(output.contains("jail_memoryuse_bytes{name=\"test\"} 2048\n"));
+        assert!(output.contains("jail_starts_total{name=\"test\"} 0\n"));
+        assert!(output.contains("jail_stops_total{name=\"test\"} 0\n"));
+
+        // Reading it again doesn't count a start.
+        source.recover_jail("test");
+        let output = exporter.export().unwrap();
+
+        assert!(output.contains("jail_present{name=\"test\"} 1\n"));
+        assert!(output.contains("jail_starts_total{name=\"test\"} 0\n"));
+        assert!(output.contains("jail_stops_total{name=\"test\"} 0\n"));
+
+        // A jail that has never been read is still reaped once it's gone.
+        source.add_jail(Jail::new(2, "other"), Rusage::new());
+        source.fail_jail("other", Stage::Rusage);
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_id{name=\"other\"} 2\n"));
+
+        source.remove_jail("other");
+        let output = exporter.export().unwrap();
+        assert!(!output.contains("jail_id{name=\"other\"}"));
+    }
+
+    #[test]
+    fn export_scrape_metrics() {
+        let (exporter, source) = fixture_exporter();
+        source.add_jail(Jail::new(1, "test"), Rusage::new());
+
+        exporter.export().unwrap();
+        let output = exporter.export().unwrap();
+
+        assert!(output.contains(
+            "jail_exporter_scrapes_total{result=\"success\"} 2\n"
+        ));
+        assert!(output.contains(
+            "jail_exporter_scrape_duration_seconds_count 2\n"
+        ));
+        assert!(output.contains(
+            "jail_exporter_collector_duration_seconds{collector=\"rctl\"}"
+        ));
+        assert!(!output.contains("collector=\"zfs\""));
+
+        // The scrape time series are appended to the output, which still
+        // has a single end.
+        assert_eq!(output.matches("# EOF\n").count(), 1);
+        assert!(output.ends_with("# EOF\n"));
+
+        // Failed scrapes are counted, but return an error.
+        source.fail_all(true);
+        assert!(exporter.export().is_err());
+
+        let output = exporter.scrape.encode();
+        assert!(output.contains(
+            "jail_exporter_scrapes_total{result=\"failure\"} 1\n"
+        ));
+    }
+
+    #[test]
+    fn export_racct_disabled() {
+        let options = ExporterOptions::new()
+            .rctl(false)
+            .rctl_state(RctlState::Disabled);
+        let source = FixtureJailSource::new();
+        let exporter = Exporter::with_source_and_options(
+            source.clone(),
+            &options,
+        );
+
+        let jail = Jail::with_params(1, "test", &[
+            ("host.hostname", "test.example.com"),
+        ]);
+
+        let rusage = Rusage::from([(Resource::MemoryUse, 2048)]);
+        source.add_jail(jail, rusage);
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_id{name=\"test\"} 1\n"));
+        assert!(output.contains("jail_num 1\n"));
+        assert!(output.contains("jail_info{name=\"test\",jid=\"1\""));
+        assert!(output.contains(
+            "jail_exporter_racct_state{state=\"disabled\"} 1\n"
+        ));
+        assert!(output.contains(
+            "jail_exporter_racct_state{state=\"enabled\"} 0\n"
+        ));
+
+        // The time series queried from rctl(8) aren't exported at all.
+        assert!(!output.contains("jail_memoryuse_bytes"));
+        assert!(!output.contains("jail_limit"));
+    }
+
+    #[test]
+    fn export_reap_after() {
+        let (exporter, source) = fixture_exporter();
+        let exporter = exporter.reap_after(ReapAfter::Scrapes(1));
+
+        let rusage = Rusage::from([(Resource::MemoryUse, 2048)]);
+        source.add_jail(Jail::new(1, "test"), rusage);
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_present{name=\"test\"} 1\n"));
+
+        // The jail is missing, but kept with its last known values.
+        source.remove_jail("test");
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_num 0\n"));
+        assert!(output.contains("jail_present{name=\"test\"} 0\n"));
+        assert!(output.contains("jail_memoryuse_bytes{name=\"test\"} 2048\n"));
+
+        // Missing for more than one scrape, so the jail is reaped.
+        let output = exporter.export().unwrap();
+        assert!(jail_series(&output, "test").is_empty());
+    }
+
+    #[test]
+    fn export_lifecycle() {
+        let (exporter, source) = fixture_exporter();
+
+        source.add_jail(Jail::new(1, "test"), Rusage::new());
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_starts_total{name=\"test\"} 0\n"));
+
+        // Restarted under a new jail ID.
+        source.add_jail(Jail::new(2, "test"), Rusage::new());
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_restarts_total{name=\"test\"} 1\n"));
+
+        // The counters survive the jail being reaped, until its stop has
+        // been exported.
+        source.remove_jail("test");
+        let output = exporter.export().unwrap();
+        assert!(!output.contains("jail_id{name=\"test\"}"));
+        assert!(output.contains("jail_starts_total{name=\"test\"} 1\n"));
+        assert!(output.contains("jail_stops_total{name=\"test\"} 2\n"));
+
+        let output = exporter.export().unwrap();
+        assert!(!output.contains("name=\"test\""));
+    }
+
+    #[test]
+    fn export_created() {
+        let (exporter, source) = fixture_exporter();
+
+        let rusage = Rusage::from([
+            (Resource::CpuTime, 1000),
+            (Resource::Wallclock, 2000),
+        ]);
+        source.add_jail(Jail::new(1, "test"), rusage);
+
+        let output = exporter.export().unwrap();
+        let created: Vec<&str> = output.lines()
+            .filter(|line| line.contains("_created{"))
+            .collect();
+
+        assert_eq!(created.len(), 2);
+        assert!(created[0].starts_with("jail_cputime_seconds_created{name=\"test\"} "));
+        assert!(created[1].starts_with("jail_wallclock_seconds_created{name=\"test\"} "));
+
+        // The _created sample follows the _total sample of the series.
+        assert!(output.contains(&format!(
+            "jail_cputime_seconds_total{{name=\"test\"}} 1000\n{}\n",
+            created[0],
+        )));
+
+        // The creation time is kept from one scrape to the next.
+        let cputime_created = created[0].to_string();
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains(&format!("{cputime_created}\n")));
+
+        // A jail restarted under a new jail ID has its series created again.
+        let rusage = Rusage::from([(Resource::CpuTime, 10)]);
+        source.add_jail(Jail::new(2, "test"), rusage);
+
+        let output = exporter.export().unwrap();
+        assert!(!output.contains(&format!("{cputime_created}\n")));
+        assert!(output.contains(
+            "jail_cputime_seconds_total{name=\"test\"} 10\n\
+             jail_cputime_seconds_created{name=\"test\"} "
+        ));
+
+        // Reaping removes the _created samples.
+        source.remove_jail("test");
+
+        let output = exporter.export().unwrap();
+        assert!(!output.contains("_created{"));
+    }
+
+    #[test]
+    fn export_created_labelled() {
+        // Returns a single interface.
+        struct FixtureReader;
+
+        impl InterfaceReader for FixtureReader {
+            fn interfaces(&self, _jail: &Jail)
+            -> Result<Vec<InterfaceStats>, ExporterError> {
+                let stats = InterfaceStats {
+                    interface:     "epair0b".into(),
+                    receive_bytes: 1024,
+                    ..Default::default()
+                };
+
+                Ok(vec![stats])
+            }
+        }
+
+        let config = Config::from_yaml(
+            Path::new("test-data/config/config.yaml"),
+        ).unwrap();
+
+        let mut collectors = Collectors::default();
+        collectors.set("network", true).unwrap();
+
+        let options = ExporterOptions::new()
+            .collectors(collectors)
+            .const_labels(config.const_labels)
+            .naming(NamingScheme::Conventional);
+
+        let source = FixtureJailSource::new();
+        let exporter = Exporter::with_source_and_options(
+            source.clone(),
+            &options,
+        )
+        .relabel(config.relabel_configs)
+        .network(FixtureReader);
+
+        let jail = Jail::with_params(1, "prod-www", &[
+            ("host.hostname", "www.web.example.org"),
+            ("vnet", "1"),
+        ]);
+
+        let rusage = Rusage::from([(Resource::CpuTime, 1000)]);
+        source.add_jail(jail, rusage);
+
+        let output = exporter.export().unwrap();
+
+        // Each _created sample follows its _total sample, with the constant
+        // labels and the labels from relabeling.
+        let labels = "{datacenter=\"dc1\",name=\"www\",team=\"web\"}";
+        assert!(output.contains(&format!(
+            "jail_cpu_seconds_total{labels} 1000\n\
+             jail_cpu_seconds_created{labels} "
+        )));
+
+        let labels = "{datacenter=\"dc1\",name=\"www\",team=\"web\",\
+                      interface=\"epair0b\"}";
+        assert!(output.contains(&format!(
+            "jail_network_receive_bytes_total{labels} 1024\n\
+             jail_network_receive_bytes_created{labels} "
+        )));
+
+        let created = output.lines()
+            .filter(|line| line.contains("_created{"))
+            .count();
+
+        // One for the CPU time, and one for each network counter.
+        assert_eq!(created, 9);
+    }
+
+    #[test]
+    fn export_counters_follow_source() {
+        let (exporter, source) = fixture_exporter();
+        let jail = Jail::new(1, "test");
+
+        let rusage = Rusage::from([(Resource::CpuTime, 1000)]);
+        source.add_jail(jail.clone(), rusage);
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_cputime_seconds_total{name=\"test\"} 1000\n"));
+
+        // The jail restarted, the counter should follow the OS value.
+        let rusage = Rusage::from([(Resource::CpuTime, 10)]);
+        source.add_jail(jail, rusage);
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_cputime_seconds_total{name=\"test\"} 10\n"));
+    }
+
+    #[test]
+    fn export_info() {
+        let (exporter, source) = fixture_exporter();
+
+        let mut jail = Jail::with_params(1, "test", &[
+            ("host.hostname", "test.example.com"),
+            ("path", "/jails/test"),
+            ("osrelease", "14.3-RELEASE"),
+            ("ip4.addr", "192.0.2.1,192.0.2.2"),
+            ("ip6.addr", ""),
+            ("vnet", "2"),
+            ("persist", "1"),
+        ]);
+
+        source.add_jail(jail.clone(), Rusage::new());
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("# TYPE jail info\n"));
+        assert!(output.contains(
+            "jail_info{name=\"test\",jid=\"1\",hostname=\"test.example.com\",\
+             path=\"/jails/test\",osrelease=\"14.3-RELEASE\",\
+             ip4=\"192.0.2.1,192.0.2.2\",ip6=\"\",vnet=\"inherit\",\
+             persist=\"true\"} 1\n"
+        ));
+
+        // Changing a parameter replaces the old label set.
+        jail.params.insert("host.hostname".into(), "new.example.com".into());
+        source.add_jail(jail, Rusage::new());
+
+        let output = exporter.export().unwrap();
+        assert!(!output.contains("test.example.com"));
+        assert!(output.contains("hostname=\"new.example.com\""));
+
+        // Reaping removes the jail_info.
+        source.remove_jail("test");
+
+        let output = exporter.export().unwrap();
+        assert!(!output.contains("jail_info{"));
+    }
+
+    #[test]
+    fn export_hierarchy() {
+        let (exporter, source) = fixture_exporter();
+
+        let parent = Jail::with_params(1, "parent", &[
+            ("parent", "0"),
+            ("children.cur", "1"),
+            ("children.max", "2"),
+        ]);
+
+        let child = Jail::with_params(2, "parent.child", &[
+            ("parent", "1"),
+            ("children.cur", "0"),
+            ("children.max", "0"),
+        ]);
+
+        source.add_jail(parent, Rusage::new());
+        source.add_jail(child, Rusage::new());
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains(
+            "jail_parent_info{name=\"parent\",parent=\"\"} 1\n"
+        ));
+        assert!(output.contains(
+            "jail_parent_info{name=\"parent.child\",parent=\"parent\"} 1\n"
+        ));
+        assert!(output.contains("jail_children_cur{name=\"parent\"} 1\n"));
+        assert!(output.contains("jail_children_max{name=\"parent\"} 2\n"));
+
+        // Reaping the child removes its hierarchy metrics.
+        source.remove_jail("parent.child");
+
+        let output = exporter.export().unwrap();
+        assert!(jail_series(&output, "parent.child").is_empty());
+    }
+
+    #[test]
+    fn export_jail_up() {
+        let source = FixtureJailSource::new();
+        let exporter = Exporter::with_source(source.clone())
+            .jail_conf("test-data/jailconf/jail.conf");
+
+        source.add_jail(Jail::new(1, "www"), Rusage::new());
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_up{name=\"www\"} 1\n"));
+        assert!(output.contains("jail_up{name=\"db\"} 0\n"));
+        assert!(output.contains("jail_up{name=\"mail\"} 0\n"));
+
+        // A stopped jail is reaped, but is still reported as down.
+        source.remove_jail("www");
+
+        let output = exporter.export().unwrap();
+        assert!(!output.contains("jail_id{name=\"www\"}"));
+        assert!(output.contains("jail_up{name=\"www\"} 0\n"));
+
+        // Running jails that aren't configured have no jail_up.
+        source.add_jail(Jail::new(2, "other"), Rusage::new());
+
+        let output = exporter.export().unwrap();
+        assert!(!output.contains("jail_up{name=\"other\"}"));
+    }
+
+    #[test]
+    fn export_jail_up_relabel() {
+        let config = Config::from_yaml(
+            Path::new("test-data/config/config.yaml"),
+        ).unwrap();
+
+        let exporter = Exporter::with_source(FixtureJailSource::new())
+            .jail_conf("test-data/jailconf/jail.conf")
+            .relabel(config.relabel_configs);
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_up{name=\"db\",team=\"example\"} 0\n"));
+
+        // The labels of jails that are no longer configured are forgotten.
+        let exporter = exporter.jail_conf("test-data/jailconf/jail.conf.d/mail.conf");
+
+        let output = exporter.export().unwrap();
+        assert!(!output.contains("jail_up{name=\"db\""));
+
+        assert!(exporter.configured_jails.lock().get("db").is_none());
+    }
+
+    #[test]
+    fn export_jail_up_invalid() {
+        let source = FixtureJailSource::new();
+        let exporter = Exporter::with_source(source.clone())
+            .jail_conf("test-data/jailconf/jail.conf");
+
+        source.add_jail(Jail::new(1, "www"), Rusage::new());
+        exporter.export().unwrap();
+
+        // An unreadable jail.conf(5) keeps the previous jail_up, and is
+        // counted without failing the scrape.
+        let exporter = exporter.jail_conf("test-data/jailconf/invalid.conf");
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_up{name=\"www\"} 1\n"));
+        assert!(output.contains("jail_up{name=\"db\"} 0\n"));
+        assert!(output.contains(
+            "jail_exporter_collection_errors_total{name=\"\",\
+             stage=\"jail_conf\"} 1\n"
+        ));
+    }
+
+    #[cfg(feature = "jail_manager")]
+    #[test]
+    fn export_jail_managers() {
+        let managers = JailManagers::new()
+            .bastille_root("test-data/manager/bastille".into())
+            .iocage_root("test-data/manager/missing".into())
+            .pot_root("test-data/manager/pot".into());
+
+        let (exporter, _source) = fixture_exporter_with("jail-manager");
+        let exporter = exporter.jail_managers(managers);
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("# TYPE jail_manager info\n"));
+        assert!(output.contains(
+            "jail_manager_info{name=\"web\",manager=\"bastille\",\
+             release=\"14.3-RELEASE\",tags=\"prod,web\"} 1\n"
+        ));
+        assert!(output.contains(
+            "jail_manager_info{name=\"dns\",manager=\"pot\",\
+             release=\"14.3\",template=\"base-dns\"} 1\n"
+        ));
+    }
+
+    #[test]
+    fn export_filesystem() {
+        let (exporter, source) = fixture_exporter();
+        let dir = tempfile::tempdir().unwrap();
+
+        let jail = Jail::with_params(1, "test", &[
+            ("path", dir.path().to_str().unwrap()),
+        ]);
+
+        source.add_jail(jail, Rusage::new());
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("# TYPE jail_filesystem_size_bytes gauge\n"));
+        assert!(output.contains("jail_filesystem_size_bytes{name=\"test\"} "));
+        assert!(output.contains("jail_filesystem_avail_bytes{name=\"test\"} "));
+        assert!(output.contains("jail_filesystem_files_free{name=\"test\"} "));
+
+        // Reaping removes the filesystem time series.
+        source.remove_jail("test");
+
+        let output = exporter.export().unwrap();
+        assert!(!output.contains("jail_filesystem_size_bytes{"));
+    }
+
+    #[test]
+    fn export_zfs() {
+        // Returns captured zfs(8) output.
+        struct FixtureRunner;
+
+        impl CommandRunner for FixtureRunner {
+            fn run(&self, _program: &str, _args: &[&str])
+            -> Result<String, ExporterError> {
+                let output = include_str!("../test-data/zfs/list.txt");
+                Ok(output.into())
+            }
+        }
+
+        let (exporter, source) = fixture_exporter_with("zfs");
+        let exporter = exporter.zfs(FixtureRunner);
+
+        let jail = Jail::with_params(1, "www", &[
+            ("path", "/jails/www"),
+        ]);
+
+        source.add_jail(jail, Rusage::new());
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains(
+            "jail_zfs_quota_bytes{name=\"www\",dataset=\"zroot/jails/www\"} \
+             10737418240\n"
+        ));
+        assert!(output.contains(
+            "jail_zfs_compressratio{name=\"www\",dataset=\"zroot/jails/www\"} \
+             1.87\n"
+        ));
+
+        // Reaping removes the ZFS time series.
+        source.remove_jail("www");
+
+        let output = exporter.export().unwrap();
+        assert!(!output.contains("jail_zfs_used_bytes{"));
+    }
+
+    #[test]
+    fn export_network() {
+        // Returns captured netstat(1) output.
+        struct FixtureRunner;
+
+        impl CommandRunner for FixtureRunner {
+            fn run(&self, _program: &str, _args: &[&str])
+            -> Result<String, ExporterError> {
+                let output = include_str!("../test-data/network/netstat.txt");
+                Ok(output.into())
+            }
+        }
+
+        let (exporter, source) = fixture_exporter_with("network");
+        let exporter = exporter.network(NetstatReader::new(FixtureRunner));
+
+        let jail = Jail::with_params(1, "vnet", &[("vnet", "1")]);
+        source.add_jail(jail, Rusage::new());
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains(
+            "jail_network_receive_bytes_total\
+             {name=\"vnet\",interface=\"epair0b\"} 9876543\n"
+        ));
+        assert!(output.contains(
+            "jail_network_transmit_drop_total\
+             {name=\"vnet\",interface=\"epair0b\"} 4\n"
+        ));
+
+        // Reaping removes the network time series.
+        source.remove_jail("vnet");
+
+        let output = exporter.export().unwrap();
+        assert!(!output.contains("jail_network_receive_bytes_total{"));
+    }
+
+    #[test]
+    fn export_processes() {
+        // One zombie in the jail, alongside a process on the host.
+        let process = |jid, state| {
+            Process {
+                jid:        jid,
+                state:      state,
+                start_time: 1000,
+            }
+        };
+
+        let (exporter, source) = fixture_exporter_with("processes");
+        let exporter = exporter.processes(FixtureProcesses(vec![
+            process(0, ProcessState::Sleeping),
+            process(1, ProcessState::Sleeping),
+            process(1, ProcessState::Runnable),
+            process(1, ProcessState::Zombie),
+        ]));
+
+        source.add_jail(Jail::new(1, "test"), Rusage::new());
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains(
+            "jail_processes{name=\"test\",state=\"sleeping\"} 1\n"
+        ));
+        assert!(output.contains(
+            "jail_processes{name=\"test\",state=\"zombie\"} 1\n"
+        ));
+        assert!(output.contains(
+            "jail_processes{name=\"test\",state=\"stopped\"} 0\n"
+        ));
+        assert!(output.contains(
+            "jail_start_time_seconds{name=\"test\"} 1000\n"
+        ));
+
+        // Reaping removes the process time series.
+        source.remove_jail("test");
+
+        let output = exporter.export().unwrap();
+        assert!(!output.contains("jail_processes{"));
+        assert!(!output.contains("jail_start_time_seconds{"));
+    }
+
+    #[test]
+    fn export_start_time() {
+        // Start times are exported by default, without the process states.
+        let (exporter, source) = fixture_exporter();
+        let exporter = exporter.processes(FixtureProcesses(vec![
+            Process {
+                jid:        1,
+                state:      ProcessState::Sleeping,
+                start_time: 1000,
+            },
+        ]));
+
+        source.add_jail(Jail::new(1, "a"), Rusage::new());
+        source.add_jail(Jail::new(2, "b"), Rusage::new());
+
+        let before = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_start_time_seconds{name=\"a\"} 1000\n"));
+        assert!(!output.contains("jail_processes{"));
+
+        // Jail b has no processes, so it started when it was first seen.
+        let start = output.lines()
+            .find_map(|line| {
+                line.strip_prefix("jail_start_time_seconds{name=\"b\"} ")
+            })
+            .map(|value| value.parse::<u64>().unwrap())
+            .unwrap();
+
+        assert!(start >= before);
+
+        // Without a process table, start times are still exported.
+        let (exporter, source) = fixture_exporter();
+        source.add_jail(Jail::new(1, "a"), Rusage::new());
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_start_time_seconds{name=\"a\"} "));
+    }
+
+    #[test]
+    fn export_limits() {
+        let (exporter, source) = fixture_exporter();
+
+        let rusage = Rusage::from([(Resource::MemoryUse, 268435456)]);
+        source.add_jail(Jail::new(1, "test"), rusage);
+        source.set_rules("test", &[
+            "jail:test:memoryuse:deny=1073741824",
+            "jail:test:maxproc:log=100/process",
+        ]);
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains(
+            "jail_limit{name=\"test\",resource=\"memoryuse\",action=\"deny\",per=\"jail\"} 1073741824\n"
+        ));
+        assert!(output.contains(
+            "jail_limit{name=\"test\",resource=\"maxproc\",action=\"log\",per=\"process\"} 100\n"
+        ));
+        assert!(output.contains(
+            "jail_resource_utilisation_ratio{name=\"test\",resource=\"memoryuse\"} 0.25\n"
+        ));
+
+        source.remove_jail("test");
+
+        let output = exporter.export().unwrap();
+        assert!(!output.contains("jail_limit{"));
+        assert!(!output.contains("jail_resource_utilisation_ratio{"));
+    }
+
+    #[test]
+    fn export_reaps_dead_jails() {
+        let (exporter, source) = fixture_exporter();
+
+        let rusage = Rusage::from([(Resource::MemoryUse, 2048)]);
+        source.add_jail(Jail::new(1, "test_a"), rusage.clone());
+        source.add_jail(Jail::new(2, "test_b"), rusage);
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_memoryuse_bytes{name=\"test_b\"} 2048\n"));
+
+        source.remove_jail("test_b");
+
+        let output = exporter.export().unwrap();
+        assert!(output.contains("jail_memoryuse_bytes{name=\"test_a\"} 2048\n"));
+        assert!(jail_series(&output, "test_b").is_empty());
+    }
+
     #[test]
     fn reap_ok() {
         let names = ["test_a", "test_b", "test_c"];
@@ -678,7 +2317,9 @@ mod tests {
         for name in names {
             let mut hash = Rusage::new();
             hash.insert(Resource::CpuTime, 1000);
-            exporter.process_rusage(name, &hash);
+            let labels = &JailLabelSet::from(name);
+            exporter.process_rusage(labels, &hash);
+            exporter.add_seen_jail(labels);
         }
 
         // Now, create a seen array containing only a and c.
@@ -687,59 +2328,19 @@ mod tests {
             "test_c".into(),
         ]);
 
-        let dead_jail = "test_b";
-        let labels = &NameLabel {
-            name: dead_jail.to_string(),
-        };
+        let series = "jail_cputime_seconds_total{name=\"test_b\"} 1000\n";
 
-        assert_eq!(exporter.cputime.get_or_create(labels).get(), 1000);
+        let mut buffer = String::new();
+        encode(&mut buffer, &exporter.registry).unwrap();
+        assert!(buffer.contains(series));
 
         // Workout which jails are dead, it should be b.
         let dead = exporter.dead_jails(&seen);
         exporter.reap(dead);
 
-        assert_eq!(exporter.cputime.get_or_create(labels).get(), 0);
-    }
-
-    #[test]
-    fn wallclock_counter_increase() {
-        let names = ["test", "test2"];
-        let exporter = Exporter::new();
-
-        for name in names {
-            let mut hash = Rusage::new();
-
-            let labels = &NameLabel {
-                name: name.to_string(),
-            };
-
-            // Initial check, should be zero. We didn't set anything yet.
-            assert_eq!(exporter.wallclock.get_or_create(labels).get(), 0);
-
-            // First run, adds 1000, total 1000.
-            hash.insert(Resource::Wallclock, 1000);
-            exporter.process_rusage(name, &hash);
-            assert_eq!(exporter.wallclock.get_or_create(labels).get(), 1000);
-
-            // Second, adds 20, total 1020
-            hash.insert(Resource::Wallclock, 1020);
-            exporter.process_rusage(name, &hash);
-            assert_eq!(exporter.wallclock.get_or_create(labels).get(), 1020);
-
-            // Third, counter was reset. Adds 10, total 1030.
-            hash.insert(Resource::Wallclock, 10);
-            exporter.process_rusage(name, &hash);
-            assert_eq!(exporter.wallclock.get_or_create(labels).get(), 10);
-
-            // Fourth, adds 40, total 1070.
-            hash.insert(Resource::Wallclock, 50);
-            exporter.process_rusage(name, &hash);
-            assert_eq!(exporter.wallclock.get_or_create(labels).get(), 50);
-
-            // Fifth, add 0, total 1070
-            hash.insert(Resource::Wallclock, 50);
-            exporter.process_rusage(name, &hash);
-            assert_eq!(exporter.wallclock.get_or_create(labels).get(), 50);
-        }
+        let mut buffer = String::new();
+        encode(&mut buffer, &exporter.registry).unwrap();
+        assert!(!buffer.contains("name=\"test_b\""));
+        assert!(buffer.contains("jail_cputime_seconds_total{name=\"test_a\"}"));
     }
 }
@@ -31,15 +31,17 @@ impl fmt::Display for FileExporterOutput {
 }
 
 pub struct FileExporter {
-    dest: FileExporterOutput,
+    dest:     FileExporterOutput,
+    exporter: Exporter,
 }
 
 impl FileExporter {
-    pub fn new(output: FileExporterOutput) -> Self {
+    pub fn new(output: FileExporterOutput, exporter: Exporter) -> Self {
         debug!("New FileExporter output to: {output}");
 
         Self {
-            dest: output,
+            dest:     output,
+            exporter: exporter,
         }
     }
 
@@ -70,9 +72,8 @@ impl FileExporter {
     pub fn export(self) -> Result<(), ExporterError> {
         debug!("Exporting metrics to file");
 
-        // Get an exporter and export the metrics.
-        let exporter = Exporter::new();
-        let metrics  = exporter.export()?;
+        // Export the metrics.
+        let metrics = self.exporter.export()?;
 
         // Write metrics
         self.write(&metrics)?;
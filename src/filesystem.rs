@@ -0,0 +1,217 @@
+// filesystem: This module exports the usage of the filesystem holding each
+//             jail's root.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::register_gauge_with_registry;
+use crate::errors::ExporterError;
+use crate::jailsource::Jail;
+use crate::relabel::JailLabelSet;
+use prometheus_client::metrics::{
+    family::Family,
+    gauge::Gauge,
+};
+use prometheus_client::registry::{
+    Registry,
+    Unit,
+};
+use std::path::Path;
+use tracing::debug;
+
+/// Usage of a filesystem.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FilesystemUsage {
+    /// Total size of the filesystem, in bytes.
+    pub size_bytes: u64,
+
+    /// Space available to unprivileged users, in bytes.
+    pub avail_bytes: u64,
+
+    /// Number of free inodes.
+    pub files_free: u64,
+}
+
+/// A source of filesystem usage.
+pub trait FilesystemReader {
+    /// Returns the usage of the filesystem holding `path`.
+    fn usage(&self, path: &Path) -> Result<FilesystemUsage, ExporterError>;
+}
+
+/// A `FilesystemReader` backed by statvfs(3).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StatvfsReader;
+
+impl FilesystemReader for StatvfsReader {
+    // The types of the statvfs fields differ between platforms.
+    #[allow(clippy::useless_conversion)]
+    fn usage(&self, path: &Path) -> Result<FilesystemUsage, ExporterError> {
+        let stat = nix::sys::statvfs::statvfs(path)
+            .map_err(std::io::Error::from)?;
+
+        let fragment_size = u64::from(stat.fragment_size());
+
+        let usage = FilesystemUsage {
+            size_bytes:  u64::from(stat.blocks()).saturating_mul(fragment_size),
+            avail_bytes: u64::from(stat.blocks_available()).saturating_mul(fragment_size),
+            files_free:  u64::from(stat.files_free()),
+        };
+
+        Ok(usage)
+    }
+}
+
+/// Time series describing the filesystem usage of jails.
+pub struct FilesystemMetrics {
+    avail:      Family<JailLabelSet, Gauge>,
+    files_free: Family<JailLabelSet, Gauge>,
+    size:       Family<JailLabelSet, Gauge>,
+
+    reader: Box<dyn FilesystemReader + Send + Sync>,
+}
+
+impl FilesystemMetrics {
+    /// Registers the filesystem time series with the given `registry`,
+    /// reading usage with `reader`.
+    pub fn new<R>(registry: &mut Registry, reader: R) -> Self
+    where
+        R: FilesystemReader + Send + Sync + 'static,
+    {
+        Self {
+            avail: register_gauge_with_registry!(
+                "filesystem_avail",
+                "Space available to unprivileged users on the filesystem \
+                 holding the named jail's path, in bytes",
+                JailLabelSet,
+                Unit::Bytes,
+                registry,
+            ),
+
+            files_free: register_gauge_with_registry!(
+                "filesystem_files_free",
+                "Free inodes on the filesystem holding the named jail's path",
+                JailLabelSet,
+                registry,
+            ),
+
+            size: register_gauge_with_registry!(
+                "filesystem_size",
+                "Size of the filesystem holding the named jail's path, in bytes",
+                JailLabelSet,
+                Unit::Bytes,
+                registry,
+            ),
+
+            reader: Box::new(reader),
+        }
+    }
+
+    /// Sets the filesystem time series for the given jail from its `path`
+    /// parameter, with the given `labels`.
+    pub fn update(&self, jail: &Jail, labels: &JailLabelSet) {
+        let usage = jail.param("path")
+            .filter(|path| !path.is_empty())
+            .map(|path| self.reader.usage(Path::new(path)));
+
+        // A jail may be on its way out, with its path already gone. Don't
+        // fail the whole scrape because of this.
+        let usage = match usage {
+            Some(Ok(usage)) => usage,
+            Some(Err(e)) => {
+                debug!("Couldn't get filesystem usage for {}: {e}", jail.name);
+                self.remove(labels);
+                return;
+            },
+            None => {
+                self.remove(labels);
+                return;
+            },
+        };
+
+        let values = [
+            (&self.avail, usage.avail_bytes),
+            (&self.files_free, usage.files_free),
+            (&self.size, usage.size_bytes),
+        ];
+
+        for (family, value) in values {
+            let value = i64::try_from(value).unwrap_or(i64::MAX);
+            family.get_or_create(labels).set(value);
+        }
+    }
+
+    /// Removes the filesystem time series for the jail with the given
+    /// `labels`.
+    pub fn remove(&self, labels: &JailLabelSet) {
+        self.avail.remove(labels);
+        self.files_free.remove(labels);
+        self.size.remove(labels);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    // Returns fixed usage for every path.
+    struct FixedReader;
+
+    impl FilesystemReader for FixedReader {
+        fn usage(&self, _path: &Path) -> Result<FilesystemUsage, ExporterError> {
+            Ok(FilesystemUsage {
+                size_bytes:  1024,
+                avail_bytes: 512,
+                files_free:  10,
+            })
+        }
+    }
+
+    #[test]
+    fn statvfs_reader_usage() {
+        let dir = tempfile::tempdir().unwrap();
+        let usage = StatvfsReader.usage(dir.path()).unwrap();
+
+        assert!(usage.size_bytes > 0);
+        assert!(usage.avail_bytes <= usage.size_bytes);
+    }
+
+    #[test]
+    fn statvfs_reader_missing_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+
+        assert!(StatvfsReader.usage(&missing).is_err());
+    }
+
+    #[test]
+    fn filesystem_metrics_update() {
+        let mut registry = Registry::default();
+        let metrics = FilesystemMetrics::new(&mut registry, FixedReader);
+        let labels = &JailLabelSet::from("test");
+        let jail = Jail::with_params(1, "test", &[("path", "/jails/test")]);
+
+        metrics.update(&jail, labels);
+
+        assert_eq!(metrics.size.get_or_create(labels).get(), 1024);
+        assert_eq!(metrics.avail.get_or_create(labels).get(), 512);
+        assert_eq!(metrics.files_free.get_or_create(labels).get(), 10);
+    }
+
+    #[test]
+    fn filesystem_metrics_update_missing_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap().to_string();
+
+        let mut registry = Registry::default();
+        let metrics = FilesystemMetrics::new(&mut registry, StatvfsReader);
+        let jail = Jail::with_params(1, "test", &[("path", &path)]);
+        let labels = &JailLabelSet::from("test");
+
+        metrics.update(&jail, labels);
+        assert!(metrics.size.get(labels).is_some());
+
+        // Once the path goes away, so do the time series.
+        drop(dir);
+        metrics.update(&jail, labels);
+        assert!(metrics.size.get(labels).is_none());
+    }
+}
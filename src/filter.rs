@@ -0,0 +1,199 @@
+// filter: This module decides which jails are exported, matching their names
+//         and parameters against regular expressions.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::jailconf::ConfParams;
+use crate::jailsource::Jail;
+use regex::Regex;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+// Splits a matcher into its parameter name and regular expression.
+static PARAM_PREFIX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^([A-Za-z0-9_.]+)=(.*)$").expect("valid param regex")
+});
+
+/// Matches a jail parameter against a regular expression.
+///
+/// Written as `param=regex`, or just `regex` to match the jail name.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub struct JailMatcher {
+    param: String,
+    regex: Regex,
+}
+
+impl FromStr for JailMatcher {
+    type Err = regex::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [param, regex] = match PARAM_PREFIX.captures(s) {
+            Some(captures) => captures.extract().1,
+            None           => ["name", s],
+        };
+
+        let matcher = Self {
+            param: param.to_string(),
+            regex: Regex::new(regex)?,
+        };
+
+        Ok(matcher)
+    }
+}
+
+impl TryFrom<String> for JailMatcher {
+    type Error = regex::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for JailMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.param, self.regex)
+    }
+}
+
+impl JailMatcher {
+    // Returns true if the parameter is set and matches the regex. The name
+    // is always set.
+    fn is_match<'a, F>(&self, name: &str, param: &F) -> bool
+    where
+        F: Fn(&str) -> Option<Cow<'a, str>>,
+    {
+        if self.param == "name" {
+            return self.regex.is_match(name);
+        }
+
+        param(&self.param).is_some_and(|value| self.regex.is_match(&value))
+    }
+}
+
+/// Filters jails using include and exclude matchers.
+///
+/// A jail is exported if it matches any of the include matchers, or there
+/// are none, and matches none of the exclude matchers.
+#[derive(Clone, Debug, Default)]
+pub struct JailFilter {
+    include: Vec<JailMatcher>,
+    exclude: Vec<JailMatcher>,
+}
+
+// Implements a builder pattern for configuring the filter.
+impl JailFilter {
+    /// Returns a filter matching every jail.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only export jails matching `matcher`, or any other include matcher.
+    #[must_use]
+    pub fn include(mut self, matcher: JailMatcher) -> Self {
+        self.include.push(matcher);
+        self
+    }
+
+    /// Don't export jails matching `matcher`.
+    #[must_use]
+    pub fn exclude(mut self, matcher: JailMatcher) -> Self {
+        self.exclude.push(matcher);
+        self
+    }
+
+    /// Returns true if the running `jail` should be exported.
+    pub fn matches(&self, jail: &Jail) -> bool {
+        self.matches_with(&jail.name, |param| jail.param(param).map(Cow::from))
+    }
+
+    /// Returns true if the named jail, configured with `params` in
+    /// jail.conf(5), should be exported.
+    pub fn matches_conf(&self, name: &str, params: &ConfParams) -> bool {
+        self.matches_with(name, |param| {
+            params.get(param).map(|values| Cow::from(values.join(",")))
+        })
+    }
+
+    fn matches_with<'a, F>(&self, name: &str, param: F) -> bool
+    where
+        F: Fn(&str) -> Option<Cow<'a, str>>,
+    {
+        let included = self.include.is_empty()
+            || self.include.iter().any(|m| m.is_match(name, &param));
+
+        let excluded = self.exclude.iter().any(|m| m.is_match(name, &param));
+
+        included && !excluded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn matcher(s: &str) -> JailMatcher {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn jail_matcher_from_str() {
+        let tests = [
+            ("^ci-", "name=^ci-"),
+            ("path=^/ci/", "path=^/ci/"),
+            ("host.hostname=\\.example\\.org$", "host.hostname=\\.example\\.org$"),
+            ("a{1,2}=b", "name=a{1,2}=b"),
+        ];
+
+        for (input, ok) in tests {
+            assert_eq!(matcher(input).to_string(), ok);
+        }
+
+        assert!("path=(".parse::<JailMatcher>().is_err());
+    }
+
+    #[test]
+    fn jail_filter_default() {
+        let jail = Jail::with_params(1, "www", &[("path", "/jails/www")]);
+
+        assert!(JailFilter::new().matches(&jail));
+    }
+
+    #[test]
+    fn jail_filter_include_exclude() {
+        let filter = JailFilter::new()
+            .include(matcher("^prod-"))
+            .include(matcher("path=^/jails/"))
+            .exclude(matcher("-test$"));
+
+        let tests = [
+            ("prod-www", "/ci/www", true),
+            ("db", "/jails/db", true),
+            ("ci-1234", "/ci/1234", false),
+            ("prod-test", "/jails/test", false),
+        ];
+
+        for (name, path, ok) in tests {
+            let jail = Jail::with_params(1, name, &[("path", path)]);
+
+            assert_eq!(filter.matches(&jail), ok, "{name}");
+        }
+
+        // Jails without the parameter don't match.
+        assert!(!filter.matches(&Jail::new(1, "db")));
+    }
+
+    #[test]
+    fn jail_filter_matches_conf() {
+        let filter = JailFilter::new().exclude(matcher("path=^/ci/"));
+        let params = ConfParams::from([
+            ("path".into(), vec!["/ci/1234".into()]),
+        ]);
+
+        assert!(!filter.matches_conf("ci-1234", &params));
+        assert!(filter.matches_conf("www", &ConfParams::new()));
+    }
+}
@@ -0,0 +1,197 @@
+// hierarchy: This module handles exporting the hierarchy of jails, for hosts
+//            running jails within jails.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::{
+    register_gauge_with_registry,
+    register_info_with_registry,
+};
+use crate::info::InfoFamily;
+use crate::jailsource::Jail;
+use crate::relabel::JailLabelSet;
+use parking_lot::Mutex;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::{
+    family::Family,
+    gauge::Gauge,
+};
+use prometheus_client::registry::Registry;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, EncodeLabelSet)]
+struct ParentLabels {
+    // Jail name, along with the labels added to the jail.
+    #[prometheus(flatten)]
+    jail: JailLabelSet,
+
+    // Name of the parent jail, empty for jails at the top of the hierarchy.
+    parent: String,
+}
+
+/// Mapping of jail IDs to jail names, used to find the name of a parent
+/// jail.
+pub type JailNames<'a> = HashMap<i32, &'a str>;
+
+/// Returns the name of the parent of the given jail, if it has one.
+///
+/// The parent is found via the jail's `parent` parameter. If the parent isn't
+/// visible to us, we fall back to the hierarchical jail name, where a child
+/// jail is named `parent.child`.
+pub fn parent_name(jail: &Jail, names: &JailNames) -> Option<String> {
+    let parent = jail.param("parent")
+        .and_then(|jid| jid.parse::<i32>().ok())
+        .unwrap_or(0);
+
+    // A parent of 0 means that the jail is at the top of the hierarchy as
+    // far as we can see.
+    if parent == 0 {
+        return None;
+    }
+
+    if let Some(name) = names.get(&parent) {
+        return Some((*name).to_string());
+    }
+
+    jail.name
+        .rsplit_once('.')
+        .map(|(parent, _child)| parent.to_string())
+}
+
+/// Time series describing the jail hierarchy.
+pub struct HierarchyMetrics {
+    children_cur: Family<JailLabelSet, Gauge>,
+    children_max: Family<JailLabelSet, Gauge>,
+    parent_info:  InfoFamily<ParentLabels>,
+
+    // The parent_info label set of each jail, kept so that the old label set
+    // can be removed.
+    parent_labels: Mutex<HashMap<String, ParentLabels>>,
+}
+
+impl HierarchyMetrics {
+    /// Registers the hierarchy time series with the given `registry`.
+    pub fn new(registry: &mut Registry) -> Self {
+        Self {
+            children_cur: register_gauge_with_registry!(
+                "children_cur",
+                "Current number of child jails of the named jail",
+                JailLabelSet,
+                registry,
+            ),
+
+            children_max: register_gauge_with_registry!(
+                "children_max",
+                "Maximum number of child jails the named jail may create",
+                JailLabelSet,
+                registry,
+            ),
+
+            parent_info: register_info_with_registry!(
+                "parent",
+                "The parent of the named jail",
+                Family<ParentLabels>,
+                registry,
+            ),
+
+            parent_labels: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the hierarchy time series for the given jail, with the given
+    /// `labels`.
+    pub fn update(
+        &self,
+        jail: &Jail,
+        labels: &JailLabelSet,
+        parent: Option<String>,
+    ) {
+        let children = [
+            ("children.cur", &self.children_cur),
+            ("children.max", &self.children_max),
+        ];
+
+        for (param, family) in children {
+            let value = jail.param(param)
+                .and_then(|value| value.parse::<i64>().ok());
+
+            match value {
+                Some(value) => { family.get_or_create(labels).set(value); },
+                None        => { family.remove(labels); },
+            }
+        }
+
+        let parent_labels = ParentLabels {
+            jail:   labels.clone(),
+            parent: parent.unwrap_or_default(),
+        };
+
+        let mut all_labels = self.parent_labels.lock();
+
+        if let Some(old) = all_labels.get(&jail.name) {
+            if *old != parent_labels {
+                self.parent_info.remove(old);
+            }
+        }
+
+        self.parent_info.insert(parent_labels.clone());
+        all_labels.insert(jail.name.clone(), parent_labels);
+    }
+
+    /// Removes the hierarchy time series for the jail with the given
+    /// `labels`.
+    pub fn remove(&self, labels: &JailLabelSet) {
+        self.children_cur.remove(labels);
+        self.children_max.remove(labels);
+
+        if let Some(old) = self.parent_labels.lock().remove(labels.name()) {
+            self.parent_info.remove(&old);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parent_name_ok() {
+        let names = JailNames::from([(1, "parent")]);
+
+        // Top level jail
+        let top = Jail::with_params(1, "parent", &[("parent", "0")]);
+        assert_eq!(parent_name(&top, &names), None);
+
+        // Child of a jail we can see
+        let child = Jail::with_params(2, "parent.child", &[("parent", "1")]);
+        assert_eq!(parent_name(&child, &names), Some("parent".into()));
+
+        // Child of a jail we can't see, falls back to the jail name
+        let child = Jail::with_params(4, "other.child", &[("parent", "3")]);
+        assert_eq!(parent_name(&child, &names), Some("other".into()));
+    }
+
+    #[test]
+    fn hierarchy_metrics_update_and_remove() {
+        let mut registry = Registry::default();
+        let metrics = HierarchyMetrics::new(&mut registry);
+        let labels = &JailLabelSet::from("parent.child");
+
+        let child = Jail::with_params(2, "parent.child", &[
+            ("parent", "1"),
+            ("children.cur", "1"),
+            ("children.max", "4"),
+        ]);
+        metrics.update(&child, labels, Some("parent".into()));
+
+        assert_eq!(metrics.children_cur.get_or_create(labels).get(), 1);
+        assert_eq!(metrics.children_max.get_or_create(labels).get(), 4);
+        assert_eq!(
+            metrics.parent_labels.lock()["parent.child"].parent,
+            "parent",
+        );
+
+        metrics.remove(labels);
+        assert!(metrics.parent_labels.lock().is_empty());
+    }
+}
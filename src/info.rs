@@ -0,0 +1,123 @@
+// info: A family of OpenMetrics Info metrics.
+//       prometheus_client's Family can't hold Info metrics, as an Info metric
+//       needs its label set at construction time.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use parking_lot::RwLock;
+use prometheus_client::encoding::{
+    EncodeLabelSet,
+    EncodeMetric,
+    MetricEncoder,
+};
+use prometheus_client::metrics::{
+    MetricType,
+    TypedMetric,
+};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::Arc;
+
+/// A set of Info metrics sharing a name, each with its own label set.
+///
+/// Clones share the same underlying label sets, in the same way that a
+/// `Family` does. The label sets are kept in order, so that they're encoded
+/// in the same order on every scrape.
+#[derive(Debug)]
+pub struct InfoFamily<S> {
+    label_sets: Arc<RwLock<BTreeSet<S>>>,
+}
+
+impl<S> Clone for InfoFamily<S> {
+    fn clone(&self) -> Self {
+        Self {
+            label_sets: Arc::clone(&self.label_sets),
+        }
+    }
+}
+
+impl<S> Default for InfoFamily<S> {
+    fn default() -> Self {
+        Self {
+            label_sets: Arc::new(RwLock::new(BTreeSet::new())),
+        }
+    }
+}
+
+impl<S: Clone + Ord> InfoFamily<S> {
+    /// Adds an Info metric with the given label set to the family.
+    pub fn insert(&self, labels: S) {
+        self.label_sets.write().insert(labels);
+    }
+
+    /// Removes the Info metric with the given label set from the family.
+    pub fn remove(&self, labels: &S) -> bool {
+        self.label_sets.write().remove(labels)
+    }
+
+    /// Replaces all of the Info metrics in the family with the given label
+    /// sets.
+    #[cfg(feature = "jail_manager")]
+    pub fn replace<I>(&self, labels: I)
+    where
+        I: IntoIterator<Item = S>,
+    {
+        *self.label_sets.write() = labels.into_iter().collect();
+    }
+}
+
+impl<S> TypedMetric for InfoFamily<S> {
+    const TYPE: MetricType = MetricType::Info;
+}
+
+impl<S> EncodeMetric for InfoFamily<S>
+where
+    S: Clone + Ord + EncodeLabelSet,
+{
+    fn encode(&self, mut encoder: MetricEncoder) -> Result<(), fmt::Error> {
+        for labels in self.label_sets.read().iter() {
+            encoder.encode_info(labels)?;
+        }
+
+        Ok(())
+    }
+
+    fn metric_type(&self) -> MetricType {
+        Self::TYPE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use prometheus_client::encoding::text::encode;
+    use prometheus_client::registry::Registry;
+
+    #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, EncodeLabelSet)]
+    struct TestLabels {
+        name: String,
+    }
+
+    #[test]
+    fn info_family_encode() {
+        let mut registry = Registry::default();
+        let family = InfoFamily::<TestLabels>::default();
+        registry.register("test", "Test info", family.clone());
+
+        family.insert(TestLabels { name: "c".into() });
+        family.insert(TestLabels { name: "a".into() });
+        family.insert(TestLabels { name: "b".into() });
+        assert!(family.remove(&TestLabels { name: "b".into() }));
+
+        let mut output = String::new();
+        encode(&mut output, &registry).unwrap();
+
+        let ok = "# HELP test Test info.\n\
+                  # TYPE test info\n\
+                  test_info{name=\"a\"} 1\n\
+                  test_info{name=\"c\"} 1\n\
+                  # EOF\n";
+
+        assert_eq!(output, ok);
+    }
+}
@@ -0,0 +1,400 @@
+// jailconf: This module reads jail.conf(5), allowing us to find jails that
+//           are configured but not running.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::errors::ExporterError;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use tracing::debug;
+
+mod parser;
+
+use parser::{
+    Op,
+    Param,
+    Part,
+    Statement,
+    Value,
+};
+
+/// The default location of jail.conf(5).
+pub const DEFAULT_JAIL_CONF: &str = "/etc/jail.conf";
+
+// Limits protecting us from include loops and self referencing variables.
+const MAX_INCLUDE_DEPTH: usize = 16;
+const MAX_EXPANSION_DEPTH: usize = 16;
+
+/// Resolved parameters of a configured jail, keyed on parameter name.
+pub type ConfParams = BTreeMap<String, Vec<String>>;
+
+/// The jails configured in jail.conf(5).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct JailConf {
+    jails: BTreeMap<String, ConfParams>,
+}
+
+impl JailConf {
+    /// Loads the jail.conf(5) at `path` along with any `.conf` files in the
+    /// `.d` directory next to it, in the same way as `/etc/jail.conf` and
+    /// `/etc/jail.conf.d/*.conf`.
+    ///
+    /// A missing configuration isn't an error, it simply has no jails.
+    pub fn load(path: &Path) -> Result<Self, ExporterError> {
+        debug!("Loading jail configuration from: {}", path.display());
+
+        let mut statements = Vec::new();
+
+        if path.exists() {
+            read_file(path, 0, &mut statements)?;
+        }
+
+        let mut conf_d = path.as_os_str().to_owned();
+        conf_d.push(".d");
+
+        let pattern = Path::new(&conf_d).join("*.conf");
+
+        for file in glob(&pattern)? {
+            read_file(&file, 0, &mut statements)?;
+        }
+
+        Self::from_statements(&statements)
+    }
+
+    /// Returns the names of the configured jails along with their resolved
+    /// parameters.
+    pub fn jails(&self) -> impl Iterator<Item = (&str, &ConfParams)> {
+        self.jails.iter().map(|(name, params)| (name.as_str(), params))
+    }
+
+    // Resolves the parameters of each named jail from the parsed statements.
+    fn from_statements(statements: &[Statement]) -> Result<Self, ExporterError> {
+        // Parameters outside of any block and those within a "*" block apply
+        // to every jail. Blocks with a wildcard in their name apply to the
+        // jails matching them.
+        let mut global: Vec<&Param> = Vec::new();
+        let mut blocks: Vec<(&str, &[Param])> = Vec::new();
+
+        for statement in statements {
+            match statement {
+                Statement::Param(param) => global.push(param),
+                Statement::Block { name, params } if name == "*" => {
+                    global.extend(params);
+                },
+                Statement::Block { name, params } => {
+                    blocks.push((name, params));
+                },
+                // Includes are handled while reading files.
+                Statement::Include(_) => {},
+            }
+        }
+
+        let mut jails = BTreeMap::new();
+
+        for (name, _) in &blocks {
+            if name.contains('*') || jails.contains_key(*name) {
+                continue;
+            }
+
+            let wildcards = blocks.iter()
+                .filter(|(pattern, _)| {
+                    pattern.contains('*') && wildcard_match(pattern, name)
+                })
+                .flat_map(|(_, params)| params.iter());
+
+            let own = blocks.iter()
+                .filter(|(block, _)| block == name)
+                .flat_map(|(_, params)| params.iter());
+
+            let params = global.iter()
+                .copied()
+                .chain(wildcards)
+                .chain(own);
+
+            jails.insert((*name).to_string(), resolve(name, params)?);
+        }
+
+        Ok(Self {
+            jails: jails,
+        })
+    }
+}
+
+// Applies the given parameters in order and expands any variables in them.
+fn resolve<'a, I>(name: &str, params: I) -> Result<ConfParams, ExporterError>
+where
+    I: Iterator<Item = &'a Param>,
+{
+    let mut raw: BTreeMap<&str, Vec<&Value>> = BTreeMap::new();
+
+    for param in params {
+        let values = raw.entry(&param.name).or_default();
+
+        if param.op == Op::Assign {
+            values.clear();
+        }
+
+        values.extend(&param.values);
+    }
+
+    // The jail name is always available for expansion.
+    let jail_name = vec![Part::Literal(name.to_string())];
+    raw.insert("name", vec![&jail_name]);
+
+    raw.iter()
+        .filter(|(param, _)| !param.starts_with('$'))
+        .map(|(param, values)| {
+            let values = values.iter()
+                .map(|value| expand(name, value, &raw, 0))
+                .collect::<Result<_, _>>()?;
+
+            Ok(((*param).to_string(), values))
+        })
+        .collect()
+}
+
+// Expands the variables within a value. Variables refer to either a $variable
+// or, failing that, a parameter of the jail.
+fn expand(
+    name: &str,
+    value: &Value,
+    raw: &BTreeMap<&str, Vec<&Value>>,
+    depth: usize,
+) -> Result<String, ExporterError> {
+    if depth > MAX_EXPANSION_DEPTH {
+        return Err(ExporterError::JailConfError(format!(
+            "jail {name}: variable expansion too deep",
+        )));
+    }
+
+    let mut expanded = String::new();
+
+    for part in value {
+        match part {
+            Part::Literal(s) => expanded.push_str(s),
+            Part::Variable(var) => {
+                let values = raw.get(format!("${var}").as_str())
+                    .or_else(|| raw.get(var.as_str()))
+                    .ok_or_else(|| {
+                        ExporterError::JailConfError(format!(
+                            "jail {name}: undefined variable: {var}",
+                        ))
+                    })?;
+
+                let values = values.iter()
+                    .map(|value| expand(name, value, raw, depth + 1))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                expanded.push_str(&values.join(","));
+            },
+        }
+    }
+
+    Ok(expanded)
+}
+
+// Reads and parses a file, following any includes within it.
+fn read_file(
+    path: &Path,
+    depth: usize,
+    statements: &mut Vec<Statement>,
+) -> Result<(), ExporterError> {
+    debug!("Reading jail configuration: {}", path.display());
+
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(ExporterError::JailConfError(format!(
+            "{}: includes nested too deeply",
+            path.display(),
+        )));
+    }
+
+    let input = fs::read_to_string(path)?;
+
+    let parsed = parser::parse(&input).map_err(|e| {
+        ExporterError::JailConfError(format!("{}: {e}", path.display()))
+    })?;
+
+    for statement in parsed {
+        let Statement::Include(include) = statement else {
+            statements.push(statement);
+            continue;
+        };
+
+        let include = match include.as_slice() {
+            [Part::Literal(include)] => PathBuf::from(include),
+            _ => {
+                return Err(ExporterError::JailConfError(format!(
+                    "{}: variables are not supported in .include",
+                    path.display(),
+                )));
+            },
+        };
+
+        // Relative includes are relative to the including file.
+        let include = match path.parent() {
+            Some(parent) => parent.join(include),
+            None         => include,
+        };
+
+        for file in glob(&include)? {
+            read_file(&file, depth + 1, statements)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Expands wildcards in the final component of a path, returning the matching
+// files in sorted order. A path without wildcards is returned as is.
+fn glob(path: &Path) -> Result<Vec<PathBuf>, ExporterError> {
+    let pattern = path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    if !pattern.contains(['*', '?']) {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    // No directory means no files to include.
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(Vec::new());
+    };
+
+    let mut files = Vec::new();
+
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+
+        if entry.path().is_file() && wildcard_match(&pattern, &name.to_string_lossy()) {
+            files.push(entry.path());
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+// Matches a name against a pattern where '*' matches any number of
+// characters and '?' matches a single character.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            },
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            },
+            _ => {
+                let Some((star, matched)) = backtrack else {
+                    return false;
+                };
+
+                p = star + 1;
+                n = matched + 1;
+                backtrack = Some((star, matched + 1));
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn params(conf: &JailConf, name: &str, param: &str) -> Vec<String> {
+        conf.jails[name][param].clone()
+    }
+
+    #[test]
+    fn jail_conf_load() {
+        let conf = JailConf::load(Path::new("test-data/jailconf/jail.conf"))
+            .unwrap();
+
+        let names: Vec<_> = conf.jails().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["db", "mail", "www", "www.child"]);
+
+        // Variables, the wildcard block and the jail name are expanded.
+        assert_eq!(params(&conf, "www", "path"), vec!["/jails/www"]);
+        assert_eq!(
+            params(&conf, "www", "host.hostname"),
+            vec!["www.example.com"],
+        );
+
+        // += appends to the value from the wildcard block.
+        assert_eq!(
+            params(&conf, "www", "ip4.addr"),
+            vec!["192.0.2.1", "192.0.2.2"],
+        );
+
+        // Blocks matching a wildcard pattern apply to matching jails only.
+        assert_eq!(params(&conf, "www.child", "persist"), Vec::<String>::new());
+        assert!(!conf.jails["www"].contains_key("persist"));
+
+        // Jails from includes and the .d directory.
+        assert_eq!(params(&conf, "db", "path"), vec!["/jails/db"]);
+        assert_eq!(params(&conf, "mail", "path"), vec!["/data/mail"]);
+
+        // Variables aren't exported as parameters.
+        assert!(!conf.jails["www"].contains_key("$domain"));
+    }
+
+    #[test]
+    fn jail_conf_load_missing() {
+        let conf = JailConf::load(Path::new("test-data/jailconf/missing.conf"))
+            .unwrap();
+
+        assert_eq!(conf, JailConf::default());
+    }
+
+    #[test]
+    fn jail_conf_load_invalid() {
+        let res = JailConf::load(Path::new("test-data/jailconf/invalid.conf"));
+
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "jail.conf error: test-data/jailconf/invalid.conf: line 3: \
+             unterminated jail block",
+        );
+    }
+
+    #[test]
+    fn jail_conf_undefined_variable() {
+        let statements = parser::parse("www { path = $nope; }").unwrap();
+        let res = JailConf::from_statements(&statements);
+
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "jail.conf error: jail www: undefined variable: nope",
+        );
+    }
+
+    #[test]
+    fn wildcard_match_ok() {
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("*.conf", "www.conf"));
+        assert!(wildcard_match("www.*", "www.child"));
+        assert!(wildcard_match("w?w", "www"));
+        assert!(wildcard_match("*a*b", "xaxxab"));
+        assert!(!wildcard_match("*.conf", "www.conf.bak"));
+        assert!(!wildcard_match("www.*", "www"));
+    }
+}
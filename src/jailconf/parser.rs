@@ -0,0 +1,552 @@
+// parser: Tokenizer and parser for the jail.conf(5) grammar.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+
+/// A piece of a value, either literal text or a reference to a variable or
+/// parameter that is expanded later.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Part {
+    Literal(String),
+    Variable(String),
+}
+
+/// A single value, as it appears in the configuration.
+pub type Value = Vec<Part>;
+
+/// How a parameter is being set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    // name = value;
+    Assign,
+
+    // name += value;
+    Append,
+}
+
+/// A parameter or variable being set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Param {
+    pub name:   String,
+    pub op:     Op,
+    pub values: Vec<Value>,
+}
+
+/// A top level statement in a jail.conf(5) file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Statement {
+    // .include "path";
+    Include(Value),
+
+    // name { params }
+    Block {
+        name:   String,
+        params: Vec<Param>,
+    },
+
+    // A parameter set outside of a jail block, applying to all jails.
+    Param(Param),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Word(Value),
+    Open,
+    Close,
+    Semicolon,
+    Comma,
+    Assign,
+    Append,
+}
+
+// Characters that end an unquoted word.
+const SPECIAL: &[char] = &['{', '}', ';', ',', '=', '"', '\'', '#'];
+
+struct Lexer {
+    chars: Vec<char>,
+    pos:   usize,
+    line:  usize,
+}
+
+impl Lexer {
+    fn new(input: &str) -> Self {
+        Self {
+            chars: input.chars().collect(),
+            pos:   0,
+            line:  1,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_next(&self) -> Option<char> {
+        self.chars.get(self.pos + 1).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+
+        if c == '\n' {
+            self.line += 1;
+        }
+
+        Some(c)
+    }
+
+    fn error(&self, msg: &str) -> String {
+        format!("line {}: {msg}", self.line)
+    }
+
+    // Skips whitespace and all three comment styles.
+    fn skip_whitespace(&mut self) -> Result<(), String> {
+        loop {
+            match (self.peek(), self.peek_next()) {
+                (Some(c), _) if c.is_whitespace() => {
+                    self.bump();
+                },
+                (Some('#'), _) | (Some('/'), Some('/')) => {
+                    while !matches!(self.bump(), Some('\n') | None) {}
+                },
+                (Some('/'), Some('*')) => {
+                    self.pos += 2;
+
+                    loop {
+                        match (self.bump(), self.peek()) {
+                            (Some('*'), Some('/')) => {
+                                self.bump();
+                                break;
+                            },
+                            (None, _) => {
+                                return Err(self.error("unterminated comment"));
+                            },
+                            _ => {},
+                        }
+                    }
+                },
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    // Reads a variable reference following a '$', in either the $name or
+    // ${name} form.
+    fn variable(&mut self) -> Result<String, String> {
+        let mut name = String::new();
+
+        if self.peek() == Some('{') {
+            self.bump();
+
+            loop {
+                match self.bump() {
+                    Some('}') => break,
+                    Some(c)   => name.push(c),
+                    None      => {
+                        return Err(self.error("unterminated variable"));
+                    },
+                }
+            }
+        }
+        else {
+            while let Some(c) = self.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+
+                name.push(c);
+                self.bump();
+            }
+        }
+
+        if name.is_empty() {
+            return Err(self.error("empty variable name"));
+        }
+
+        Ok(name)
+    }
+
+    // Reads a double or single quoted string. Variables are only expanded
+    // within double quotes.
+    fn quoted(&mut self, quote: char, parts: &mut Value) -> Result<(), String> {
+        let mut literal = String::new();
+
+        loop {
+            match self.bump() {
+                None => return Err(self.error("unterminated string")),
+                Some(c) if c == quote => break,
+                Some('\\') if quote == '"' => {
+                    match self.bump() {
+                        Some('n')  => literal.push('\n'),
+                        Some('t')  => literal.push('\t'),
+                        Some('\n') => {},
+                        Some(c)    => literal.push(c),
+                        None       => {
+                            return Err(self.error("unterminated string"));
+                        },
+                    }
+                },
+                Some('$') if quote == '"' => {
+                    push_literal(parts, &mut literal);
+                    parts.push(Part::Variable(self.variable()?));
+                },
+                Some(c) => literal.push(c),
+            }
+        }
+
+        // An empty string still needs to be a value.
+        if parts.is_empty() || !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(())
+    }
+
+    // Reads an unquoted word.
+    fn unquoted(&mut self, parts: &mut Value) -> Result<(), String> {
+        let mut literal = String::new();
+
+        while let Some(c) = self.peek() {
+            let ends = c.is_whitespace()
+                || SPECIAL.contains(&c)
+                || (c == '+' && self.peek_next() == Some('='));
+
+            if ends {
+                break;
+            }
+
+            self.bump();
+
+            match c {
+                '\\' => {
+                    if let Some(c) = self.bump() {
+                        literal.push(c);
+                    }
+                },
+                '$' => {
+                    push_literal(parts, &mut literal);
+                    parts.push(Part::Variable(self.variable()?));
+                },
+                c => literal.push(c),
+            }
+        }
+
+        push_literal(parts, &mut literal);
+
+        Ok(())
+    }
+
+    fn next_token(&mut self) -> Result<Option<(Token, usize)>, String> {
+        self.skip_whitespace()?;
+
+        let line = self.line;
+
+        let token = match self.peek() {
+            None      => return Ok(None),
+            Some('{') => Token::Open,
+            Some('}') => Token::Close,
+            Some(';') => Token::Semicolon,
+            Some(',') => Token::Comma,
+            Some('=') => Token::Assign,
+            Some('+') if self.peek_next() == Some('=') => {
+                self.pos += 1;
+                Token::Append
+            },
+            Some(quote @ ('"' | '\'')) => {
+                self.bump();
+
+                let mut parts = Value::new();
+                self.quoted(quote, &mut parts)?;
+
+                return Ok(Some((Token::Word(parts), line)));
+            },
+            Some(_) => {
+                let mut parts = Value::new();
+                self.unquoted(&mut parts)?;
+
+                return Ok(Some((Token::Word(parts), line)));
+            },
+        };
+
+        self.bump();
+
+        Ok(Some((token, line)))
+    }
+}
+
+// Moves any pending literal text into the parts of a value.
+fn push_literal(parts: &mut Value, literal: &mut String) {
+    if !literal.is_empty() {
+        parts.push(Part::Literal(std::mem::take(literal)));
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos:    usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or_else(|| self.tokens.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    fn error(&self, msg: &str) -> String {
+        format!("line {}: {msg}", self.line())
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(token, _)| token.clone());
+        self.pos += 1;
+
+        token
+    }
+
+    fn word(&mut self) -> Result<Value, String> {
+        match self.peek().cloned() {
+            Some(Token::Word(value)) => {
+                self.pos += 1;
+                Ok(value)
+            },
+            Some(token) => Err(self.error(&format!("unexpected {token:?}"))),
+            None        => Err(self.error("unexpected end of file")),
+        }
+    }
+
+    // Names of jails and parameters must be literal, with the exception of
+    // variable definitions which are a lone $name.
+    fn name(&self, value: Value) -> Result<String, String> {
+        match value.as_slice() {
+            [Part::Literal(name)]  => Ok(name.clone()),
+            [Part::Variable(name)] => Ok(format!("${name}")),
+            _ => Err(self.error("invalid name")),
+        }
+    }
+
+    fn statements(&mut self) -> Result<Vec<Statement>, String> {
+        let mut statements = Vec::new();
+
+        while self.peek().is_some() {
+            let word = self.word()?;
+
+            if self.peek() == Some(&Token::Open) {
+                self.next();
+
+                let name = self.name(word)?;
+                let mut params = Vec::new();
+
+                loop {
+                    match self.peek() {
+                        Some(Token::Close) => {
+                            self.next();
+                            break;
+                        },
+                        Some(_) => {
+                            let word = self.word()?;
+                            let name = self.name(word)?;
+                            params.push(self.param(name)?);
+                        },
+                        None => {
+                            return Err(self.error("unterminated jail block"));
+                        },
+                    }
+                }
+
+                statements.push(Statement::Block { name, params });
+
+                continue;
+            }
+
+            let name = self.name(word)?;
+
+            if name == ".include" {
+                let path = self.word()?;
+                self.end_of_param()?;
+
+                statements.push(Statement::Include(path));
+
+                continue;
+            }
+
+            statements.push(Statement::Param(self.param(name)?));
+        }
+
+        Ok(statements)
+    }
+
+    // Parses the remainder of a parameter, following its name.
+    fn param(&mut self, name: String) -> Result<Param, String> {
+        let op = match self.peek() {
+            Some(Token::Assign) => Op::Assign,
+            Some(Token::Append) => Op::Append,
+
+            // Boolean parameters may be given with no value.
+            _ => {
+                self.end_of_param()?;
+
+                return Ok(Param {
+                    name:   name,
+                    op:     Op::Assign,
+                    values: Vec::new(),
+                });
+            },
+        };
+
+        self.next();
+
+        let mut values = vec![self.word()?];
+
+        while self.peek() == Some(&Token::Comma) {
+            self.next();
+            values.push(self.word()?);
+        }
+
+        self.end_of_param()?;
+
+        Ok(Param {
+            name:   name,
+            op:     op,
+            values: values,
+        })
+    }
+
+    // Parameters end with a semicolon, which may be left off of the last
+    // parameter in a block.
+    fn end_of_param(&mut self) -> Result<(), String> {
+        match self.peek() {
+            Some(Token::Semicolon) => {
+                self.next();
+                Ok(())
+            },
+            Some(Token::Close) => Ok(()),
+            Some(token) => Err(self.error(&format!("unexpected {token:?}"))),
+            None        => Err(self.error("missing ';'")),
+        }
+    }
+}
+
+/// Parses the contents of a jail.conf(5) file into a list of statements.
+pub fn parse(input: &str) -> Result<Vec<Statement>, String> {
+    let mut lexer = Lexer::new(input);
+    let mut tokens = Vec::new();
+
+    while let Some(token) = lexer.next_token()? {
+        tokens.push(token);
+    }
+
+    let mut parser = Parser {
+        tokens: tokens,
+        pos:    0,
+    };
+
+    parser.statements()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    fn literal(s: &str) -> Value {
+        vec![Part::Literal(s.into())]
+    }
+
+    #[test]
+    fn parse_ok() {
+        let input = indoc!(r#"
+            # Comment
+            $domain = "example.com"; // Another comment
+            /* A
+               block comment */
+            persist;
+            www {
+                host.hostname = "www.${domain}";
+                ip4.addr += 192.0.2.1, '192.0.2.2';
+                path = /jails/$name
+            }
+            .include "/etc/jail.d/*.conf";
+        "#);
+
+        let ok = vec![
+            Statement::Param(Param {
+                name:   "$domain".into(),
+                op:     Op::Assign,
+                values: vec![literal("example.com")],
+            }),
+            Statement::Param(Param {
+                name:   "persist".into(),
+                op:     Op::Assign,
+                values: vec![],
+            }),
+            Statement::Block {
+                name:   "www".into(),
+                params: vec![
+                    Param {
+                        name:   "host.hostname".into(),
+                        op:     Op::Assign,
+                        values: vec![vec![
+                            Part::Literal("www.".into()),
+                            Part::Variable("domain".into()),
+                        ]],
+                    },
+                    Param {
+                        name:   "ip4.addr".into(),
+                        op:     Op::Append,
+                        values: vec![literal("192.0.2.1"), literal("192.0.2.2")],
+                    },
+                    Param {
+                        name:   "path".into(),
+                        op:     Op::Assign,
+                        values: vec![vec![
+                            Part::Literal("/jails/".into()),
+                            Part::Variable("name".into()),
+                        ]],
+                    },
+                ],
+            },
+            Statement::Include(literal("/etc/jail.d/*.conf")),
+        ];
+
+        assert_eq!(parse(input).unwrap(), ok);
+    }
+
+    #[test]
+    fn parse_single_quotes_do_not_expand() {
+        let statements = parse("foo = '$bar';").unwrap();
+
+        let ok = vec![
+            Statement::Param(Param {
+                name:   "foo".into(),
+                op:     Op::Assign,
+                values: vec![literal("$bar")],
+            }),
+        ];
+
+        assert_eq!(statements, ok);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let tests = [
+            ("www {\n    persist;\n", "line 2: unterminated jail block"),
+            ("foo = \"bar;", "line 1: unterminated string"),
+            ("foo = bar", "line 1: missing ';'"),
+            ("/* foo", "line 1: unterminated comment"),
+            ("\n\n= bar;", "line 3: unexpected Assign"),
+            ("foo =", "line 1: unexpected end of file"),
+            ("www { foo = {", "line 1: unexpected Open"),
+        ];
+
+        for (input, ok) in tests {
+            assert_eq!(parse(input).unwrap_err(), ok);
+        }
+    }
+}
@@ -0,0 +1,501 @@
+// jailsource: This module provides the sources of jail data that the exporter
+//             collects metrics from.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::errors::ExporterError;
+use crate::rctltypes::Resource;
+use std::collections::HashMap;
+use std::fmt;
+
+#[cfg(target_os = "freebsd")]
+use jail::param::Value;
+
+#[cfg(target_os = "freebsd")]
+use jail::RunningJail;
+
+#[cfg(target_os = "freebsd")]
+use rctl::{
+    Filter,
+    Subject,
+};
+
+#[cfg(target_os = "freebsd")]
+use tracing::debug;
+
+#[cfg(test)]
+use parking_lot::Mutex;
+
+#[cfg(test)]
+use std::sync::Arc;
+
+/// Type alias for our resource usage metrics coming from the rctl library.
+pub type Rusage = HashMap<Resource, usize>;
+
+/// Type alias for jail parameters, keyed on the parameter name with the
+/// values rendered as strings.
+pub type JailParams = HashMap<String, String>;
+
+/// A jail discovered by a `JailSource`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Jail {
+    /// The jail ID.
+    pub jid: i32,
+
+    /// The jail name.
+    pub name: String,
+
+    /// Parameters of the jail, such as `path` and `host.hostname`.
+    pub params: JailParams,
+}
+
+impl Jail {
+    /// Returns a new `Jail` with the given `jid` and `name` and no parameters.
+    #[cfg(test)]
+    pub fn new(jid: i32, name: &str) -> Self {
+        Self {
+            jid:    jid,
+            name:   name.to_string(),
+            params: JailParams::new(),
+        }
+    }
+
+    /// Returns the value of the named jail parameter, if it was found.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(String::as_str)
+    }
+}
+
+/// The stage of collecting the jails, reported when that stage fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Reading the name and parameters of the jail.
+    Jail,
+
+    /// Reading jail.conf(5), which isn't specific to a jail.
+    JailConf,
+
+    /// Reading the resource usage of the jail.
+    Rusage,
+
+    /// Reading the rctl(8) rules applying to the jail.
+    Rules,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stage = match self {
+            Self::Jail     => "jail",
+            Self::JailConf => "jail_conf",
+            Self::Rusage   => "rusage",
+            Self::Rules    => "rules",
+        };
+
+        write!(f, "{stage}")
+    }
+}
+
+/// A running jail that couldn't be read, such as one that exited while it
+/// was being read.
+#[derive(Debug)]
+pub struct JailFailure {
+    /// The jail name, or the jail ID if the name couldn't be read.
+    pub name: String,
+
+    /// The error reading the jail.
+    pub error: ExporterError,
+}
+
+/// A source of jails and their resource usage.
+///
+/// The exporter only ever talks to the OS through this trait, allowing
+/// alternative sources to be plugged in for testing.
+pub trait JailSource {
+    /// Returns the jails that are currently running, along with those that
+    /// couldn't be read.
+    fn jails(&self) -> Result<Vec<Result<Jail, JailFailure>>, ExporterError>;
+
+    /// Returns the resource usage of the given jail.
+    fn rusage(&self, jail: &Jail) -> Result<Rusage, ExporterError>;
+
+    /// Returns the rctl(8) rules applying to the given jail, in the rctl(8)
+    /// rule text format.
+    fn rules(&self, jail: &Jail) -> Result<Vec<String>, ExporterError>;
+}
+
+/// The `JailSource` of the running system.
+#[cfg(target_os = "freebsd")]
+pub type SystemJailSource = RctlJailSource;
+
+/// The `JailSource` of the running system. Jails only exist on FreeBSD, so
+/// elsewhere this fails to read any.
+#[cfg(not(target_os = "freebsd"))]
+pub type SystemJailSource = UnsupportedJailSource;
+
+/// A `JailSource` backed by the running system via the `jail` and `rctl`
+/// crates.
+#[cfg(target_os = "freebsd")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RctlJailSource;
+
+#[cfg(target_os = "freebsd")]
+impl RctlJailSource {
+    // Gathers the parameters of a running jail.
+    // The jail crate handles some parameters separately from the others, so
+    // we add those back in here.
+    // The parent and children.cur parameters are also left out by the jail
+    // crate, but we need these to describe the jail hierarchy.
+    fn params(jail: &RunningJail) -> Result<JailParams, ExporterError> {
+        let mut params: JailParams = jail.params()?
+            .into_iter()
+            .map(|(name, value)| (name, param_to_string(value)))
+            .collect();
+
+        let path = jail.path()?;
+        params.insert("path".into(), path.to_string_lossy().into_owned());
+
+        let (ip4, ip6): (Vec<_>, Vec<_>) = jail.ips()?
+            .into_iter()
+            .partition(std::net::IpAddr::is_ipv4);
+
+        params.insert("ip4.addr".into(), join_addrs(&ip4));
+        params.insert("ip6.addr".into(), join_addrs(&ip6));
+
+        for name in ["parent", "children.cur"] {
+            let value = jail.param(name)?;
+            params.insert(name.into(), param_to_string(value));
+        }
+
+        Ok(params)
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+impl JailSource for RctlJailSource {
+    fn jails(&self) -> Result<Vec<Result<Jail, JailFailure>>, ExporterError> {
+        debug!("Enumerating running jails");
+
+        // Jails may exit while we're reading them, so failing to read one
+        // doesn't stop us from reading the others.
+        let jails = RunningJail::all()
+            .map(|jail| {
+                let name = jail.name().map_err(|e| JailFailure {
+                    name:  jail.jid.to_string(),
+                    error: e.into(),
+                })?;
+
+                let params = Self::params(&jail).map_err(|e| JailFailure {
+                    name:  name.clone(),
+                    error: e,
+                })?;
+
+                Ok(Jail {
+                    jid:    jail.jid,
+                    name:   name,
+                    params: params,
+                })
+            })
+            .collect();
+
+        Ok(jails)
+    }
+
+    fn rusage(&self, jail: &Jail) -> Result<Rusage, ExporterError> {
+        let running = RunningJail::from_jid_unchecked(jail.jid);
+        let rusage = running.racct_statistics()?;
+
+        Ok(rusage)
+    }
+
+    fn rules(&self, jail: &Jail) -> Result<Vec<String>, ExporterError> {
+        let subject = Subject::jail_name(jail.name.as_str());
+        let rules = Filter::new().subject(&subject).rules()?;
+
+        let rules = rules.into_iter()
+            .map(|rule| String::from(&rule))
+            .collect();
+
+        Ok(rules)
+    }
+}
+
+/// A `JailSource` for platforms without jails, which fails to read them.
+#[cfg(not(target_os = "freebsd"))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UnsupportedJailSource;
+
+#[cfg(not(target_os = "freebsd"))]
+impl UnsupportedJailSource {
+    fn unsupported() -> ExporterError {
+        std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "jails are only supported on FreeBSD",
+        ).into()
+    }
+}
+
+#[cfg(not(target_os = "freebsd"))]
+impl JailSource for UnsupportedJailSource {
+    fn jails(&self) -> Result<Vec<Result<Jail, JailFailure>>, ExporterError> {
+        Err(Self::unsupported())
+    }
+
+    fn rusage(&self, _jail: &Jail) -> Result<Rusage, ExporterError> {
+        Err(Self::unsupported())
+    }
+
+    fn rules(&self, _jail: &Jail) -> Result<Vec<String>, ExporterError> {
+        Err(Self::unsupported())
+    }
+}
+
+// Renders a list of addresses in the same comma separated format that
+// jail(8) uses.
+#[cfg(target_os = "freebsd")]
+fn join_addrs(addrs: &[std::net::IpAddr]) -> String {
+    addrs.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// Renders a jail parameter value as a string.
+#[cfg(target_os = "freebsd")]
+fn param_to_string(value: Value) -> String {
+    match value {
+        Value::Int(v)    => v.to_string(),
+        Value::String(v) => v,
+        Value::S64(v)    => v.to_string(),
+        Value::Uint(v)   => v.to_string(),
+        Value::Long(v)   => v.to_string(),
+        Value::Ulong(v)  => v.to_string(),
+        Value::U64(v)    => v.to_string(),
+        Value::U8(v)     => v.to_string(),
+        Value::U16(v)    => v.to_string(),
+        Value::S8(v)     => v.to_string(),
+        Value::S16(v)    => v.to_string(),
+        Value::S32(v)    => v.to_string(),
+        Value::U32(v)    => v.to_string(),
+        Value::Ipv4Addrs(addrs) => {
+            let addrs: Vec<_> = addrs.into_iter().map(Into::into).collect();
+            join_addrs(&addrs)
+        },
+        Value::Ipv6Addrs(addrs) => {
+            let addrs: Vec<_> = addrs.into_iter().map(Into::into).collect();
+            join_addrs(&addrs)
+        },
+    }
+}
+
+#[cfg(test)]
+impl Jail {
+    /// Returns a new `Jail` with the given `jid`, `name` and parameters.
+    pub fn with_params(jid: i32, name: &str, params: &[(&str, &str)]) -> Self {
+        let params = params.iter()
+            .map(|(param, value)| (param.to_string(), value.to_string()))
+            .collect();
+
+        Self {
+            jid:    jid,
+            name:   name.to_string(),
+            params: params,
+        }
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, Default)]
+struct FixtureState {
+    fail_all: bool,
+    failures: HashMap<String, Stage>,
+    jails:    Vec<Jail>,
+    rules:    HashMap<String, Vec<String>>,
+    rusage:   HashMap<String, Rusage>,
+}
+
+/// An in-memory `JailSource`, used to drive the exporter during tests.
+///
+/// Clones share the same underlying state, so a test can keep a handle on the
+/// fixture and alter the jails between scrapes.
+#[cfg(test)]
+#[derive(Clone, Debug, Default)]
+pub struct FixtureJailSource {
+    state: Arc<Mutex<FixtureState>>,
+}
+
+#[cfg(test)]
+impl FixtureJailSource {
+    /// Returns a new, empty, fixture.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a jail with the given resource usage, replacing any existing jail
+    /// with the same name.
+    pub fn add_jail(&self, jail: Jail, rusage: Rusage) {
+        let mut state = self.state.lock();
+
+        state.jails.retain(|j| j.name != jail.name);
+        state.rusage.insert(jail.name.clone(), rusage);
+        state.jails.push(jail);
+    }
+
+    /// Removes the named jail.
+    pub fn remove_jail(&self, name: &str) {
+        let mut state = self.state.lock();
+
+        state.jails.retain(|j| j.name != name);
+        state.failures.remove(name);
+        state.rules.remove(name);
+        state.rusage.remove(name);
+    }
+
+    /// Makes reading the named jail fail at the given `stage`.
+    pub fn fail_jail(&self, name: &str, stage: Stage) {
+        self.state.lock().failures.insert(name.to_string(), stage);
+    }
+
+    /// Makes reading the named jail succeed again.
+    pub fn recover_jail(&self, name: &str) {
+        self.state.lock().failures.remove(name);
+    }
+
+    /// Makes listing the jails fail, failing the whole scrape.
+    pub fn fail_all(&self, fail: bool) {
+        self.state.lock().fail_all = fail;
+    }
+
+    // Returns an error if reading the named jail should fail at the given
+    // `stage`.
+    fn check(&self, name: &str, stage: Stage) -> Result<(), ExporterError> {
+        if self.state.lock().failures.get(name) == Some(&stage) {
+            let error = std::io::Error::other(format!("{name} has exited"));
+            return Err(error.into());
+        }
+
+        Ok(())
+    }
+
+    /// Sets the rctl(8) rules for the named jail.
+    pub fn set_rules(&self, name: &str, rules: &[&str]) {
+        let rules = rules.iter().map(ToString::to_string).collect();
+
+        self.state.lock().rules.insert(name.to_string(), rules);
+    }
+}
+
+#[cfg(test)]
+impl JailSource for FixtureJailSource {
+    fn jails(&self) -> Result<Vec<Result<Jail, JailFailure>>, ExporterError> {
+        let state = self.state.lock();
+        if state.fail_all {
+            let error = std::io::Error::other("jails can't be listed");
+            return Err(error.into());
+        }
+
+        let jails = state.jails.clone();
+        drop(state);
+
+        let jails = jails.into_iter()
+            .map(|jail| {
+                self.check(&jail.name, Stage::Jail).map_err(|e| JailFailure {
+                    name:  jail.name.clone(),
+                    error: e,
+                })?;
+
+                Ok(jail)
+            })
+            .collect();
+
+        Ok(jails)
+    }
+
+    fn rusage(&self, jail: &Jail) -> Result<Rusage, ExporterError> {
+        self.check(&jail.name, Stage::Rusage)?;
+
+        let rusage = self.state
+            .lock()
+            .rusage
+            .get(&jail.name)
+            .cloned()
+            .unwrap_or_default();
+
+        Ok(rusage)
+    }
+
+    fn rules(&self, jail: &Jail) -> Result<Vec<String>, ExporterError> {
+        self.check(&jail.name, Stage::Rules)?;
+
+        let rules = self.state
+            .lock()
+            .rules
+            .get(&jail.name)
+            .cloned()
+            .unwrap_or_default();
+
+        Ok(rules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    // Returns the jails of the source, all of which must have been read.
+    fn read_jails(source: &FixtureJailSource) -> Vec<Jail> {
+        source.jails()
+            .unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn fixture_add_and_remove_jail() {
+        let source = FixtureJailSource::new();
+        let handle = source.clone();
+
+        let rusage = Rusage::from([(Resource::MemoryUse, 1024)]);
+        handle.add_jail(Jail::new(1, "test_a"), rusage.clone());
+        handle.add_jail(Jail::new(2, "test_b"), Rusage::new());
+
+        let jails = read_jails(&source);
+        assert_eq!(jails.len(), 2);
+        assert_eq!(source.rusage(&jails[0]).unwrap(), rusage);
+
+        handle.remove_jail("test_a");
+
+        let jails = read_jails(&source);
+        assert_eq!(jails, vec![Jail::new(2, "test_b")]);
+    }
+
+    #[test]
+    fn fixture_fail_jail() {
+        let source = FixtureJailSource::new();
+        source.add_jail(Jail::new(1, "test_a"), Rusage::new());
+        source.add_jail(Jail::new(2, "test_b"), Rusage::new());
+        source.fail_jail("test_a", Stage::Jail);
+        source.fail_jail("test_b", Stage::Rusage);
+
+        let jails = source.jails().unwrap();
+        let failure = jails[0].as_ref().unwrap_err();
+        let jail = jails[1].as_ref().unwrap();
+
+        assert_eq!(failure.name, "test_a");
+        assert!(source.rusage(jail).is_err());
+        assert!(source.rules(jail).is_ok());
+    }
+
+    #[cfg(target_os = "freebsd")]
+    #[test]
+    fn param_to_string_ok() {
+        let addrs = Value::Ipv4Addrs(vec![
+            "192.0.2.1".parse().unwrap(),
+            "192.0.2.2".parse().unwrap(),
+        ]);
+
+        assert_eq!(param_to_string(Value::Int(1)), "1");
+        assert_eq!(param_to_string(Value::String("foo".into())), "foo");
+        assert_eq!(param_to_string(addrs), "192.0.2.1,192.0.2.2");
+    }
+}
@@ -0,0 +1,186 @@
+// kernproc: This module reads the kernel process table from the kern.proc
+//           sysctl. The sysctl crate can't read it, as the kernel only
+//           estimates the size of the table, so we call sysctl(3) ourselves
+//           through a single unsafe block.
+#![deny(unsafe_code)]
+#![deny(missing_docs)]
+use libc::{
+    c_int,
+    c_long,
+    kinfo_proc,
+    time_t,
+    timeval,
+};
+use std::io;
+use std::mem::{
+    offset_of,
+    size_of,
+};
+use std::ptr;
+
+// kern.proc.proc, which gives every process without its threads.
+const KERN_PROC_PROC: [c_int; 3] = [
+    libc::CTL_KERN,
+    libc::KERN_PROC,
+    libc::KERN_PROC_PROC,
+];
+
+/// The fields of a `struct kinfo_proc` that we're interested in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KinfoProc {
+    /// ID of the jail that the process is running in, 0 for the host.
+    pub jid: c_int,
+
+    /// State of the process, one of the `S*` constants in `<sys/proc.h>`.
+    pub stat: u8,
+
+    /// Flags of the thread of the process, the `TDF_*` constants in
+    /// `<sys/proc.h>`.
+    pub tdflags: c_long,
+
+    /// When the process started, in seconds since the Unix epoch.
+    pub start: time_t,
+}
+
+// Calls sysctl(3) for the value named by `mib`, reading it into `buf`, or
+// only asking for its size when there's no `buf`. Returns the size of the
+// value, or the number of bytes read.
+//
+// This is the only unsafe code in the exporter. The sysctl crate can't read
+// the process table: the kernel overestimates its size when asked, and the
+// crate treats reading less than that as an error.
+#[allow(unsafe_code)]
+fn raw_sysctl(mib: &[c_int], buf: Option<&mut [u8]>) -> io::Result<usize> {
+    let namelen = libc::c_uint::try_from(mib.len())
+        .map_err(io::Error::other)?;
+
+    let (oldp, mut len) = match buf {
+        Some(buf) => (buf.as_mut_ptr().cast(), buf.len()),
+        None      => (ptr::null_mut(), 0),
+    };
+
+    // SAFETY: mib is valid for reads of namelen ints, and newp is null so
+    // nothing is written to the sysctl. oldp is either null, in which case
+    // sysctl(3) only writes the size of the value to len, or valid for writes
+    // of len bytes, which sysctl(3) doesn't write beyond.
+    let ret = unsafe {
+        libc::sysctl(mib.as_ptr(), namelen, oldp, &mut len, ptr::null(), 0)
+    };
+
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(len)
+}
+
+// Reads the value of the sysctl named by `mib`. The value may grow between
+// asking for its size and reading it, in which case we try again.
+fn sysctl(mib: &[c_int]) -> io::Result<Vec<u8>> {
+    loop {
+        let len = raw_sysctl(mib, None)?;
+        let mut buf = vec![0_u8; len];
+
+        match raw_sysctl(mib, Some(&mut buf)) {
+            Ok(len) => {
+                buf.truncate(len);
+                return Ok(buf);
+            },
+            Err(e) if e.raw_os_error() == Some(libc::ENOMEM) => {},
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Returns the `N` bytes at `offset` within a record.
+fn field<const N: usize>(record: &[u8], offset: usize) -> [u8; N] {
+    let mut bytes = [0; N];
+    bytes.copy_from_slice(&record[offset..offset + N]);
+
+    bytes
+}
+
+// Parses the records of a kern.proc sysctl. Each record begins with its size,
+// which must match the size of our kinfo_proc.
+fn parse_kinfo_procs(buf: &[u8]) -> io::Result<Vec<KinfoProc>> {
+    let size = size_of::<kinfo_proc>();
+
+    buf.chunks(size)
+        .map(|record| {
+            let structsize = field(record, offset_of!(kinfo_proc, ki_structsize));
+            let structsize = c_int::from_ne_bytes(structsize);
+
+            if record.len() != size || usize::try_from(structsize) != Ok(size) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("kinfo_proc of {structsize} bytes, not {size}"),
+                ));
+            }
+
+            let jid = field(record, offset_of!(kinfo_proc, ki_jid));
+            let [stat] = field(record, offset_of!(kinfo_proc, ki_stat));
+            let tdflags = field(record, offset_of!(kinfo_proc, ki_tdflags));
+
+            let start = offset_of!(kinfo_proc, ki_start)
+                + offset_of!(timeval, tv_sec);
+            let start = field(record, start);
+
+            Ok(KinfoProc {
+                jid:     c_int::from_ne_bytes(jid),
+                stat:    stat,
+                tdflags: c_long::from_ne_bytes(tdflags),
+                start:   time_t::from_ne_bytes(start),
+            })
+        })
+        .collect()
+}
+
+/// Returns every process in the kernel process table.
+pub fn processes() -> io::Result<Vec<KinfoProc>> {
+    let buf = sysctl(&KERN_PROC_PROC)?;
+
+    parse_kinfo_procs(&buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    // Writes `bytes` at `offset` within a record.
+    fn set(record: &mut [u8], offset: usize, bytes: &[u8]) {
+        record[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    #[test]
+    fn parse_kinfo_procs_ok() {
+        let size = size_of::<kinfo_proc>();
+        let mut record = vec![0_u8; size];
+        let structsize = c_int::try_from(size).unwrap();
+
+        set(&mut record, offset_of!(kinfo_proc, ki_structsize),
+            &structsize.to_ne_bytes());
+        set(&mut record, offset_of!(kinfo_proc, ki_jid),
+            &c_int::to_ne_bytes(3));
+        set(&mut record, offset_of!(kinfo_proc, ki_stat), &[5]);
+        set(&mut record, offset_of!(kinfo_proc, ki_tdflags),
+            &c_long::to_ne_bytes(8));
+        set(&mut record, offset_of!(kinfo_proc, ki_start),
+            &time_t::to_ne_bytes(1000));
+
+        let buf = [record.clone(), record].concat();
+        let procs = parse_kinfo_procs(&buf).unwrap();
+
+        let ok = KinfoProc {
+            jid:     3,
+            stat:    5,
+            tdflags: 8,
+            start:   1000,
+        };
+
+        assert_eq!(procs, vec![ok, ok]);
+
+        // A truncated record is an error.
+        assert!(parse_kinfo_procs(&buf[..size + 1]).is_err());
+    }
+}
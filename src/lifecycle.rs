@@ -0,0 +1,324 @@
+// lifecycle: This module tracks jails across runs, counting when they start,
+//            stop and restart.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::register_counter_with_registry;
+use crate::jailsource::Jail;
+use crate::relabel::JailLabelSet;
+use parking_lot::Mutex;
+use prometheus_client::metrics::{
+    counter::Counter,
+    family::Family,
+};
+use prometheus_client::registry::Registry;
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+
+/// Time series counting the starts, stops and restarts of jails.
+///
+/// Unlike the other per-jail time series, these are kept until the stop of a
+/// reaped jail has been exported, otherwise the stop would never be seen.
+pub struct LifecycleMetrics {
+    restarts: Family<JailLabelSet, Counter>,
+    starts:   Family<JailLabelSet, Counter>,
+    stops:    Family<JailLabelSet, Counter>,
+
+    // The jail ID of each jail running on the last run. This is None until
+    // the first run, as we can't know whether those jails have just started.
+    jids: Mutex<Option<HashMap<String, i32>>>,
+
+    // Jails reaped before their stop was counted, which are removed on the
+    // run after it.
+    reaped: Mutex<HashSet<String>>,
+
+    // The label set of each jail with counters, kept so that the counters
+    // can be moved to the new label set if the labels of a jail change.
+    label_sets: Mutex<HashMap<String, JailLabelSet>>,
+}
+
+impl LifecycleMetrics {
+    /// Registers the lifecycle time series with the given `registry`.
+    pub fn new(registry: &mut Registry) -> Self {
+        Self {
+            restarts: register_counter_with_registry!(
+                "restarts",
+                "Number of times the named jail was restarted under a new \
+                 jail ID between runs",
+                JailLabelSet,
+                registry,
+            ),
+
+            starts: register_counter_with_registry!(
+                "starts",
+                "Number of times the named jail was seen to start",
+                JailLabelSet,
+                registry,
+            ),
+
+            stops: register_counter_with_registry!(
+                "stops",
+                "Number of times the named jail was seen to stop",
+                JailLabelSet,
+                registry,
+            ),
+
+            jids:       Mutex::new(None),
+            reaped:     Mutex::new(HashSet::new()),
+            label_sets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Returns the counters for the jail with the given `labels`, creating
+    // them at 0 if this is the first time that we've seen it. If the labels
+    // of the jail have changed, its counts are moved to the new label set.
+    fn counters(&self, labels: &JailLabelSet) -> [Counter; 3] {
+        let families = [&self.starts, &self.stops, &self.restarts];
+        let mut label_sets = self.label_sets.lock();
+
+        if let Some(old) = label_sets.get(labels.name()) {
+            if old != labels {
+                for family in families {
+                    let count = family.get(old).map_or(0, |c| c.get());
+                    family.remove(old);
+                    family.get_or_create(labels).inc_by(count);
+                }
+            }
+        }
+
+        label_sets.insert(labels.name().to_string(), labels.clone());
+
+        families.map(|family| family.get_or_create(labels).clone())
+    }
+
+    // Removes the counters for the named jail.
+    fn remove_counters(&self, name: &str) {
+        let Some(labels) = self.label_sets.lock().remove(name) else {
+            return;
+        };
+
+        self.starts.remove(&labels);
+        self.stops.remove(&labels);
+        self.restarts.remove(&labels);
+    }
+
+    /// Compares the given running jails against those from the last run,
+    /// counting the jails that have started, stopped or restarted since.
+    ///
+    /// A restart is also counted as a stop and a start. Reaped jails whose
+    /// stop has been exported have their time series removed.
+    pub fn update(&self, jails: &[(Jail, JailLabelSet)]) {
+        let current: HashMap<String, i32> = jails.iter()
+            .map(|(jail, _)| (jail.name.clone(), jail.jid))
+            .collect();
+
+        let mut jids = self.jids.lock();
+
+        // On the first run, the jails were started before we were, so we only
+        // create their counters.
+        let Some(previous) = &*jids else {
+            for (_, labels) in jails {
+                self.counters(labels);
+            }
+
+            *jids = Some(current);
+            return;
+        };
+
+        for (jail, labels) in jails {
+            let [starts, stops, restarts] = self.counters(labels);
+
+            match previous.get(&jail.name) {
+                None => {
+                    starts.inc();
+                },
+                Some(old) if *old != jail.jid => {
+                    stops.inc();
+                    starts.inc();
+                    restarts.inc();
+                },
+                Some(_) => {},
+            }
+        }
+
+        for name in previous.keys().filter(|name| !current.contains_key(*name)) {
+            let labels = self.label_sets.lock().get(name).cloned();
+
+            if let Some(labels) = labels {
+                let [_, stops, _] = self.counters(&labels);
+                stops.inc();
+            }
+        }
+
+        // Reaped jails that stopped on this run are kept for the next, while
+        // those that have started again are no longer reaped.
+        self.reaped.lock().retain(|name| {
+            if current.contains_key(name) {
+                return false;
+            }
+
+            if previous.contains_key(name) {
+                return true;
+            }
+
+            self.remove_counters(name);
+
+            false
+        });
+
+        *jids = Some(current);
+    }
+
+    /// Removes the lifecycle time series for the jail with the given
+    /// `labels`, which has been reaped.
+    ///
+    /// If the jail was running on the last run, its stop is yet to be
+    /// counted, so the removal is deferred until the run after that.
+    pub fn remove(&self, labels: &JailLabelSet) {
+        let name = labels.name();
+        let running = self.jids.lock()
+            .as_ref()
+            .is_some_and(|jids| jids.contains_key(name));
+
+        if running {
+            self.reaped.lock().insert(name.to_string());
+        }
+        else {
+            self.remove_counters(name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::relabel::Labels;
+    use pretty_assertions::assert_eq;
+
+    // Runs the snapshots of running jails through a new LifecycleMetrics,
+    // returning the starts, stops and restarts of each named jail.
+    fn run_snapshots(snapshots: &[&[(i32, &str)]], names: &[&str])
+    -> Vec<(String, [u64; 3])> {
+        let mut registry = Registry::default();
+        let metrics = LifecycleMetrics::new(&mut registry);
+
+        for snapshot in snapshots {
+            let jails: Vec<_> = snapshot.iter()
+                .map(|(jid, name)| (Jail::new(*jid, name), (*name).into()))
+                .collect();
+
+            metrics.update(&jails);
+        }
+
+        names.iter()
+            .map(|name| {
+                let labels = &JailLabelSet::from(*name);
+
+                let counts = [
+                    metrics.starts.get(labels).map_or(0, |c| c.get()),
+                    metrics.stops.get(labels).map_or(0, |c| c.get()),
+                    metrics.restarts.get(labels).map_or(0, |c| c.get()),
+                ];
+
+                (name.to_string(), counts)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lifecycle_first_run() {
+        let counts = run_snapshots(&[&[(1, "a")]], &["a"]);
+
+        // Jails running before the exporter aren't counted as starts.
+        assert_eq!(counts, vec![("a".into(), [0, 0, 0])]);
+    }
+
+    #[test]
+    fn lifecycle_start_stop() {
+        let snapshots: &[&[(i32, &str)]] = &[
+            &[(1, "a")],
+            &[(1, "a"), (2, "b")],
+            &[(2, "b")],
+            &[(2, "b")],
+            &[(2, "b"), (3, "a")],
+        ];
+
+        let counts = run_snapshots(snapshots, &["a", "b"]);
+
+        let ok = vec![
+            ("a".into(), [1, 1, 0]),
+            ("b".into(), [1, 0, 0]),
+        ];
+
+        assert_eq!(counts, ok);
+    }
+
+    #[test]
+    fn lifecycle_remove() {
+        let mut registry = Registry::default();
+        let metrics = LifecycleMetrics::new(&mut registry);
+        let jail = |jid, name: &str| (Jail::new(jid, name), name.into());
+        let labels = &JailLabelSet::from("a");
+
+        metrics.update(&[jail(1, "a"), jail(2, "b")]);
+        metrics.update(&[jail(1, "a"), jail(2, "b")]);
+
+        // Reaped as soon as it stopped, so its stop is still to be counted.
+        metrics.remove(labels);
+        metrics.update(&[jail(2, "b")]);
+        assert_eq!(metrics.stops.get(labels).map(|c| c.get()), Some(1));
+
+        // The stop has been exported, so the jail is removed.
+        metrics.update(&[jail(2, "b")]);
+        assert!(metrics.starts.get(labels).is_none());
+        assert!(metrics.stops.get(labels).is_none());
+        assert!(metrics.restarts.get(labels).is_none());
+
+        // Reaped after its stop was counted, so it's removed immediately.
+        let labels = &JailLabelSet::from("b");
+
+        metrics.update(&[]);
+        metrics.remove(labels);
+        assert!(metrics.stops.get(labels).is_none());
+    }
+
+    #[test]
+    fn lifecycle_relabel() {
+        let mut registry = Registry::default();
+        let metrics = LifecycleMetrics::new(&mut registry);
+        let old = JailLabelSet::from("a");
+        let new = JailLabelSet::new("a", Labels::from([
+            ("owner".into(), "alice".into()),
+        ]));
+
+        metrics.update(&[(Jail::new(1, "a"), old.clone())]);
+        metrics.update(&[(Jail::new(2, "a"), old.clone())]);
+        metrics.update(&[(Jail::new(2, "a"), new.clone())]);
+
+        // The counts follow the jail to its new label set.
+        assert!(metrics.restarts.get(&old).is_none());
+        assert_eq!(metrics.restarts.get(&new).map(|c| c.get()), Some(1));
+        assert_eq!(metrics.starts.get(&new).map(|c| c.get()), Some(1));
+    }
+
+    #[test]
+    fn lifecycle_restart() {
+        // Restarted between runs, under a new jail ID.
+        let snapshots: &[&[(i32, &str)]] = &[
+            &[(1, "a"), (2, "b")],
+            &[(3, "a"), (2, "b")],
+            &[(3, "a"), (2, "b")],
+            &[(4, "a"), (5, "b")],
+        ];
+
+        let counts = run_snapshots(snapshots, &["a", "b"]);
+
+        let ok = vec![
+            ("a".into(), [2, 2, 2]),
+            ("b".into(), [1, 1, 1]),
+        ];
+
+        assert_eq!(counts, ok);
+    }
+}
@@ -0,0 +1,454 @@
+// limits: This module handles the exporting of rctl(8) rules set on jails, and
+//         the utilisation of resources against those rules.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::{
+    register_float_gauge_with_registry,
+    register_gauge_with_registry,
+};
+use crate::errors::ExporterError;
+use crate::jailsource::Rusage;
+use crate::rctltypes::{
+    Action,
+    Resource,
+    SubjectType,
+};
+use crate::relabel::JailLabelSet;
+use parking_lot::Mutex;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::{
+    family::Family,
+    gauge::Gauge,
+};
+use prometheus_client::registry::{
+    Registry,
+    Unit,
+};
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+use std::str::FromStr;
+use std::sync::atomic::AtomicU64;
+use tracing::debug;
+
+// Suffixes accepted on rule amounts, as documented in rctl(8). Each suffix
+// multiplies the amount by a further power of 1024.
+const AMOUNT_SUFFIXES: &[char] = &['k', 'm', 'g', 't', 'p', 'e'];
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct LimitLabels {
+    // Jail name, along with the labels added to the jail.
+    #[prometheus(flatten)]
+    jail: JailLabelSet,
+
+    // The rctl resource being limited.
+    resource: String,
+
+    // Action taken when the limit is reached.
+    action: String,
+
+    // The entity the amount is accounted for.
+    per: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ResourceLabels {
+    // Jail name, along with the labels added to the jail.
+    #[prometheus(flatten)]
+    jail: JailLabelSet,
+
+    // The rctl resource.
+    resource: String,
+}
+
+// Label sets created for a jail on the last run.
+#[derive(Debug, Default)]
+struct JailLabelSets {
+    limits:      HashSet<LimitLabels>,
+    utilisation: HashSet<ResourceLabels>,
+}
+
+/// A limit parsed from the text format of an rctl(8) rule.
+///
+/// Rules take the form `subject:subject-id:resource:action=amount/per`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RctlLimit {
+    /// The resource being limited.
+    pub resource: Resource,
+
+    /// The action taken when the limit is reached.
+    pub action: Action,
+
+    /// The amount of the resource allowed.
+    pub amount: u64,
+
+    /// The entity the amount is accounted for. Defaults to the type of the
+    /// rule subject if the rule doesn't specify one.
+    pub per: SubjectType,
+}
+
+impl FromStr for RctlLimit {
+    type Err = ExporterError;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| {
+            ExporterError::InvalidRctlRule(format!("{rule}: {reason}"))
+        };
+
+        let parts: Vec<&str> = rule.trim().split(':').collect();
+
+        let [subject, _subject_id, resource, action_amount] = parts[..] else {
+            return Err(invalid("expected subject:subject-id:resource:action"));
+        };
+
+        let subject = subject.parse::<SubjectType>()
+            .map_err(|e| invalid(&e.to_string()))?;
+
+        let resource = resource.parse::<Resource>()
+            .map_err(|e| invalid(&e.to_string()))?;
+
+        let Some((action, amount_per)) = action_amount.split_once('=') else {
+            return Err(invalid("expected action=amount"));
+        };
+
+        let action = action.parse::<Action>()
+            .map_err(|e| invalid(&e.to_string()))?;
+
+        let (amount, per) = match amount_per.split_once('/') {
+            Some((amount, per)) => {
+                let per = per.parse::<SubjectType>()
+                    .map_err(|e| invalid(&e.to_string()))?;
+
+                (amount, per)
+            },
+            None => (amount_per, subject),
+        };
+
+        let amount = parse_amount(amount)
+            .ok_or_else(|| invalid("invalid amount"))?;
+
+        Ok(Self {
+            resource: resource,
+            action:   action,
+            amount:   amount,
+            per:      per,
+        })
+    }
+}
+
+// Parses a rule amount, handling the optional size suffix.
+fn parse_amount(amount: &str) -> Option<u64> {
+    let amount = amount.to_lowercase();
+
+    let (number, multiplier) = match amount.strip_suffix(AMOUNT_SUFFIXES) {
+        Some(number) => {
+            let suffix = amount.chars().last()?;
+            let power = AMOUNT_SUFFIXES.iter().position(|&s| s == suffix)?;
+            let power = u32::try_from(power + 1).ok()?;
+
+            (number, 1024_u64.checked_pow(power)?)
+        },
+        None => (amount.as_str(), 1),
+    };
+
+    number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Parses the given rules, skipping over any that are invalid.
+pub fn parse_rules(rules: &[String]) -> Vec<RctlLimit> {
+    rules.iter()
+        .filter_map(|rule| {
+            match rule.parse::<RctlLimit>() {
+                Ok(limit) => Some(limit),
+                Err(e)    => {
+                    debug!("Skipping rule: {}", e);
+                    None
+                },
+            }
+        })
+        .collect()
+}
+
+// Returns the deny limits that apply to the jail as a whole, keyed on
+// resource. If there are several for a resource, the lowest one wins as it's
+// the one that will be hit first.
+fn deny_limits(limits: &[RctlLimit]) -> HashMap<Resource, u64> {
+    let mut deny = HashMap::new();
+
+    let limits = limits.iter().filter(|limit| {
+        limit.action == Action::Deny && limit.per == SubjectType::Jail
+    });
+
+    for limit in limits {
+        deny.entry(limit.resource)
+            .and_modify(|amount: &mut u64| *amount = limit.amount.min(*amount))
+            .or_insert(limit.amount);
+    }
+
+    deny
+}
+
+/// Time series for the rctl(8) limits set on jails.
+pub struct LimitMetrics {
+    jail_limit:  Family<LimitLabels, Gauge>,
+    utilisation: Family<ResourceLabels, Gauge<f64, AtomicU64>>,
+
+    // The label sets created for each jail on the last run. Rules can be
+    // removed from a running jail, so we use this to remove stale label sets.
+    label_sets: Mutex<HashMap<String, JailLabelSets>>,
+}
+
+impl LimitMetrics {
+    /// Registers the limit time series with the given `registry`.
+    pub fn new(registry: &mut Registry) -> Self {
+        Self {
+            jail_limit: register_gauge_with_registry!(
+                "limit",
+                "rctl(8) limit set on the named jail",
+                LimitLabels,
+                registry,
+            ),
+
+            utilisation: register_float_gauge_with_registry!(
+                "resource_utilisation",
+                "usage of the resource as a ratio of the deny limit set on \
+                 the named jail",
+                ResourceLabels,
+                Unit::Other("ratio".into()),
+                registry,
+            ),
+
+            label_sets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the limits for the jail with the label set `jail`, along with the
+    /// utilisation of any resources with a deny limit, removing any that no
+    /// longer exist.
+    pub fn update(
+        &self,
+        jail: &JailLabelSet,
+        limits: &[RctlLimit],
+        rusage: &Rusage,
+    ) {
+        let name = jail.name();
+        let mut label_sets = self.label_sets.lock();
+        let mut current = JailLabelSets::default();
+
+        for limit in limits {
+            let labels = LimitLabels {
+                jail:     jail.clone(),
+                resource: limit.resource.to_string(),
+                action:   limit.action.to_string(),
+                per:      limit.per.to_string(),
+            };
+
+            let amount = i64::try_from(limit.amount).unwrap_or(i64::MAX);
+            self.jail_limit.get_or_create(&labels).set(amount);
+
+            current.limits.insert(labels);
+        }
+
+        for (resource, amount) in deny_limits(limits) {
+            // A zero limit would leave us dividing by zero.
+            if amount == 0 {
+                continue;
+            }
+
+            let Some(usage) = rusage.get(&resource) else {
+                continue;
+            };
+
+            let labels = ResourceLabels {
+                jail:     jail.clone(),
+                resource: resource.to_string(),
+            };
+
+            #[allow(clippy::cast_precision_loss)]
+            let ratio = *usage as f64 / amount as f64;
+            self.utilisation.get_or_create(&labels).set(ratio);
+
+            current.utilisation.insert(labels);
+        }
+
+        let previous = label_sets.remove(name).unwrap_or_default();
+
+        for labels in previous.limits.difference(&current.limits) {
+            self.jail_limit.remove(labels);
+        }
+
+        for labels in previous.utilisation.difference(&current.utilisation) {
+            self.utilisation.remove(labels);
+        }
+
+        if !current.limits.is_empty() {
+            label_sets.insert(name.to_string(), current);
+        }
+    }
+
+    /// Removes all limits for the jail with the given `labels`.
+    pub fn remove(&self, labels: &JailLabelSet) {
+        self.update(labels, &[], &Rusage::new());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse_amount_ok() {
+        assert_eq!(parse_amount("100"), Some(100));
+        assert_eq!(parse_amount("1k"), Some(1024));
+        assert_eq!(parse_amount("2G"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_amount("1e"), Some(1024_u64.pow(6)));
+        assert_eq!(parse_amount("1z"), None);
+        assert_eq!(parse_amount("g"), None);
+        assert_eq!(parse_amount("-1"), None);
+        assert_eq!(parse_amount("100000e"), None);
+    }
+
+    #[test]
+    fn rctl_limit_from_str_ok() {
+        let limit = "jail:foo:memoryuse:deny=2G".parse::<RctlLimit>().unwrap();
+        let ok = RctlLimit {
+            resource: Resource::MemoryUse,
+            action:   Action::Deny,
+            amount:   2 * 1024 * 1024 * 1024,
+            per:      SubjectType::Jail,
+        };
+
+        assert_eq!(limit, ok);
+    }
+
+    #[test]
+    fn rctl_limit_from_str_per() {
+        let limit = "jail:foo:maxproc:log=100/process".parse::<RctlLimit>()
+            .unwrap();
+
+        let ok = RctlLimit {
+            resource: Resource::MaxProcesses,
+            action:   Action::Log,
+            amount:   100,
+            per:      SubjectType::Process,
+        };
+
+        assert_eq!(limit, ok);
+    }
+
+    #[test]
+    fn rctl_limit_from_str_invalid() {
+        let rules = [
+            "",
+            "jail:foo",
+            "jail:foo:memoryuse",
+            "jail:foo:memoryuse:deny",
+            "jail:foo:memoryuse:deny=",
+            "jail:foo:memoryuse:deny=lots",
+            "jail:foo:memoryuse:deny=1g/nobody",
+            "jail:foo:memoryuse:obliterate=1g",
+            "jail:foo:memoryused:deny=1g",
+            "prison:foo:memoryuse:deny=1g",
+            "jail:foo:memoryuse:deny=1g:extra",
+        ];
+
+        for rule in rules {
+            assert!(rule.parse::<RctlLimit>().is_err(), "{rule}");
+        }
+    }
+
+    #[test]
+    fn parse_rules_skips_invalid() {
+        let rules = vec![
+            "jail:foo:memoryuse:deny=1024".to_string(),
+            "jail:foo:bogus:deny=1".to_string(),
+            "jail:foo:openfiles:devctl=512".to_string(),
+        ];
+
+        let limits = parse_rules(&rules);
+        let resources: Vec<Resource> = limits.iter()
+            .map(|limit| limit.resource)
+            .collect();
+
+        assert_eq!(resources, vec![Resource::MemoryUse, Resource::OpenFiles]);
+    }
+
+    #[test]
+    fn limit_metrics_update_removes_stale() {
+        let mut registry = Registry::default();
+        let metrics = LimitMetrics::new(&mut registry);
+
+        let limits = parse_rules(&[
+            "jail:foo:memoryuse:deny=1024".to_string(),
+            "jail:foo:maxproc:deny=10".to_string(),
+        ]);
+
+        metrics.update(&"foo".into(), &limits, &Rusage::new());
+
+        let labels = LimitLabels {
+            jail:     "foo".into(),
+            resource: "maxproc".into(),
+            action:   "deny".into(),
+            per:      "jail".into(),
+        };
+
+        assert_eq!(metrics.jail_limit.get_or_create(&labels).get(), 10);
+
+        // The maxproc rule was removed from the jail.
+        metrics.update(&"foo".into(), &limits[..1], &Rusage::new());
+        assert_eq!(metrics.label_sets.lock()["foo"].limits.len(), 1);
+        assert!(metrics.jail_limit.get(&labels).is_none());
+
+        metrics.remove(&"foo".into());
+        assert!(metrics.label_sets.lock().is_empty());
+    }
+
+    #[test]
+    fn deny_limits_ok() {
+        let limits = parse_rules(&[
+            "jail:foo:memoryuse:deny=2048".to_string(),
+            "jail:foo:memoryuse:deny=1024".to_string(),
+            "jail:foo:memoryuse:log=512".to_string(),
+            "jail:foo:maxproc:deny=10/process".to_string(),
+        ]);
+
+        let ok = HashMap::from([(Resource::MemoryUse, 1024)]);
+
+        assert_eq!(deny_limits(&limits), ok);
+    }
+
+    #[test]
+    fn limit_metrics_utilisation() {
+        let mut registry = Registry::default();
+        let metrics = LimitMetrics::new(&mut registry);
+
+        let limits = parse_rules(&[
+            "jail:foo:memoryuse:deny=1024".to_string(),
+            "jail:foo:openfiles:log=100".to_string(),
+            "jail:foo:nthr:deny=0".to_string(),
+        ]);
+
+        let rusage = Rusage::from([
+            (Resource::MemoryUse, 768),
+            (Resource::OpenFiles, 50),
+            (Resource::NThreads, 5),
+        ]);
+
+        metrics.update(&"foo".into(), &limits, &rusage);
+
+        let labels = ResourceLabels {
+            jail:     "foo".into(),
+            resource: "memoryuse".into(),
+        };
+
+        // Only memoryuse has a usable deny limit.
+        assert_eq!(metrics.label_sets.lock()["foo"].utilisation.len(), 1);
+        assert_eq!(metrics.utilisation.get_or_create(&labels).get(), 0.75);
+
+        // The deny limit was removed.
+        metrics.update(&"foo".into(), &limits[1..], &rusage);
+        assert!(metrics.label_sets.lock()["foo"].utilisation.is_empty());
+    }
+}
@@ -7,7 +7,7 @@
 #[macro_export]
 macro_rules! register_counter_with_registry {
     // Counter family with no specific unit
-    ($NAME:expr, $HELP:expr, $LABELS:ty, $REGISTRY:ident $(,)?) => {{
+    ($NAME:expr, $HELP:expr, $LABELS:ty, $REGISTRY:expr $(,)?) => {{
         use prometheus_client::metrics::{
             counter::Counter,
             family::Family,
@@ -21,7 +21,7 @@ macro_rules! register_counter_with_registry {
     }};
 
     // Counter family with a specified unit
-    ($NAME:expr, $HELP:expr, $LABELS:ty, $UNIT:expr, $REGISTRY:ident $(,)?) => {{
+    ($NAME:expr, $HELP:expr, $LABELS:ty, $UNIT:expr, $REGISTRY:expr $(,)?) => {{
         use prometheus_client::metrics::{
             counter::Counter,
             family::Family,
@@ -44,7 +44,7 @@ macro_rules! register_counter_with_registry {
 #[macro_export]
 macro_rules! register_gauge_with_registry {
     // Single gauge with no specified unit
-    ($NAME:expr, $HELP:expr, $REGISTRY:ident $(,)?) => {{
+    ($NAME:expr, $HELP:expr, $REGISTRY:expr $(,)?) => {{
         use prometheus_client::metrics::gauge::Gauge;
 
         let gauge = Gauge::default();
@@ -55,7 +55,7 @@ macro_rules! register_gauge_with_registry {
     }};
 
     // Gauge family with no specified unit
-    ($NAME:expr, $HELP:expr, $LABELS:ty, $REGISTRY:ident $(,)?) => {{
+    ($NAME:expr, $HELP:expr, $LABELS:ty, $REGISTRY:expr $(,)?) => {{
         use prometheus_client::metrics::{
             family::Family,
             gauge::Gauge,
@@ -69,7 +69,7 @@ macro_rules! register_gauge_with_registry {
     }};
 
     // Gauge family with a specified unit
-    ($NAME:expr, $HELP:expr, $LABELS:ty, $UNIT:expr, $REGISTRY:ident $(,)?) => {{
+    ($NAME:expr, $HELP:expr, $LABELS:ty, $UNIT:expr, $REGISTRY:expr $(,)?) => {{
         use prometheus_client::metrics::{
             family::Family,
             gauge::Gauge,
@@ -88,11 +88,61 @@ macro_rules! register_gauge_with_registry {
     }};
 }
 
+/// Register a floating point Gauge with the Registry
+#[macro_export]
+macro_rules! register_float_gauge_with_registry {
+    // Float gauge family with no specified unit
+    ($NAME:expr, $HELP:expr, $LABELS:ty, $REGISTRY:expr $(,)?) => {{
+        use prometheus_client::metrics::{
+            family::Family,
+            gauge::Gauge,
+        };
+        use std::sync::atomic::AtomicU64;
+
+        let family = Family::<$LABELS, Gauge<f64, AtomicU64>>::default();
+
+        $REGISTRY.register($NAME, $HELP, family.clone());
+
+        family
+    }};
+
+    // Float gauge family with a specified unit
+    ($NAME:expr, $HELP:expr, $LABELS:ty, $UNIT:expr, $REGISTRY:expr $(,)?) => {{
+        use prometheus_client::metrics::{
+            family::Family,
+            gauge::Gauge,
+        };
+        use std::sync::atomic::AtomicU64;
+
+        let family = Family::<$LABELS, Gauge<f64, AtomicU64>>::default();
+
+        $REGISTRY.register_with_unit(
+            $NAME,
+            $HELP,
+            $UNIT,
+            family.clone(),
+        );
+
+        family
+    }};
+}
+
 /// Register an Info metric with the Registry
 #[macro_export]
 macro_rules! register_info_with_registry {
+    // Info family, each label set in the family is an info metric.
+    ($NAME:expr, $HELP:expr, Family<$LABELS:ty>, $REGISTRY:expr $(,)?) => {{
+        use $crate::info::InfoFamily;
+
+        let family = InfoFamily::<$LABELS>::default();
+
+        $REGISTRY.register($NAME, $HELP, family.clone());
+
+        family
+    }};
+
     // Single info metric with specified labels.
-    ($NAME:expr, $HELP:expr, $LABELS:expr, $REGISTRY:ident $(,)?) => {{
+    ($NAME:expr, $HELP:expr, $LABELS:expr, $REGISTRY:expr $(,)?) => {{
         use prometheus_client::metrics::info::Info;
 
         let info = Info::new($LABELS);
@@ -3,24 +3,46 @@
 //!
 //! An exporter for Prometheus, exporting jail metrics as reported by rctl(8).
 //!
-#![forbid(unsafe_code)]
+// Unsafe code is denied rather than forbidden so that kernproc can call
+// sysctl(3) to read the kernel process table, which the sysctl crate can't
+// read. That one call is the only unsafe code allowed.
+#![deny(unsafe_code)]
 #![deny(missing_docs)]
 #![allow(clippy::module_name_repetitions)]
 #![allow(clippy::redundant_field_names)]
 use tracing::debug;
-use uzers::UsersCache;
-
-#[cfg(feature = "auth")]
 use std::path::PathBuf;
+use uzers::UsersCache;
 
 mod cli;
+mod collectors;
+mod command;
+mod config;
+mod created;
 mod errors;
 mod exporter;
 mod file;
+mod filesystem;
+mod filter;
+mod hierarchy;
 mod httpd;
-mod racctrctl;
+mod info;
+mod jailconf;
+mod jailsource;
+mod lifecycle;
+mod limits;
+mod meta;
+mod network;
+mod options;
+mod processes;
+mod reaper;
+mod relabel;
 mod rctlstate;
+mod rctltypes;
+mod rusage;
+mod scrape;
 mod user;
+mod zfs;
 
 #[macro_use]
 mod macros;
@@ -28,11 +50,34 @@ mod macros;
 #[cfg(feature = "bcrypt_cmd")]
 mod bcrypt;
 
+#[cfg(feature = "jail_manager")]
+mod manager;
+
 #[cfg(feature = "rc_script")]
 mod rcscript;
 
+#[cfg(target_os = "freebsd")]
+mod kernproc;
+
+#[cfg(target_os = "freebsd")]
+mod racctrctl;
+
 use errors::ExporterError;
 use exporter::Exporter;
+use command::SystemCommandRunner;
+use config::Config;
+use filter::{
+    JailFilter,
+    JailMatcher,
+};
+use network::NetstatReader;
+use options::{
+    ConstLabel,
+    ExporterOptions,
+    NamingScheme,
+};
+use rctlstate::RctlState;
+use reaper::ReapAfter;
 use file::{
     FileExporter,
     FileExporterOutput,
@@ -41,6 +86,15 @@ use file::{
 #[cfg(feature = "auth")]
 use httpd::auth::BasicAuthConfig;
 
+#[cfg(feature = "jail_manager")]
+use manager::JailManagers;
+
+#[cfg(target_os = "freebsd")]
+use processes::KernProcReader;
+
+#[cfg(target_os = "freebsd")]
+use tracing::warn;
+
 #[tokio::main]
 async fn main() -> Result<(), ExporterError> {
     // We do as much as we can without checking if we're running as root.
@@ -69,15 +123,196 @@ async fn main() -> Result<(), ExporterError> {
     // Check that we're running as root.
     user::is_running_as_root(&mut UsersCache::new())?;
 
-    // Check if RACCT/RCTL is available and if it's not, exit.
-    racctrctl::is_available()?;
+    // Check if RACCT/RCTL is available. If it's not, we still run, but only
+    // export the time series that don't depend on it.
+    #[cfg(target_os = "freebsd")]
+    let (rctl_state, rctl) = {
+        let rctl_state = RctlState::check();
+        let rctl = match racctrctl::is_available(rctl_state) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("{e}, the resource usage of jails won't be exported");
+                false
+            },
+        };
+
+        (rctl_state, rctl)
+    };
+
+    // RACCT/RCTL only exists on FreeBSD.
+    #[cfg(not(target_os = "freebsd"))]
+    let (rctl_state, rctl) = (RctlState::NotPresent, false);
+
+    // We shouldn't hit the error conditions here after the validation of the
+    // CLI arguments passed.
+    let jail_conf = matches.get_one::<PathBuf>("COLLECTOR_JAIL_CONF")
+        .ok_or_else(|| {
+            ExporterError::ArgNotSet("collector.jail-conf".to_owned())
+        })?.clone();
+    debug!("collector.jail-conf: {}", jail_conf.display());
+
+    let reap_after = *matches.get_one::<ReapAfter>("COLLECTOR_REAP_AFTER")
+        .ok_or_else(|| {
+            ExporterError::ArgNotSet("collector.reap-after".to_owned())
+        })?;
+    debug!("collector.reap-after: {reap_after}");
+
+    let config = match matches.get_one::<PathBuf>("CONFIG_FILE") {
+        Some(path) => Config::from_yaml(path)?,
+        None       => Config::default(),
+    };
+
+    // Jail matchers given on the command line are added to those in the
+    // configuration file.
+    let include = matches.get_many::<JailMatcher>("COLLECTOR_JAIL_INCLUDE")
+        .into_iter()
+        .flatten()
+        .cloned();
+    let exclude = matches.get_many::<JailMatcher>("COLLECTOR_JAIL_EXCLUDE")
+        .into_iter()
+        .flatten()
+        .cloned();
+
+    let filter = config.jail_include.into_iter()
+        .chain(include)
+        .fold(JailFilter::new(), JailFilter::include);
+    let filter = config.jail_exclude.into_iter()
+        .chain(exclude)
+        .fold(filter, JailFilter::exclude);
+
+    // Constant labels given on the command line take precedence over those
+    // in the configuration file.
+    let mut const_labels = config.const_labels;
+
+    for label in matches.get_many::<ConstLabel>("WEB_CONST_LABEL")
+        .into_iter()
+        .flatten()
+    {
+        const_labels.insert(label.clone());
+    }
+
+    // Constant labels are already on every time series, so they can't also
+    // be added to those of the jails.
+    let mut relabeler = config.relabel_configs;
+
+    relabeler.reserve(const_labels.names())
+        .and_then(|()| {
+            config.meta_labels.check_const_labels(const_labels.names())
+        })
+        .map_err(ExporterError::ConfigError)?;
+
+    let prefix = matches.get_one::<String>("WEB_METRIC_PREFIX")
+        .ok_or_else(|| {
+            ExporterError::ArgNotSet("web.metric-prefix".to_owned())
+        })?;
+    debug!("web.metric-prefix: {prefix}");
+
+    let naming = *matches.get_one::<NamingScheme>("WEB_NAMING_SCHEME")
+        .ok_or_else(|| {
+            ExporterError::ArgNotSet("web.naming-scheme".to_owned())
+        })?;
+    debug!("web.naming-scheme: {naming}");
+
+    // Collectors enabled or disabled on the command line take precedence
+    // over those in the configuration file. The flags disabling collectors
+    // can only be given on the command line, so they win over the
+    // environment.
+    let mut collectors = config.collectors;
+
+    for collector in collectors::all() {
+        let enabled = if matches.get_flag(collector.no_id) {
+            false
+        }
+        else if matches.get_flag(collector.id) {
+            true
+        }
+        else {
+            continue;
+        };
+
+        collectors.insert(collector, enabled);
+    }
+
+    let options = ExporterOptions::new()
+        .collectors(collectors)
+        .const_labels(const_labels)
+        .naming(naming)
+        .prefix(prefix)
+        .rctl(rctl)
+        .rctl_state(rctl_state);
+
+    let collectors = options.enabled_collectors();
+
+    let exporter = Exporter::with_options(&options)
+        .jail_conf(jail_conf)
+        .jail_filter(filter)
+        .reap_after(reap_after)
+        .relabel(relabeler)
+        .meta_labels(config.meta_labels);
+
+    // Enable the network collector if we've been asked to.
+    let exporter = if collectors.is_enabled("network") {
+        exporter.network(NetstatReader::new(SystemCommandRunner))
+    }
+    else {
+        exporter
+    };
+
+    // Read the process table if the process or start time collectors are
+    // enabled. The process table is only read on FreeBSD.
+    #[cfg(target_os = "freebsd")]
+    let exporter = if collectors.is_enabled("processes")
+        || collectors.is_enabled("start-time")
+    {
+        exporter.processes(KernProcReader)
+    }
+    else {
+        exporter
+    };
+
+    // Enable the ZFS collector if we've been asked to.
+    let exporter = if collectors.is_enabled("zfs") {
+        exporter.zfs(SystemCommandRunner)
+    }
+    else {
+        exporter
+    };
+
+    #[cfg(feature = "jail_manager")]
+    // Enable the jail manager collector if we've been asked to.
+    let exporter = if collectors.is_enabled("jail-manager") {
+        let root = |id: &str, arg: &str| {
+            matches.get_one::<PathBuf>(id)
+                .cloned()
+                .ok_or_else(|| ExporterError::ArgNotSet(arg.to_owned()))
+        };
+
+        let managers = JailManagers::new()
+            .bastille_root(root(
+                "COLLECTOR_JAIL_MANAGER_BASTILLE_ROOT",
+                "collector.jail-manager.bastille-root",
+            )?)
+            .iocage_root(root(
+                "COLLECTOR_JAIL_MANAGER_IOCAGE_ROOT",
+                "collector.jail-manager.iocage-root",
+            )?)
+            .pot_root(root(
+                "COLLECTOR_JAIL_MANAGER_POT_ROOT",
+                "collector.jail-manager.pot-root",
+            )?);
+
+        exporter.jail_managers(managers)
+    }
+    else {
+        exporter
+    };
 
     // If an output file was specified, we do that. We will never launch the
     // HTTPd when we're passed an OUTPUT_FILE_PATH.
     if let Some(output_path) = matches.get_one::<FileExporterOutput>("OUTPUT_FILE_PATH") {
         debug!("output.file-path: {}", output_path);
 
-        let exporter = FileExporter::new(output_path.clone());
+        let exporter = FileExporter::new(output_path.clone(), exporter);
 
         return exporter.export();
     }
@@ -118,7 +353,6 @@ async fn main() -> Result<(), ExporterError> {
         server = server.auth_config(config);
     }
 
-    let exporter = Exporter::new();
     server.run(exporter).await?;
 
     Ok(())
@@ -0,0 +1,241 @@
+// manager: This module reads the on-disk configuration of jail managers,
+//          labelling jails with details that their name alone doesn't give.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use prometheus_client::encoding::{
+    EncodeLabel,
+    EncodeLabelSet,
+    LabelSetEncoder,
+};
+use std::fmt;
+use std::fs;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use tracing::{
+    debug,
+    warn,
+};
+
+mod bastille;
+mod iocage;
+mod pot;
+
+/// The default `bastille_prefix` of Bastille.
+pub const DEFAULT_BASTILLE_ROOT: &str = "/usr/local/bastille";
+
+/// The default mountpoint of the iocage dataset.
+pub const DEFAULT_IOCAGE_ROOT: &str = "/iocage";
+
+/// The default `POT_FS_ROOT` of pot.
+pub const DEFAULT_POT_ROOT: &str = "/opt/pot";
+
+/// Label set for the `jail_manager_info` metric.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ManagerLabels {
+    // Jail name, as it appears to jls(8).
+    name: String,
+
+    // The jail manager that the jail belongs to.
+    manager: String,
+
+    // FreeBSD release the jail was created from.
+    release: String,
+
+    // Template the jail was created from, if the manager records it.
+    template: String,
+
+    // Comma separated, sorted, tags applied to the jail.
+    tags: String,
+}
+
+// Not every jail manager records templates or tags, so those labels are only
+// given when they have a value.
+impl EncodeLabelSet for ManagerLabels {
+    fn encode(&self, encoder: &mut LabelSetEncoder) -> Result<(), fmt::Error> {
+        let labels = [
+            ("name",    &self.name),
+            ("manager", &self.manager),
+            ("release", &self.release),
+        ];
+
+        let optional = [
+            ("template", &self.template),
+            ("tags",     &self.tags),
+        ];
+
+        let optional = optional.into_iter()
+            .filter(|(_, value)| !value.is_empty());
+
+        for (key, value) in labels.into_iter().chain(optional) {
+            (key, value.as_str()).encode(encoder.encode_label())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Root directories of the supported jail managers.
+#[derive(Clone, Debug)]
+pub struct JailManagers {
+    bastille_root: PathBuf,
+    iocage_root:   PathBuf,
+    pot_root:      PathBuf,
+}
+
+impl Default for JailManagers {
+    fn default() -> Self {
+        Self {
+            bastille_root: DEFAULT_BASTILLE_ROOT.into(),
+            iocage_root:   DEFAULT_IOCAGE_ROOT.into(),
+            pot_root:      DEFAULT_POT_ROOT.into(),
+        }
+    }
+}
+
+// Implements a builder pattern for configuring the jail manager roots.
+impl JailManagers {
+    /// Returns jail managers with their default root directories.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the root directory of Bastille, its `bastille_prefix`.
+    #[must_use]
+    pub fn bastille_root(mut self, root: PathBuf) -> Self {
+        debug!("Setting bastille root to: {}", root.display());
+
+        self.bastille_root = root;
+        self
+    }
+
+    /// Sets the root directory of iocage, the mountpoint of its dataset.
+    #[must_use]
+    pub fn iocage_root(mut self, root: PathBuf) -> Self {
+        debug!("Setting iocage root to: {}", root.display());
+
+        self.iocage_root = root;
+        self
+    }
+
+    /// Sets the root directory of pot, its `POT_FS_ROOT`.
+    #[must_use]
+    pub fn pot_root(mut self, root: PathBuf) -> Self {
+        debug!("Setting pot root to: {}", root.display());
+
+        self.pot_root = root;
+        self
+    }
+
+    /// Reads the jails of every jail manager. Managers that aren't installed
+    /// simply have no jails, and jails whose configuration can't be read are
+    /// skipped.
+    pub fn read(&self) -> Vec<ManagerLabels> {
+        let mut jails = bastille::read(&self.bastille_root);
+        jails.extend(iocage::read(&self.iocage_root));
+        jails.extend(pot::read(&self.pot_root));
+
+        jails
+    }
+}
+
+// Returns the name and path of each jail directory within the `jails`
+// directory of a jail manager root, sorted by name.
+fn jail_dirs(root: &Path) -> Vec<(String, PathBuf)> {
+    let jails = root.join("jails");
+
+    // If the directory doesn't exist, the manager isn't in use.
+    let Ok(entries) = fs::read_dir(&jails) else {
+        debug!("No jail manager jails in: {}", jails.display());
+        return Vec::new();
+    };
+
+    let mut dirs = Vec::new();
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Failed to read {}, skipping entry: {e}", jails.display());
+                continue;
+            },
+        };
+
+        let path = entry.path();
+
+        if path.is_dir() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            dirs.push((name, path));
+        }
+    }
+
+    dirs.sort();
+
+    dirs
+}
+
+// Logs a jail whose configuration couldn't be read, which is skipped.
+fn skip_jail(path: &Path, e: impl fmt::Display) {
+    warn!("Failed to read jail manager config {}, skipping: {e}", path.display());
+}
+
+// Renders tags in a stable order for the tags label.
+fn join_tags<I, S>(tags: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut tags: Vec<String> = tags.into_iter()
+        .map(|tag| tag.as_ref().trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+
+    tags.sort();
+    tags.dedup();
+    tags.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn jail_managers_read() {
+        let managers = JailManagers::new()
+            .bastille_root("test-data/manager/bastille".into())
+            .iocage_root("test-data/manager/iocage".into())
+            .pot_root("test-data/manager/pot".into());
+
+        let names: Vec<_> = managers.read()
+            .into_iter()
+            .map(|labels| (labels.manager, labels.name))
+            .collect();
+
+        let ok = vec![
+            ("bastille".to_string(), "thick".to_string()),
+            ("bastille".to_string(), "web".to_string()),
+            ("iocage".to_string(), "ioc-db".to_string()),
+            ("iocage".to_string(), "ioc-mail_example".to_string()),
+            ("pot".to_string(), "dns".to_string()),
+        ];
+
+        assert_eq!(names, ok);
+    }
+
+    #[test]
+    fn jail_managers_read_missing() {
+        let managers = JailManagers::new()
+            .bastille_root("test-data/manager/missing".into())
+            .iocage_root("test-data/manager/missing".into())
+            .pot_root("test-data/manager/missing".into());
+
+        assert_eq!(managers.read(), vec![]);
+    }
+
+    #[test]
+    fn join_tags_ok() {
+        assert_eq!(join_tags(["web", " prod ", "", "web"]), "prod,web");
+    }
+}
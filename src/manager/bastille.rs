@@ -0,0 +1,118 @@
+// bastille: Reads jails managed by Bastille.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::jailconf::JailConf;
+use std::fs;
+use std::path::Path;
+use super::{
+    jail_dirs,
+    join_tags,
+    skip_jail,
+    ManagerLabels,
+};
+
+const MANAGER: &str = "bastille";
+
+// Finds the release of a thin jail from the nullfs mount of the release in
+// the jail's fstab.
+fn fstab_release(fstab: &str) -> Option<String> {
+    fstab.lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_whitespace().next())
+        .find_map(|source| {
+            let (_, release) = source.split_once("/releases/")?;
+            let release = release.split('/').next()?;
+
+            Some(release.to_string())
+        })
+}
+
+// Finds the release of a thick jail from the freebsd-version(1) script
+// within the jail.
+fn userland_release(freebsd_version: &str) -> Option<String> {
+    freebsd_version.lines()
+        .find_map(|line| line.strip_prefix("USERLAND_VERSION="))
+        .map(|version| version.trim_matches('"').to_string())
+}
+
+/// Reads the Bastille jails under the given `root`.
+pub fn read(root: &Path) -> Vec<ManagerLabels> {
+    let mut jails = Vec::new();
+
+    for (_, dir) in jail_dirs(root) {
+        let jail_conf = dir.join("jail.conf");
+
+        if !jail_conf.is_file() {
+            continue;
+        }
+
+        let conf = match JailConf::load(&jail_conf) {
+            Ok(conf) => conf,
+            Err(e) => {
+                skip_jail(&jail_conf, e);
+                continue;
+            },
+        };
+
+        let release = fs::read_to_string(dir.join("fstab"))
+            .ok()
+            .and_then(|fstab| fstab_release(&fstab))
+            .or_else(|| {
+                let path = dir.join("root/bin/freebsd-version");
+
+                fs::read_to_string(path)
+                    .ok()
+                    .and_then(|version| userland_release(&version))
+            })
+            .unwrap_or_default();
+
+        // Tags are stored one per line.
+        let tags = fs::read_to_string(dir.join("tags")).unwrap_or_default();
+        let tags = join_tags(tags.lines());
+
+        // The jail name is taken from the jail's own jail.conf(5).
+        // Bastille doesn't record the templates applied to a jail.
+        for (name, _) in conf.jails() {
+            jails.push(ManagerLabels {
+                name:     name.to_string(),
+                manager:  MANAGER.to_string(),
+                release:  release.clone(),
+                template: String::new(),
+                tags:     tags.clone(),
+            });
+        }
+    }
+
+    jails
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn bastille_read() {
+        // The broken jail is skipped.
+        let jails = read(Path::new("test-data/manager/bastille"));
+
+        let ok = vec![
+            ManagerLabels {
+                name:     "thick".into(),
+                manager:  "bastille".into(),
+                release:  "14.3-RELEASE-p2".into(),
+                template: "".into(),
+                tags:     "".into(),
+            },
+            ManagerLabels {
+                name:     "web".into(),
+                manager:  "bastille".into(),
+                release:  "14.3-RELEASE".into(),
+                template: "".into(),
+                tags:     "prod,web".into(),
+            },
+        ];
+
+        assert_eq!(jails, ok);
+    }
+}
@@ -0,0 +1,107 @@
+// iocage: Reads jails managed by iocage.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::errors::ExporterError;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use super::{
+    jail_dirs,
+    skip_jail,
+    ManagerLabels,
+};
+
+const MANAGER: &str = "iocage";
+
+// The parts of an iocage config.json that we're interested in.
+#[derive(Debug, Default, Deserialize)]
+struct IocageConfig {
+    #[serde(default)]
+    host_hostuuid: Option<String>,
+
+    #[serde(default)]
+    release: String,
+
+    // The template that the jail was created from, "none" if it wasn't.
+    #[serde(default)]
+    source_template: Option<String>,
+}
+
+// Reads the config.json at `path`.
+fn read_config(path: &Path) -> Result<IocageConfig, ExporterError> {
+    let reader = BufReader::new(File::open(path)?);
+    let config = serde_json::from_reader(reader)?;
+
+    Ok(config)
+}
+
+/// Reads the iocage jails under the given `root`.
+pub fn read(root: &Path) -> Vec<ManagerLabels> {
+    let mut jails = Vec::new();
+
+    for (dir_name, dir) in jail_dirs(root) {
+        let path = dir.join("config.json");
+
+        if !path.is_file() {
+            continue;
+        }
+
+        let config = match read_config(&path) {
+            Ok(config) => config,
+            Err(e) => {
+                skip_jail(&path, e);
+                continue;
+            },
+        };
+
+        let uuid = config.host_hostuuid.unwrap_or(dir_name);
+        let template = config.source_template
+            .filter(|template| template != "none")
+            .unwrap_or_default();
+
+        // iocage prefixes the names of the jails that it starts, replacing
+        // dots as they would otherwise indicate a hierarchical jail. It has
+        // no tags.
+        jails.push(ManagerLabels {
+            name:     format!("ioc-{}", uuid.replace('.', "_")),
+            manager:  MANAGER.to_string(),
+            release:  config.release,
+            template: template,
+            tags:     String::new(),
+        });
+    }
+
+    jails
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn iocage_read() {
+        // The broken jail is skipped.
+        let jails = read(Path::new("test-data/manager/iocage"));
+
+        let ok = vec![
+            ManagerLabels {
+                name:     "ioc-db".into(),
+                manager:  "iocage".into(),
+                release:  "14.3-RELEASE-p2".into(),
+                template: "db-base".into(),
+                tags:     "".into(),
+            },
+            ManagerLabels {
+                name:     "ioc-mail_example".into(),
+                manager:  "iocage".into(),
+                release:  "14.2-RELEASE".into(),
+                template: "".into(),
+                tags:     "".into(),
+            },
+        ];
+
+        assert_eq!(jails, ok);
+    }
+}
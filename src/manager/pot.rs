@@ -0,0 +1,84 @@
+// pot: Reads jails managed by pot.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use super::{
+    jail_dirs,
+    skip_jail,
+    ManagerLabels,
+};
+
+const MANAGER: &str = "pot";
+
+// Parses the key=value lines of a pot.conf.
+fn parse_pot_conf(input: &str) -> HashMap<&str, &str> {
+    input.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim().trim_matches('"')))
+        .collect()
+}
+
+/// Reads the pot jails under the given `root`.
+pub fn read(root: &Path) -> Vec<ManagerLabels> {
+    let mut jails = Vec::new();
+
+    for (name, dir) in jail_dirs(root) {
+        let path = dir.join("conf/pot.conf");
+
+        if !path.is_file() {
+            continue;
+        }
+
+        let input = match fs::read_to_string(&path) {
+            Ok(input) => input,
+            Err(e) => {
+                skip_jail(&path, e);
+                continue;
+            },
+        };
+
+        let conf = parse_pot_conf(&input);
+        let value = |key: &str| {
+            conf.get(key).copied().unwrap_or_default().to_string()
+        };
+
+        // pot.potbase is the pot that this one was cloned from. pot has no
+        // tags.
+        jails.push(ManagerLabels {
+            name:     name,
+            manager:  MANAGER.to_string(),
+            release:  value("osrelease"),
+            template: value("pot.potbase"),
+            tags:     String::new(),
+        });
+    }
+
+    jails
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn pot_read() {
+        let jails = read(Path::new("test-data/manager/pot"));
+
+        let ok = vec![
+            ManagerLabels {
+                name:     "dns".into(),
+                manager:  "pot".into(),
+                release:  "14.3".into(),
+                template: "base-dns".into(),
+                tags:     "".into(),
+            },
+        ];
+
+        assert_eq!(jails, ok);
+    }
+}
@@ -0,0 +1,300 @@
+// meta: This module exports labels taken from the key=value pairs held in the
+//       free-form meta and env jail parameters.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::register_info_with_registry;
+use crate::info::InfoFamily;
+use crate::jailsource::Jail;
+use crate::relabel::{
+    is_valid_label_name,
+    sanitize_label_name,
+    JailLabelSet,
+    Labels,
+    RESERVED_LABELS,
+};
+use parking_lot::Mutex;
+use prometheus_client::registry::Registry;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// Jail parameters holding key=value pairs. Where both hold the same key, the
+// value from meta is used.
+const PARAMS: [&str; 2] = ["env", "meta"];
+
+/// A key to take from the meta and env parameters, along with the label that
+/// it's exported as.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct MetaKey {
+    key:   String,
+    label: String,
+}
+
+impl TryFrom<String> for MetaKey {
+    type Error = String;
+
+    fn try_from(key: String) -> Result<Self, Self::Error> {
+        let label = sanitize_label_name(&key);
+
+        if !is_valid_label_name(&label) {
+            return Err(format!("invalid meta label key '{key}'"));
+        }
+
+        if RESERVED_LABELS.contains(&label.as_str()) {
+            return Err(format!("the {label} label can't be a meta label"));
+        }
+
+        let key = Self {
+            key:   key,
+            label: label,
+        };
+
+        Ok(key)
+    }
+}
+
+/// Configuration of the labels taken from the meta and env parameters.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetaConfig {
+    /// Keys to export as labels, with any characters that aren't valid in a
+    /// label name replaced by `_`. Other keys are ignored.
+    #[serde(default)]
+    pub keys: Vec<MetaKey>,
+
+    /// Add the labels to every time series of the jail, rather than only to
+    /// jail_meta_info.
+    #[serde(default)]
+    pub all_series: bool,
+}
+
+impl MetaConfig {
+    /// Checks that none of the keys are exported as one of the constant
+    /// labels `names`, which are already on every time series.
+    pub fn check_const_labels<'a, I>(&self, names: I) -> Result<(), String>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        for name in names {
+            if self.keys.iter().any(|key| key.label == name) {
+                return Err(format!(
+                    "the {name} label can't be both a constant label and a \
+                     meta label"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the key=value pairs held in a meta or env parameter.
+///
+/// Each line holds one pair, so values may contain spaces. Surrounding
+/// whitespace is trimmed, and lines that aren't key=value pairs are ignored.
+pub fn parse_pairs(value: &str) -> impl Iterator<Item = (&str, &str)> {
+    value.lines()
+        .filter_map(|line| line.trim().split_once('='))
+        .filter(|(key, _)| !key.is_empty())
+}
+
+/// Time series labelled from the meta and env parameters of jails.
+pub struct MetaMetrics {
+    config:    MetaConfig,
+    meta_info: InfoFamily<JailLabelSet>,
+
+    // The jail_meta_info label set of each jail, kept so that the old label
+    // set can be removed.
+    label_sets: Mutex<HashMap<String, JailLabelSet>>,
+}
+
+impl MetaMetrics {
+    /// Registers the meta time series with the given `registry`.
+    pub fn new(registry: &mut Registry) -> Self {
+        Self {
+            config: MetaConfig::default(),
+
+            meta_info: register_info_with_registry!(
+                "meta",
+                "Labels taken from the meta and env parameters of the named \
+                 jail",
+                Family<JailLabelSet>,
+                registry,
+            ),
+
+            label_sets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the keys to export, and where to export them.
+    pub fn set_config(&mut self, config: MetaConfig) {
+        self.config = config;
+    }
+
+    /// Returns true if the labels should be added to every time series of a
+    /// jail.
+    pub fn all_series(&self) -> bool {
+        self.config.all_series
+    }
+
+    /// Returns the labels taken from the meta and env parameters of the jail.
+    pub fn labels(&self, jail: &Jail) -> Labels {
+        let mut labels = Labels::new();
+
+        for param in PARAMS {
+            let Some(value) = jail.param(param) else {
+                continue;
+            };
+
+            for (key, value) in parse_pairs(value) {
+                let meta_key = self.config.keys.iter()
+                    .find(|meta_key| meta_key.key == key);
+
+                if let Some(meta_key) = meta_key {
+                    labels.insert(meta_key.label.clone(), value.to_string());
+                }
+            }
+        }
+
+        labels
+    }
+
+    /// Sets jail_meta_info for the given jail, if any keys are configured.
+    /// The meta labels are added to the label set of the jail, `labels`,
+    /// which already holds them if they're added to every time series.
+    pub fn update(&self, jail: &Jail, labels: &JailLabelSet) {
+        if self.config.keys.is_empty() {
+            return;
+        }
+
+        let meta_labels = labels.with_labels(self.labels(jail));
+        let mut label_sets = self.label_sets.lock();
+
+        if let Some(old) = label_sets.get(&jail.name) {
+            if *old != meta_labels {
+                self.meta_info.remove(old);
+            }
+        }
+
+        self.meta_info.insert(meta_labels.clone());
+        label_sets.insert(jail.name.clone(), meta_labels);
+    }
+
+    /// Removes jail_meta_info for the jail with the given `labels`.
+    pub fn remove(&self, labels: &JailLabelSet) {
+        if let Some(old) = self.label_sets.lock().remove(labels.name()) {
+            self.meta_info.remove(&old);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use prometheus_client::encoding::text::encode;
+
+    fn config(yaml: &str) -> MetaConfig {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn parse_pairs_ok() {
+        let input = "owner=Alice Smith\n  tier=gold \n\nnot a pair\n=x\nk=a=b";
+        let pairs: Vec<_> = parse_pairs(input).collect();
+
+        let ok = vec![
+            ("owner", "Alice Smith"),
+            ("tier", "gold"),
+            ("k", "a=b"),
+        ];
+
+        assert_eq!(pairs, ok);
+    }
+
+    #[test]
+    fn meta_config_invalid() {
+        let tests = [
+            "keys: [name]",
+            "keys: [state]",
+            "keys: [\"1st\"]",
+            "other: true",
+        ];
+
+        for yaml in tests {
+            assert!(serde_yaml::from_str::<MetaConfig>(yaml).is_err());
+        }
+    }
+
+    #[test]
+    fn meta_config_check_const_labels() {
+        let config = config("keys: [owner, host.name]");
+
+        assert!(config.check_const_labels(["datacenter"]).is_ok());
+        assert!(config.check_const_labels(["datacenter", "owner"]).is_err());
+        assert!(config.check_const_labels(["host_name"]).is_err());
+    }
+
+    #[test]
+    fn meta_metrics_labels() {
+        let mut registry = Registry::default();
+        let mut metrics = MetaMetrics::new(&mut registry);
+        metrics.set_config(config("keys: [owner, service.tier]"));
+
+        let jail = Jail::with_params(1, "www", &[
+            ("meta", "owner=alice\nservice.tier=gold\nother=x"),
+            ("env", "owner=bob"),
+        ]);
+
+        let ok = Labels::from([
+            ("owner".into(), "alice".into()),
+            ("service_tier".into(), "gold".into()),
+        ]);
+
+        assert_eq!(metrics.labels(&jail), ok);
+        assert_eq!(metrics.labels(&Jail::new(2, "db")), Labels::new());
+    }
+
+    #[test]
+    fn meta_metrics_update_and_remove() {
+        let mut registry = Registry::default();
+        let mut metrics = MetaMetrics::new(&mut registry);
+        metrics.set_config(config("keys: [owner]"));
+
+        let alice = Jail::with_params(1, "www", &[("meta", "owner=alice")]);
+        let bob = Jail::with_params(1, "www", &[("meta", "owner=bob")]);
+
+        let labels = &JailLabelSet::new("www", Labels::from([
+            ("team".into(), "web".into()),
+        ]));
+
+        metrics.update(&alice, labels);
+        metrics.update(&bob, labels);
+
+        let mut output = String::new();
+        encode(&mut output, &registry).unwrap();
+
+        assert!(output.contains(
+            "meta_info{name=\"www\",team=\"web\",owner=\"bob\"} 1\n"
+        ));
+        assert!(!output.contains("alice"));
+
+        metrics.remove(labels);
+
+        let mut output = String::new();
+        encode(&mut output, &registry).unwrap();
+
+        assert!(!output.contains("meta_info{"));
+    }
+
+    #[test]
+    fn meta_metrics_unconfigured() {
+        let mut registry = Registry::default();
+        let metrics = MetaMetrics::new(&mut registry);
+
+        let jail = Jail::with_params(1, "www", &[("meta", "owner=alice")]);
+        metrics.update(&jail, &"www".into());
+
+        assert!(metrics.label_sets.lock().is_empty());
+    }
+}
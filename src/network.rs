@@ -0,0 +1,482 @@
+// network: This module exports the network interface counters of jails with
+//          their own virtual network stack.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::command::CommandRunner;
+use crate::created::CreatedCounter;
+use crate::errors::ExporterError;
+use crate::jailsource::Jail;
+use crate::relabel::JailLabelSet;
+use parking_lot::Mutex;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::registry::{
+    Registry,
+    Unit,
+};
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use tracing::debug;
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct InterfaceLabels {
+    // Jail name, along with the labels added to the jail.
+    #[prometheus(flatten)]
+    jail: JailLabelSet,
+
+    // Network interface name, as seen within the jail.
+    interface: String,
+}
+
+/// Counters for a single network interface.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InterfaceStats {
+    /// Name of the interface.
+    pub interface: String,
+
+    /// Bytes received.
+    pub receive_bytes: u64,
+
+    /// Packets received.
+    pub receive_packets: u64,
+
+    /// Input errors.
+    pub receive_errors: u64,
+
+    /// Input packets dropped.
+    pub receive_drop: u64,
+
+    /// Bytes transmitted.
+    pub transmit_bytes: u64,
+
+    /// Packets transmitted.
+    pub transmit_packets: u64,
+
+    /// Output errors.
+    pub transmit_errors: u64,
+
+    /// Output packets dropped.
+    pub transmit_drop: u64,
+}
+
+/// A source of per-jail network interface counters.
+pub trait InterfaceReader {
+    /// Returns the counters of every interface within the given `jail`.
+    fn interfaces(&self, jail: &Jail)
+    -> Result<Vec<InterfaceStats>, ExporterError>;
+}
+
+/// An `InterfaceReader` backed by `netstat -j`, which reads the interfaces
+/// of a jail's network stack with the host's netstat(1).
+#[derive(Clone, Debug, Default)]
+pub struct NetstatReader<R> {
+    runner: R,
+}
+
+impl<R: CommandRunner> NetstatReader<R> {
+    /// Returns a `NetstatReader` running netstat(1) with `runner`.
+    pub fn new(runner: R) -> Self {
+        Self {
+            runner: runner,
+        }
+    }
+}
+
+impl<R: CommandRunner> InterfaceReader for NetstatReader<R> {
+    fn interfaces(&self, jail: &Jail)
+    -> Result<Vec<InterfaceStats>, ExporterError> {
+        let jid = jail.jid.to_string();
+        let args = ["-j", &jid, "-i", "-b", "-d", "-n", "-W"];
+        let output = self.runner.run("netstat", &args)?;
+
+        parse_netstat(&output)
+    }
+}
+
+// Parses the output of `netstat -i -b -d -n -W`, returning the counters from
+// the link level row of each interface.
+// The Address column is empty for some interfaces, so fields are matched up
+// with the header from the right.
+fn parse_netstat(input: &str) -> Result<Vec<InterfaceStats>, ExporterError> {
+    let mut lines = input.lines();
+
+    let header: Vec<&str> = lines.next()
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect();
+
+    if header.first() != Some(&"Name") {
+        let msg = format!("unexpected header: {:?}", header.join(" "));
+        return Err(ExporterError::NetstatError(msg));
+    }
+
+    let mut interfaces = Vec::new();
+
+    for line in lines {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+
+        // Only the link level rows carry the interface totals, the others
+        // are per address.
+        if !fields.get(2).is_some_and(|network| network.starts_with("<Link#")) {
+            continue;
+        }
+
+        let columns: HashMap<&str, &str> = header.iter()
+            .rev()
+            .zip(fields.iter().rev())
+            .map(|(column, field)| (*column, *field))
+            .collect();
+
+        // Counters not supported by the interface are output as "-".
+        let value = |column: &str| {
+            match columns.get(column) {
+                Some(&"-") => Ok(0),
+                Some(field) => field.parse::<u64>().map_err(|e| {
+                    let msg = format!("{column} {field:?}: {e}");
+                    ExporterError::NetstatError(msg)
+                }),
+                None => {
+                    let msg = format!("missing {column} column: {line:?}");
+                    Err(ExporterError::NetstatError(msg))
+                },
+            }
+        };
+
+        // Interfaces that are down have their names suffixed with '*'.
+        let interface = fields[0].trim_end_matches('*');
+
+        interfaces.push(InterfaceStats {
+            interface:        interface.to_string(),
+            receive_bytes:    value("Ibytes")?,
+            receive_packets:  value("Ipkts")?,
+            receive_errors:   value("Ierrs")?,
+            receive_drop:     value("Idrop")?,
+            transmit_bytes:   value("Obytes")?,
+            transmit_packets: value("Opkts")?,
+            transmit_errors:  value("Oerrs")?,
+            transmit_drop:    value("Drop")?,
+        });
+    }
+
+    Ok(interfaces)
+}
+
+// A counter of network interfaces, along with how it's exported.
+struct InterfaceCounter {
+    // Name of the time series, without the prefix or unit.
+    name: &'static str,
+
+    // Help of the time series.
+    help: &'static str,
+
+    // Unit of the time series, if it has one.
+    unit: Option<Unit>,
+
+    // Returns the value of the counter for an interface.
+    value: fn(&InterfaceStats) -> u64,
+}
+
+// Every interface counter, in order of name.
+static COUNTERS: [InterfaceCounter; 8] = [
+    InterfaceCounter {
+        name:  "network_receive",
+        help:  "Bytes received on the named jail's network interface",
+        unit:  Some(Unit::Bytes),
+        value: |stats| stats.receive_bytes,
+    },
+    InterfaceCounter {
+        name:  "network_receive_drop",
+        help:  "Input packets dropped on the named jail's network interface",
+        unit:  None,
+        value: |stats| stats.receive_drop,
+    },
+    InterfaceCounter {
+        name:  "network_receive_errors",
+        help:  "Input errors on the named jail's network interface",
+        unit:  None,
+        value: |stats| stats.receive_errors,
+    },
+    InterfaceCounter {
+        name:  "network_receive_packets",
+        help:  "Packets received on the named jail's network interface",
+        unit:  None,
+        value: |stats| stats.receive_packets,
+    },
+    InterfaceCounter {
+        name:  "network_transmit",
+        help:  "Bytes transmitted on the named jail's network interface",
+        unit:  Some(Unit::Bytes),
+        value: |stats| stats.transmit_bytes,
+    },
+    InterfaceCounter {
+        name:  "network_transmit_drop",
+        help:  "Output packets dropped on the named jail's network interface",
+        unit:  None,
+        value: |stats| stats.transmit_drop,
+    },
+    InterfaceCounter {
+        name:  "network_transmit_errors",
+        help:  "Output errors on the named jail's network interface",
+        unit:  None,
+        value: |stats| stats.transmit_errors,
+    },
+    InterfaceCounter {
+        name:  "network_transmit_packets",
+        help:  "Packets transmitted on the named jail's network interface",
+        unit:  None,
+        value: |stats| stats.transmit_packets,
+    },
+];
+
+/// Time series describing the network interfaces of VNET jails.
+pub struct NetworkMetrics {
+    // A family for each of the COUNTERS, in the same order.
+    families: Vec<Family<InterfaceLabels, CreatedCounter>>,
+
+    reader: Option<Box<dyn InterfaceReader + Send + Sync>>,
+
+    // The interfaces last seen in each jail, so that interfaces which are
+    // destroyed or moved out of the jail can be removed.
+    label_sets: Mutex<HashMap<String, Vec<InterfaceLabels>>>,
+}
+
+impl NetworkMetrics {
+    /// Registers the network time series with the given `registry`.
+    pub fn new(registry: &mut Registry) -> Self {
+        let families = COUNTERS.iter()
+            .map(|counter| {
+                let family = Family::default();
+
+                match &counter.unit {
+                    Some(unit) => registry.register_with_unit(
+                        counter.name,
+                        counter.help,
+                        unit.clone(),
+                        family.clone(),
+                    ),
+                    None => registry.register(
+                        counter.name,
+                        counter.help,
+                        family.clone(),
+                    ),
+                }
+
+                family
+            })
+            .collect();
+
+        Self {
+            families:   families,
+            reader:     None,
+            label_sets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Enables the collector, reading interface counters with `reader`.
+    pub fn set_reader<R>(&mut self, reader: R)
+    where
+        R: InterfaceReader + Send + Sync + 'static,
+    {
+        self.reader = Some(Box::new(reader));
+    }
+
+    /// Sets the network time series for the given jail, if it has its own
+    /// virtual network stack, with the given `labels`.
+    pub fn update(&self, jail: &Jail, labels: &JailLabelSet) {
+        let Some(reader) = &self.reader else {
+            return;
+        };
+
+        // Jails without a VNET share the host's interfaces, there's nothing
+        // of their own to export.
+        if jail.param("vnet") != Some("1") {
+            self.remove(labels);
+            return;
+        }
+
+        // A jail may be on its way out, taking its network stack with it.
+        // Don't fail the whole scrape because of this.
+        let interfaces = match reader.interfaces(jail) {
+            Ok(interfaces) => interfaces,
+            Err(e) => {
+                debug!("Couldn't get interfaces for {}: {e}", jail.name);
+                self.remove(labels);
+                return;
+            },
+        };
+
+        let mut label_sets = self.label_sets.lock();
+        let mut seen = Vec::with_capacity(interfaces.len());
+
+        for stats in &interfaces {
+            let interface_labels = InterfaceLabels {
+                jail:      labels.clone(),
+                interface: stats.interface.clone(),
+            };
+
+            // The counters are kept by the kernel, store them directly.
+            for (counter, family) in COUNTERS.iter().zip(&self.families) {
+                family
+                    .get_or_create(&interface_labels)
+                    .inner()
+                    .store((counter.value)(stats), Ordering::Relaxed);
+            }
+
+            seen.push(interface_labels);
+        }
+
+        if let Some(old) = label_sets.insert(jail.name.clone(), seen.clone()) {
+            for labels in old.iter().filter(|labels| !seen.contains(labels)) {
+                self.remove_labels(labels);
+            }
+        }
+    }
+
+    fn remove_labels(&self, labels: &InterfaceLabels) {
+        for family in &self.families {
+            family.remove(labels);
+        }
+    }
+
+    /// Removes the network time series for the jail with the given `labels`.
+    pub fn remove(&self, labels: &JailLabelSet) {
+        if let Some(old) = self.label_sets.lock().remove(labels.name()) {
+            for labels in &old {
+                self.remove_labels(labels);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const NETSTAT: &str = include_str!("../test-data/network/netstat.txt");
+
+    // Returns a canned interface table.
+    struct FixtureReader(Vec<InterfaceStats>);
+
+    impl InterfaceReader for FixtureReader {
+        fn interfaces(&self, _jail: &Jail)
+        -> Result<Vec<InterfaceStats>, ExporterError> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn interface(name: &str, receive_bytes: u64) -> InterfaceStats {
+        InterfaceStats {
+            interface:     name.into(),
+            receive_bytes: receive_bytes,
+            ..Default::default()
+        }
+    }
+
+    fn labels(name: &str, interface: &str) -> InterfaceLabels {
+        InterfaceLabels {
+            jail:      name.into(),
+            interface: interface.into(),
+        }
+    }
+
+    #[test]
+    fn parse_netstat_ok() {
+        let interfaces = parse_netstat(NETSTAT).unwrap();
+
+        let ok = vec![
+            InterfaceStats {
+                interface:        "lo0".into(),
+                receive_bytes:    200,
+                receive_packets:  4,
+                receive_errors:   0,
+                receive_drop:     0,
+                transmit_bytes:   200,
+                transmit_packets: 4,
+                transmit_errors:  0,
+                transmit_drop:    0,
+            },
+            InterfaceStats {
+                interface:        "epair0b".into(),
+                receive_bytes:    9_876_543,
+                receive_packets:  12_345,
+                receive_errors:   1,
+                receive_drop:     2,
+                transmit_bytes:   1_234_567,
+                transmit_packets: 6_789,
+                transmit_errors:  3,
+                transmit_drop:    4,
+            },
+            InterfaceStats {
+                interface:        "tun0".into(),
+                receive_bytes:    840,
+                receive_packets:  10,
+                receive_errors:   0,
+                receive_drop:     0,
+                transmit_bytes:   840,
+                transmit_packets: 10,
+                transmit_errors:  0,
+                transmit_drop:    0,
+            },
+        ];
+
+        assert_eq!(interfaces, ok);
+    }
+
+    #[test]
+    fn parse_netstat_invalid() {
+        let header = NETSTAT.lines().next().unwrap();
+        let tests = [
+            String::new(),
+            "lo0 16384 <Link#1> lo0 4 0 0 200 4 0 200 0 0".to_string(),
+            format!("{header}\nlo0 16384 <Link#1> lo0 4 0 0 nope 4 0 200 0 0"),
+        ];
+
+        for test in tests {
+            assert!(parse_netstat(&test).is_err());
+        }
+    }
+
+    #[test]
+    fn network_metrics_update() {
+        let mut registry = Registry::default();
+        let mut metrics = NetworkMetrics::new(&mut registry);
+        let table = vec![interface("lo0", 200), interface("epair0b", 1024)];
+        metrics.set_reader(FixtureReader(table));
+
+        let vnet = Jail::with_params(1, "vnet", &[("vnet", "1")]);
+        let shared = Jail::with_params(2, "shared", &[("vnet", "2")]);
+
+        metrics.update(&vnet, &"vnet".into());
+        metrics.update(&shared, &"shared".into());
+
+        let receive_bytes = &metrics.families[0];
+        let epair = labels("vnet", "epair0b");
+        assert_eq!(receive_bytes.get_or_create(&epair).get(), 1024);
+        assert!(receive_bytes.get(&labels("shared", "lo0")).is_none());
+
+        metrics.remove(&"vnet".into());
+        assert!(receive_bytes.get(&epair).is_none());
+    }
+
+    #[test]
+    fn network_metrics_update_removed_interface() {
+        let mut registry = Registry::default();
+        let mut metrics = NetworkMetrics::new(&mut registry);
+        let table = vec![interface("lo0", 200), interface("epair0b", 1024)];
+        metrics.set_reader(FixtureReader(table));
+
+        let jail = Jail::with_params(1, "vnet", &[("vnet", "1")]);
+        let jail_labels = JailLabelSet::from("vnet");
+        metrics.update(&jail, &jail_labels);
+
+        // The epair is moved out of the jail between scrapes.
+        metrics.set_reader(FixtureReader(vec![interface("lo0", 400)]));
+        metrics.update(&jail, &jail_labels);
+
+        let receive_bytes = &metrics.families[0];
+        let lo0 = labels("vnet", "lo0");
+        assert_eq!(receive_bytes.get_or_create(&lo0).get(), 400);
+        assert!(receive_bytes.get(&labels("vnet", "epair0b")).is_none());
+    }
+}
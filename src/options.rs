@@ -0,0 +1,370 @@
+// options: This module holds the options that are fixed once the exporter
+//          has registered its time series.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::collectors::Collectors;
+use crate::rctlstate::RctlState;
+use crate::relabel::{
+    is_valid_label_name,
+    RESERVED_LABELS,
+};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// The default prefix of the names of the time series.
+pub const DEFAULT_PREFIX: &str = "jail";
+
+/// A constant label, added to every time series.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConstLabel {
+    key:   String,
+    value: String,
+}
+
+impl ConstLabel {
+    /// Returns a `ConstLabel` with the given `key` and `value`, if the label
+    /// is valid.
+    ///
+    /// prometheus_client doesn't escape the values of constant labels, so
+    /// values that would need escaping aren't allowed.
+    pub fn new(key: &str, value: &str) -> Result<Self, String> {
+        if !is_valid_label_name(key) || key.starts_with("__") {
+            return Err(format!("invalid label name '{key}'"));
+        }
+
+        if RESERVED_LABELS.contains(&key) {
+            return Err(format!("the {key} label can't be a constant label"));
+        }
+
+        if value.contains(['"', '\\', '\n']) {
+            return Err(format!("invalid value for label '{key}'"));
+        }
+
+        let label = Self {
+            key:   key.to_string(),
+            value: value.to_string(),
+        };
+
+        Ok(label)
+    }
+}
+
+// Parses a key=value pair.
+impl FromStr for ConstLabel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((key, value)) = s.split_once('=') else {
+            return Err(format!("'{s}' is not a key=value pair"));
+        };
+
+        Self::new(key, value)
+    }
+}
+
+/// A set of constant labels, keyed on label name.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(try_from = "BTreeMap<String, String>")]
+pub struct ConstLabels {
+    labels: BTreeMap<String, String>,
+}
+
+impl TryFrom<BTreeMap<String, String>> for ConstLabels {
+    type Error = String;
+
+    fn try_from(labels: BTreeMap<String, String>) -> Result<Self, Self::Error> {
+        let mut const_labels = Self::default();
+
+        for (key, value) in labels {
+            const_labels.insert(ConstLabel::new(&key, &value)?);
+        }
+
+        Ok(const_labels)
+    }
+}
+
+impl ConstLabels {
+    /// Adds the label, replacing any existing label with the same key.
+    pub fn insert(&mut self, label: ConstLabel) {
+        self.labels.insert(label.key, label.value);
+    }
+
+    /// Returns the names of the labels.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.labels.keys().map(String::as_str)
+    }
+
+    // Returns the labels in the form that the Registry takes them.
+    fn registry_labels(&self)
+    -> impl Iterator<Item = (Cow<'static, str>, Cow<'static, str>)> + '_ {
+        self.labels.iter()
+            .map(|(key, value)| (key.clone().into(), value.clone().into()))
+    }
+}
+
+/// The scheme used to name the time series of the rctl(8) resources.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NamingScheme {
+    /// Names following the rctl(8) resource names, such as `pcpu_used`.
+    #[default]
+    Legacy,
+
+    /// Names following the Prometheus naming conventions, in base units,
+    /// such as `cpu_usage_ratio`.
+    Conventional,
+}
+
+impl NamingScheme {
+    /// Returns the name for this scheme, from the `legacy` and
+    /// `conventional` names of a time series.
+    pub fn pick<'a>(self, legacy: &'a str, conventional: &'a str) -> &'a str {
+        match self {
+            Self::Legacy       => legacy,
+            Self::Conventional => conventional,
+        }
+    }
+}
+
+impl fmt::Display for NamingScheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scheme = match self {
+            Self::Legacy       => "legacy",
+            Self::Conventional => "conventional",
+        };
+
+        write!(f, "{scheme}")
+    }
+}
+
+impl FromStr for NamingScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "legacy"       => Ok(Self::Legacy),
+            "conventional" => Ok(Self::Conventional),
+            _              => Err(format!("unknown naming scheme '{s}'")),
+        }
+    }
+}
+
+/// Returns true if the given `prefix` is valid at the start of a metric name.
+pub fn is_valid_prefix(prefix: &str) -> bool {
+    // Metric names follow the same rules as label names, other than also
+    // allowing colons, which are reserved for recording rules.
+    is_valid_label_name(prefix) && !prefix.starts_with("__")
+}
+
+/// Options that must be given when the exporter is created, as they affect
+/// how its time series are registered.
+#[derive(Clone, Debug)]
+pub struct ExporterOptions {
+    collectors:   Collectors,
+    const_labels: ConstLabels,
+    naming:       NamingScheme,
+    prefix:       String,
+    rctl:         bool,
+    rctl_state:   RctlState,
+}
+
+impl Default for ExporterOptions {
+    fn default() -> Self {
+        Self {
+            collectors:   Collectors::default(),
+            const_labels: ConstLabels::default(),
+            naming:       NamingScheme::default(),
+            prefix:       DEFAULT_PREFIX.into(),
+            rctl:         true,
+            rctl_state:   RctlState::default(),
+        }
+    }
+}
+
+// Implements a builder pattern for configuring the options.
+impl ExporterOptions {
+    /// Returns the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only export the time series of the enabled `collectors`.
+    #[must_use]
+    pub fn collectors(mut self, collectors: Collectors) -> Self {
+        self.collectors = collectors;
+        self
+    }
+
+    /// Add the given constant labels to every time series.
+    #[must_use]
+    pub fn const_labels(mut self, labels: ConstLabels) -> Self {
+        self.const_labels = labels;
+        self
+    }
+
+    /// Name the rctl time series according to the given `naming` scheme.
+    #[must_use]
+    pub fn naming(mut self, naming: NamingScheme) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    /// Prefix the names of the time series with the given `prefix`, rather
+    /// than `jail`.
+    #[must_use]
+    pub fn prefix<P: Into<String>>(mut self, prefix: P) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Query rctl(8) for the resource usage and rules of jails. Without
+    /// this, only the time series that don't depend on RACCT/RCTL are
+    /// exported.
+    #[must_use]
+    pub fn rctl(mut self, enabled: bool) -> Self {
+        self.rctl = enabled;
+        self
+    }
+
+    /// Report the given RACCT/RCTL `state` of the kernel.
+    #[must_use]
+    pub fn rctl_state(mut self, state: RctlState) -> Self {
+        self.rctl_state = state;
+        self
+    }
+
+    /// Returns the enabled collectors.
+    pub fn enabled_collectors(&self) -> &Collectors {
+        &self.collectors
+    }
+
+    /// Returns the naming scheme of the rctl time series.
+    pub fn naming_scheme(&self) -> NamingScheme {
+        self.naming
+    }
+
+    /// Returns true if rctl(8) is queried for the resource usage and rules of
+    /// jails.
+    pub fn rctl_enabled(&self) -> bool {
+        self.rctl
+    }
+
+    /// Returns the RACCT/RCTL state of the kernel.
+    pub fn racct_state(&self) -> RctlState {
+        self.rctl_state
+    }
+
+    /// Returns the prefix of the names of the time series.
+    pub fn metric_prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Returns the constant labels, in the form that the Registry takes them.
+    pub fn registry_labels(&self)
+    -> impl Iterator<Item = (Cow<'static, str>, Cow<'static, str>)> + '_ {
+        self.const_labels.registry_labels()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn const_label_from_str() {
+        let label: ConstLabel = "datacenter=eu-west-1".parse().unwrap();
+        assert_eq!(label, ConstLabel::new("datacenter", "eu-west-1").unwrap());
+
+        // Values may be empty or contain =.
+        assert!("empty=".parse::<ConstLabel>().is_ok());
+        assert!("k=a=b".parse::<ConstLabel>().is_ok());
+    }
+
+    #[test]
+    fn const_label_invalid() {
+        let tests = [
+            "novalue",
+            "=value",
+            "1abc=value",
+            "__reserved=value",
+            "name=value",
+            "quote=a\"b",
+            "backslash=a\\b",
+        ];
+
+        for input in tests {
+            assert!(input.parse::<ConstLabel>().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn const_label_reserved() {
+        for key in RESERVED_LABELS {
+            assert_eq!(
+                ConstLabel::new(key, "value"),
+                Err(format!("the {key} label can't be a constant label")),
+            );
+        }
+
+        assert!(ConstLabel::new("datacenter", "dc1").is_ok());
+    }
+
+    #[test]
+    fn naming_scheme_from_str() {
+        let tests = [
+            ("legacy",       Ok(NamingScheme::Legacy)),
+            ("conventional", Ok(NamingScheme::Conventional)),
+            ("other",        Err("unknown naming scheme 'other'".into())),
+        ];
+
+        for (input, ok) in tests {
+            assert_eq!(input.parse::<NamingScheme>(), ok);
+        }
+    }
+
+    #[test]
+    fn naming_scheme_pick() {
+        let legacy = NamingScheme::Legacy.pick("readbps", "read_bytes");
+        let conventional = NamingScheme::Conventional
+            .pick("readbps", "read_bytes");
+
+        assert_eq!(legacy, "readbps");
+        assert_eq!(conventional, "read_bytes");
+    }
+
+    #[test]
+    fn is_valid_prefix_ok() {
+        let tests = [
+            ("jail",        true),
+            ("my_jail",     true),
+            ("_jail",       true),
+            ("",            false),
+            ("1jail",       false),
+            ("jail-stats",  false),
+            ("__jail",      false),
+        ];
+
+        for (input, ok) in tests {
+            assert_eq!(is_valid_prefix(input), ok, "{input}");
+        }
+    }
+
+    #[test]
+    fn const_labels_deserialize() {
+        let labels: ConstLabels = serde_yaml::from_str(
+            "host_role: web\ndatacenter: dc1",
+        ).unwrap();
+
+        let registry_labels: Vec<_> = labels.registry_labels().collect();
+        let ok: Vec<(Cow<str>, Cow<str>)> = vec![
+            ("datacenter".into(), "dc1".into()),
+            ("host_role".into(), "web".into()),
+        ];
+
+        assert_eq!(registry_labels, ok);
+        assert!(serde_yaml::from_str::<ConstLabels>("name: www").is_err());
+    }
+}
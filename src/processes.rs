@@ -0,0 +1,476 @@
+// processes: This module exports the number of processes within each jail,
+//            broken down by process state, and when each jail started.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::register_gauge_with_registry;
+use crate::errors::ExporterError;
+use crate::jailsource::Jail;
+use crate::relabel::JailLabelSet;
+use parking_lot::Mutex;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::{
+    family::Family,
+    gauge::Gauge,
+};
+use prometheus_client::registry::{
+    Registry,
+    Unit,
+};
+use std::collections::HashMap;
+
+#[cfg(target_os = "freebsd")]
+use crate::kernproc;
+
+// Process states, as given by the ki_stat of a kinfo_proc. These are the S*
+// constants in <sys/proc.h>.
+#[cfg(any(target_os = "freebsd", test))]
+mod ki_stat {
+    pub const SRUN: u8   = 2;
+    pub const SSLEEP: u8 = 3;
+    pub const SSTOP: u8  = 4;
+    pub const SZOMB: u8  = 5;
+    pub const SWAIT: u8  = 6;
+    pub const SLOCK: u8  = 7;
+}
+
+// Flag of a thread sleeping interruptibly, TDF_SINTR in <sys/proc.h>.
+#[cfg(target_os = "freebsd")]
+const TDF_SINTR: libc::c_long = 0x0000_0008;
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct StateLabels {
+    // Jail name, along with the labels added to the jail.
+    #[prometheus(flatten)]
+    jail: JailLabelSet,
+
+    // Process state.
+    state: String,
+}
+
+/// The state of a process, grouped as by the first character of the ps(1)
+/// `state` keyword.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum ProcessState {
+    /// Runnable (R).
+    Runnable,
+
+    /// Sleeping, whether for less than about 20 seconds (S) or longer (I).
+    Sleeping,
+
+    /// Stopped (T).
+    Stopped,
+
+    /// Waiting non-interruptibly (D), on a lock (L) or an idle interrupt
+    /// thread (W).
+    Waiting,
+
+    /// Exited and waiting to be reaped by its parent (Z).
+    Zombie,
+}
+
+impl ProcessState {
+    /// Every process state, in the order that they're exported.
+    pub const ALL: [Self; 5] = [
+        Self::Runnable,
+        Self::Sleeping,
+        Self::Stopped,
+        Self::Waiting,
+        Self::Zombie,
+    ];
+
+    /// Returns the value of the `state` label for this state.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Runnable => "runnable",
+            Self::Sleeping => "sleeping",
+            Self::Stopped  => "stopped",
+            Self::Waiting  => "waiting",
+            Self::Zombie   => "zombie",
+        }
+    }
+
+    // Returns the state of a process from the ki_stat of its kinfo_proc,
+    // and whether its thread is sleeping interruptibly. Processes still being
+    // created have no state worth exporting.
+    #[cfg(any(target_os = "freebsd", test))]
+    fn from_kinfo(stat: u8, interruptible: bool) -> Option<Self> {
+        use ki_stat::*;
+
+        let state = match stat {
+            SRUN                    => Self::Runnable,
+            SSLEEP if interruptible => Self::Sleeping,
+            SSLEEP | SWAIT | SLOCK  => Self::Waiting,
+            SSTOP                   => Self::Stopped,
+            SZOMB                   => Self::Zombie,
+            _                       => return None,
+        };
+
+        Some(state)
+    }
+}
+
+/// A process from the kernel process table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Process {
+    /// ID of the jail that the process is running in, 0 for the host.
+    pub jid: i32,
+
+    /// State of the process.
+    pub state: ProcessState,
+
+    /// When the process started, in seconds since the Unix epoch.
+    pub start_time: i64,
+}
+
+/// A source of the kernel process table.
+pub trait ProcessReader {
+    /// Returns every process on the system.
+    fn processes(&self) -> Result<Vec<Process>, ExporterError>;
+}
+
+/// A `ProcessReader` backed by the `kern.proc` sysctl.
+#[cfg(target_os = "freebsd")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct KernProcReader;
+
+#[cfg(target_os = "freebsd")]
+impl ProcessReader for KernProcReader {
+    fn processes(&self) -> Result<Vec<Process>, ExporterError> {
+        let processes = kernproc::processes()
+            .map_err(|e| ExporterError::ProcessError(e.to_string()))?
+            .into_iter()
+            .filter_map(|kinfo| {
+                let interruptible = kinfo.tdflags & TDF_SINTR != 0;
+                let state = ProcessState::from_kinfo(kinfo.stat, interruptible)?;
+
+                Some(Process {
+                    jid:        kinfo.jid,
+                    state:      state,
+                    start_time: kinfo.start,
+                })
+            })
+            .collect();
+
+        Ok(processes)
+    }
+}
+
+// Counts the processes in each state, per jail ID.
+fn count_states(processes: &[Process])
+-> HashMap<i32, HashMap<ProcessState, i64>> {
+    let mut counts: HashMap<i32, HashMap<ProcessState, i64>> = HashMap::new();
+
+    for process in processes {
+        *counts.entry(process.jid)
+            .or_default()
+            .entry(process.state)
+            .or_default() += 1;
+    }
+
+    counts
+}
+
+// Finds the start time of the oldest process, per jail ID.
+fn oldest_processes(processes: &[Process]) -> HashMap<i32, i64> {
+    let mut oldest: HashMap<i32, i64> = HashMap::new();
+
+    for process in processes {
+        oldest.entry(process.jid)
+            .and_modify(|start| *start = (*start).min(process.start_time))
+            .or_insert(process.start_time);
+    }
+
+    oldest
+}
+
+/// Time series describing the processes within jails.
+pub struct ProcessMetrics {
+    processes: Family<StateLabels, Gauge>,
+}
+
+impl ProcessMetrics {
+    /// Registers the process time series with the given `registry`.
+    pub fn new(registry: &mut Registry) -> Self {
+        Self {
+            processes: register_gauge_with_registry!(
+                "processes",
+                "Number of processes within the named jail, by state",
+                StateLabels,
+                registry,
+            ),
+        }
+    }
+
+    /// Sets the process time series for the given jails, with the label set
+    /// of each jail, from the process table. Every state is exported for
+    /// every jail, so that states without processes read 0.
+    ///
+    /// Without the process table, there's nothing current to export, so the
+    /// time series of the jails are removed.
+    pub fn update(
+        &self,
+        jails: &[(Jail, JailLabelSet)],
+        processes: Option<&[Process]>,
+    ) {
+        let Some(processes) = processes else {
+            for (_, labels) in jails {
+                self.remove(labels);
+            }
+
+            return;
+        };
+        let counts = count_states(processes);
+
+        for (jail, labels) in jails {
+            let states = counts.get(&jail.jid);
+
+            for state in ProcessState::ALL {
+                let count = states
+                    .and_then(|states| states.get(&state))
+                    .copied()
+                    .unwrap_or_default();
+
+                let labels = &StateLabels {
+                    jail:  labels.clone(),
+                    state: state.as_str().to_string(),
+                };
+
+                self.processes.get_or_create(labels).set(count);
+            }
+        }
+    }
+
+    /// Removes the process time series for the jail with the given
+    /// `labels`.
+    pub fn remove(&self, labels: &JailLabelSet) {
+        for state in ProcessState::ALL {
+            let labels = &StateLabels {
+                jail:  labels.clone(),
+                state: state.as_str().to_string(),
+            };
+
+            self.processes.remove(labels);
+        }
+    }
+}
+
+/// Time series describing when jails started.
+pub struct StartTimeMetrics {
+    start_time: Family<JailLabelSet, Gauge>,
+
+    // The jail ID and start time last exported for each jail. The oldest
+    // process may exit, or a persistent jail may run without any processes,
+    // but while the jail ID is unchanged, the jail hasn't restarted.
+    start_times: Mutex<HashMap<String, (i32, i64)>>,
+}
+
+impl StartTimeMetrics {
+    /// Registers the start time series with the given `registry`.
+    pub fn new(registry: &mut Registry) -> Self {
+        Self {
+            start_time: register_gauge_with_registry!(
+                "start_time",
+                "Start time of the named jail since the Unix epoch, taken \
+                 from its oldest process, or from when the jail was first \
+                 seen if none of its processes could be read, in seconds",
+                JailLabelSet,
+                Unit::Seconds,
+                registry,
+            ),
+
+            start_times: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the start time of the given jails, with the label set of each
+    /// jail, from the process table if it could be read.
+    ///
+    /// Jails first seen without any processes that could be read are taken
+    /// to have started at `now`, in seconds since the Unix epoch.
+    pub fn update(
+        &self,
+        jails: &[(Jail, JailLabelSet)],
+        processes: Option<&[Process]>,
+        now: i64,
+    ) {
+        let oldest = processes.map(oldest_processes).unwrap_or_default();
+        let mut start_times = self.start_times.lock();
+
+        for (jail, labels) in jails {
+            // Keep the earliest start time seen for this jail ID.
+            let previous = start_times.get(&jail.name)
+                .filter(|(jid, _)| *jid == jail.jid)
+                .map(|(_, start)| *start);
+
+            let start = match (previous, oldest.get(&jail.jid).copied()) {
+                (Some(previous), Some(oldest)) => previous.min(oldest),
+                (previous, oldest) => previous.or(oldest).unwrap_or(now),
+            };
+
+            self.start_time.get_or_create(labels).set(start);
+            start_times.insert(jail.name.clone(), (jail.jid, start));
+        }
+    }
+
+    /// Removes the start time series for the jail with the given `labels`.
+    pub fn remove(&self, labels: &JailLabelSet) {
+        self.start_time.remove(labels);
+        self.start_times.lock().remove(labels.name());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn process(jid: i32, state: ProcessState) -> Process {
+        Process {
+            jid:        jid,
+            state:      state,
+            start_time: 1000,
+        }
+    }
+
+    fn started(jid: i32, start_time: i64) -> Process {
+        Process {
+            start_time: start_time,
+            ..process(jid, ProcessState::Sleeping)
+        }
+    }
+
+    fn labels(name: &str, state: &str) -> StateLabels {
+        StateLabels {
+            jail:  name.into(),
+            state: state.into(),
+        }
+    }
+
+    #[test]
+    fn process_state_from_kinfo() {
+        use ProcessState::*;
+        use ki_stat::*;
+
+        let tests = [
+            (SRUN, false, Some(Runnable)),
+            (SSLEEP, true, Some(Sleeping)),
+            (SSLEEP, false, Some(Waiting)),
+            (SSTOP, false, Some(Stopped)),
+            (SZOMB, false, Some(Zombie)),
+            (SWAIT, false, Some(Waiting)),
+            (SLOCK, false, Some(Waiting)),
+            (1, false, None),
+        ];
+
+        for (stat, interruptible, ok) in tests {
+            assert_eq!(ProcessState::from_kinfo(stat, interruptible), ok);
+        }
+    }
+
+    #[test]
+    fn process_metrics_update() {
+        use ProcessState::*;
+
+        let mut registry = Registry::default();
+        let metrics = ProcessMetrics::new(&mut registry);
+        let processes = [
+            process(0, Runnable),
+            process(1, Runnable),
+            process(1, Sleeping),
+            process(1, Sleeping),
+            process(1, Zombie),
+            process(1, Zombie),
+            process(1, Zombie),
+            process(2, Stopped),
+        ];
+
+        let jails = [
+            (Jail::new(1, "one"), "one".into()),
+            (Jail::new(2, "two"), "two".into()),
+        ];
+
+        metrics.update(&jails, Some(&processes));
+
+        let get = |name: &str, state: &str| {
+            metrics.processes.get_or_create(&labels(name, state)).get()
+        };
+
+        assert_eq!(get("one", "runnable"), 1);
+        assert_eq!(get("one", "sleeping"), 2);
+        assert_eq!(get("one", "zombie"), 3);
+        assert_eq!(get("one", "stopped"), 0);
+        assert_eq!(get("two", "stopped"), 1);
+        assert_eq!(get("two", "zombie"), 0);
+
+        metrics.remove(&"one".into());
+        assert!(metrics.processes.get(&labels("one", "zombie")).is_none());
+        assert!(metrics.processes.get(&labels("two", "stopped")).is_some());
+    }
+
+    #[test]
+    fn process_metrics_update_failed() {
+        let mut registry = Registry::default();
+        let metrics = ProcessMetrics::new(&mut registry);
+        let jails = [(Jail::new(1, "test"), "test".into())];
+
+        metrics.update(&jails, Some(&[started(1, 100)]));
+        assert!(metrics.processes.get(&labels("test", "sleeping")).is_some());
+
+        // Without the process table, the stale time series are removed.
+        metrics.update(&jails, None);
+        assert!(metrics.processes.get(&labels("test", "sleeping")).is_none());
+    }
+
+    #[test]
+    fn start_time_metrics_update() {
+        let mut registry = Registry::default();
+        let metrics = StartTimeMetrics::new(&mut registry);
+        let labels = &JailLabelSet::from("test");
+        let jails = [(Jail::new(1, "test"), labels.clone())];
+        let get = || metrics.start_time.get_or_create(labels).get();
+
+        metrics.update(&jails, Some(&[started(1, 200), started(1, 100)]), 500);
+        assert_eq!(get(), 100);
+
+        // The oldest process exits, but the jail hasn't restarted.
+        metrics.update(&jails, Some(&[started(1, 200)]), 500);
+        assert_eq!(get(), 100);
+
+        // A persistent jail without processes keeps its start time, as it
+        // does when the process table can't be read.
+        metrics.update(&jails, Some(&[]), 500);
+        assert_eq!(get(), 100);
+        metrics.update(&jails, None, 500);
+        assert_eq!(get(), 100);
+
+        // Restarted under a new jail ID.
+        let restarted = [(Jail::new(2, "test"), labels.clone())];
+        metrics.update(&restarted, Some(&[started(2, 300)]), 500);
+        assert_eq!(get(), 300);
+
+        metrics.remove(labels);
+        assert!(metrics.start_time.get(labels).is_none());
+        assert!(metrics.start_times.lock().is_empty());
+    }
+
+    #[test]
+    fn start_time_metrics_first_seen() {
+        let mut registry = Registry::default();
+        let metrics = StartTimeMetrics::new(&mut registry);
+        let labels = &JailLabelSet::from("test");
+        let jails = [(Jail::new(1, "test"), labels.clone())];
+        let get = || metrics.start_time.get_or_create(labels).get();
+
+        // Without any processes to read, the jail started when it was first
+        // seen.
+        metrics.update(&jails, None, 500);
+        assert_eq!(get(), 500);
+
+        metrics.update(&jails, Some(&[]), 600);
+        assert_eq!(get(), 500);
+
+        // Processes older than that are taken as the start of the jail.
+        metrics.update(&jails, Some(&[started(1, 400)]), 700);
+        assert_eq!(get(), 400);
+    }
+}
@@ -2,13 +2,15 @@
 #![deny(missing_docs)]
 use crate::errors::ExporterError;
 use crate::rctlstate::RctlState;
+use rctl::Filter;
 use tracing::debug;
 
-// Checks for the availability of RACCT/RCTL in the kernel.
-pub fn is_available() -> Result<(), ExporterError> {
-    debug!("Checking RACCT/RCTL status");
+// Checks whether the resource usage of jails can be queried in the given
+// RACCT/RCTL `state`.
+pub fn is_available(state: RctlState) -> Result<(), ExporterError> {
+    debug!("Checking RACCT/RCTL status: {}", state.as_str());
 
-    match RctlState::check() {
+    match state {
         RctlState::Disabled => {
             Err(ExporterError::RctlUnavailable(
                 "Present, but disabled; enable using \
@@ -17,12 +19,18 @@ pub fn is_available() -> Result<(), ExporterError> {
         },
         RctlState::Enabled => Ok(()),
         RctlState::Jailed => {
-            // This isn't strictly true. Jail exporter should be able to run
-            // within a jail, for situations where a user has jails within
-            // jails. It is just untested at the moment.
-            Err(ExporterError::RctlUnavailable(
-                "Jail Exporter cannot run within a jail".to_owned()
-            ))
+            // Running within a jail is supported for hosts with jails within
+            // jails, as long as the parent jail is allowed to query RCTL.
+            // Probe RCTL to find out.
+            if Filter::new().rules().is_err() {
+                return Err(ExporterError::RctlUnavailable(
+                    "Running within a jail that cannot query RCTL".to_owned()
+                ));
+            }
+
+            debug!("Running within a jail, only child jails will be exported");
+
+            Ok(())
         },
         RctlState::NotPresent => {
             Err(ExporterError::RctlUnavailable(
@@ -1,31 +1,49 @@
 // rctlstate: Module that quickly checks if rctl is available.
 //            This should be temporary while the upstream rctl module doesn't
 //            have FreeBSD 13 support.
+#[cfg(target_os = "freebsd")]
 use sysctl::{
     Ctl,
     CtlValue,
     Sysctl,
 };
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum RctlState {
     Disabled,
+    #[default]
     Enabled,
     Jailed,
     NotPresent,
 }
 
+#[cfg(target_os = "freebsd")]
 const CTL_KERN_RACCT_ENABLE: &str    = "kern.racct.enable";
+#[cfg(target_os = "freebsd")]
 const CTL_SECURITY_JAIL_JAILED: &str = "security.jail.jailed";
 
 impl RctlState {
-    pub fn check() -> Self {
-        // Quick check to see if we're in a jail
-        if Self::jailed() {
-            return Self::Jailed;
+    // Every state, in the order that they're exported.
+    pub const ALL: [Self; 4] = [
+        Self::Disabled,
+        Self::Enabled,
+        Self::Jailed,
+        Self::NotPresent,
+    ];
+
+    // The name of the state, as given in the state label.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Disabled   => "disabled",
+            Self::Enabled    => "enabled",
+            Self::Jailed     => "jailed",
+            Self::NotPresent => "not_present",
         }
+    }
 
-        // Now check for RCTL being available
+    #[cfg(target_os = "freebsd")]
+    pub fn check() -> Self {
+        // Check for RCTL being available
         let res = Ctl::new(CTL_KERN_RACCT_ENABLE);
 
         // If any error occurs, we assume RCTL is not present
@@ -38,7 +56,16 @@ impl RctlState {
                 // FreeBSD 13+ returns a U8 as the kernel variable is bool.
                 // FreeBSD older than 13 returns a Uint as the kernel
                 // variable is an int.
-                CtlValue::U8(1) | CtlValue::Uint(1) => Self::Enabled,
+                // If we're within a jail, RACCT is visible to us, but we may
+                // only be able to see our child jails.
+                CtlValue::U8(1) | CtlValue::Uint(1) => {
+                    if Self::jailed() {
+                        Self::Jailed
+                    }
+                    else {
+                        Self::Enabled
+                    }
+                },
 
                 // Anything else, it's disabled
                 _ => Self::Disabled,
@@ -50,6 +77,7 @@ impl RctlState {
         }
     }
 
+    #[cfg(target_os = "freebsd")]
     fn jailed() -> bool {
         let res = Ctl::new(CTL_SECURITY_JAIL_JAILED);
 
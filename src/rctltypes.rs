@@ -0,0 +1,152 @@
+// rctltypes: This module provides the resources, actions and subjects of
+//            rctl(8) rules. On FreeBSD these are the types of the rctl crate.
+//            The rctl crate only builds on FreeBSD, so elsewhere a minimal
+//            stand-in is provided, enough for the exporter and its tests to
+//            build.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+
+#[cfg(target_os = "freebsd")]
+pub use rctl::{
+    Action,
+    Resource,
+    SubjectType,
+};
+
+#[cfg(not(target_os = "freebsd"))]
+pub use self::standin::{
+    Action,
+    Resource,
+    SubjectType,
+};
+
+#[cfg(not(target_os = "freebsd"))]
+mod standin {
+    use std::fmt;
+    use std::str::FromStr;
+    use thiserror::Error;
+
+    /// Raised when parsing a name that the stand-in doesn't know.
+    #[derive(Clone, Debug, Error, PartialEq, Eq)]
+    #[error("Unknown {0}: {1}")]
+    pub struct ParseError(&'static str, String);
+
+    // Defines a fieldless enum along with the names rctl(8) uses for each
+    // variant.
+    macro_rules! rctl_enum {
+        ($(#[$meta:meta])* $kind:literal, $enum:ident {
+            $($variant:ident => $name:literal,)+
+        }) => {
+            $(#[$meta])*
+            #[derive(Clone, Copy, Debug, PartialEq, Hash, Eq)]
+            pub enum $enum {
+                $(
+                    #[allow(missing_docs)]
+                    $variant,
+                )+
+            }
+
+            impl $enum {
+                /// Returns the name used by rctl(8).
+                pub fn as_str(&self) -> &'static str {
+                    match self {
+                        $(Self::$variant => $name,)+
+                    }
+                }
+            }
+
+            impl FromStr for $enum {
+                type Err = ParseError;
+
+                fn from_str(s: &str) -> Result<Self, Self::Err> {
+                    match s {
+                        $($name => Ok(Self::$variant),)+
+                        _ => Err(ParseError($kind, s.into())),
+                    }
+                }
+            }
+
+            impl fmt::Display for $enum {
+                fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    write!(f, "{}", self.as_str())
+                }
+            }
+        };
+    }
+
+    rctl_enum! {
+        /// A resource accounted by RACCT, as listed in rctl(8).
+        "resource", Resource {
+            CpuTime         => "cputime",
+            DataSize        => "datasize",
+            StackSize       => "stacksize",
+            CoreDumpSize    => "coredumpsize",
+            MemoryUse       => "memoryuse",
+            MemoryLocked    => "memorylocked",
+            MaxProcesses    => "maxproc",
+            OpenFiles       => "openfiles",
+            VMemoryUse      => "vmemoryuse",
+            PseudoTerminals => "pseudoterminals",
+            SwapUse         => "swapuse",
+            NThreads        => "nthr",
+            MsgqQueued      => "msgqqueued",
+            MsgqSize        => "msgqsize",
+            NMsgq           => "nmsgq",
+            Nsem            => "nsem",
+            NSemop          => "nsemop",
+            NShm            => "nshm",
+            ShmSize         => "shmsize",
+            Wallclock       => "wallclock",
+            PercentCpu      => "pcpu",
+            ReadBps         => "readbps",
+            WriteBps        => "writebps",
+            ReadIops        => "readiops",
+            WriteIops       => "writeiops",
+        }
+    }
+
+    rctl_enum! {
+        /// The action taken when a rule is matched. Signal actions are left
+        /// out, as the signals rctl(8) uses don't all exist here.
+        "action", Action {
+            Deny     => "deny",
+            Log      => "log",
+            DevCtl   => "devctl",
+            Throttle => "throttle",
+        }
+    }
+
+    rctl_enum! {
+        /// The type of the subject of a rule.
+        "subject type", SubjectType {
+            Process    => "process",
+            Jail       => "jail",
+            User       => "user",
+            LoginClass => "loginclass",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn resource_from_str() {
+        let resource = "nthr".parse::<Resource>().unwrap();
+
+        assert_eq!(resource, Resource::NThreads);
+        assert_eq!(resource.to_string(), "nthr");
+        assert!("nope".parse::<Resource>().is_err());
+    }
+
+    #[test]
+    fn subject_type_from_str() {
+        let subject = "loginclass".parse::<SubjectType>().unwrap();
+
+        assert_eq!(subject, SubjectType::LoginClass);
+        assert_eq!(subject.to_string(), "loginclass");
+        assert!("group".parse::<SubjectType>().is_err());
+    }
+}
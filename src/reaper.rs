@@ -0,0 +1,154 @@
+// reaper: This module decides when the time series of jails that have
+//         disappeared should be removed.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+use std::fmt;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+/// How long the time series of a jail are kept after the jail disappears.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReapAfter {
+    /// Reap once the jail has been missing for more than this many scrapes.
+    Scrapes(u32),
+
+    /// Reap once the jail has been missing for at least this long.
+    Duration(Duration),
+}
+
+impl Default for ReapAfter {
+    // Reap on the first scrape that a jail is missing from.
+    fn default() -> Self {
+        Self::Scrapes(0)
+    }
+}
+
+impl fmt::Display for ReapAfter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Scrapes(scrapes)   => write!(f, "{scrapes} scrapes"),
+            Self::Duration(duration) => write!(f, "{}s", duration.as_secs()),
+        }
+    }
+}
+
+// A jail missing from the latest scrapes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Missing {
+    // Number of consecutive scrapes that the jail has been missing from.
+    scrapes: u32,
+
+    // When the jail was first found to be missing.
+    since: Instant,
+}
+
+/// Tracks jails that have disappeared until their time series are due to be
+/// reaped.
+#[derive(Debug, Default)]
+pub struct Reaper {
+    reap_after: ReapAfter,
+    missing:    HashMap<String, Missing>,
+}
+
+impl Reaper {
+    /// Returns a `Reaper` following the given `reap_after` policy.
+    pub fn new(reap_after: ReapAfter) -> Self {
+        Self {
+            reap_after: reap_after,
+            missing:    HashMap::new(),
+        }
+    }
+
+    /// Takes the jails found to be `missing` from the scrape at `now`, and
+    /// returns those that are due to be reaped. The remaining missing jails
+    /// are kept until a later scrape.
+    ///
+    /// Jails that were missing but are no longer are forgotten.
+    pub fn update(&mut self, missing: &HashSet<String>, now: Instant)
+    -> HashSet<String> {
+        self.missing.retain(|name, _| missing.contains(name));
+
+        let mut dead = HashSet::new();
+
+        for name in missing {
+            let entry = self.missing.entry(name.clone())
+                .or_insert(Missing {
+                    scrapes: 0,
+                    since:   now,
+                });
+
+            entry.scrapes = entry.scrapes.saturating_add(1);
+
+            let expired = match self.reap_after {
+                ReapAfter::Scrapes(scrapes) => entry.scrapes > scrapes,
+                ReapAfter::Duration(duration) => {
+                    now.saturating_duration_since(entry.since) >= duration
+                },
+            };
+
+            if expired {
+                dead.insert(name.clone());
+            }
+        }
+
+        self.missing.retain(|name, _| !dead.contains(name));
+
+        dead
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn names(names: &[&str]) -> HashSet<String> {
+        names.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn reaper_default() {
+        let mut reaper = Reaper::default();
+        let now = Instant::now();
+
+        // Missing jails are reaped immediately.
+        assert_eq!(reaper.update(&names(&["a"]), now), names(&["a"]));
+        assert!(reaper.missing.is_empty());
+    }
+
+    #[test]
+    fn reaper_scrapes() {
+        let mut reaper = Reaper::new(ReapAfter::Scrapes(2));
+        let now = Instant::now();
+
+        assert_eq!(reaper.update(&names(&["a", "b"]), now), names(&[]));
+        assert_eq!(reaper.update(&names(&["a", "b"]), now), names(&[]));
+
+        // b comes back, so its count starts over when it next goes missing.
+        assert_eq!(reaper.update(&names(&["a"]), now), names(&["a"]));
+        assert_eq!(reaper.update(&names(&["b"]), now), names(&[]));
+        assert_eq!(reaper.update(&names(&["b"]), now), names(&[]));
+        assert_eq!(reaper.update(&names(&["b"]), now), names(&["b"]));
+
+        assert!(reaper.missing.is_empty());
+    }
+
+    #[test]
+    fn reaper_duration() {
+        let mut reaper = Reaper::new(ReapAfter::Duration(Duration::from_secs(60)));
+        let start = Instant::now();
+        let after = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(reaper.update(&names(&["a"]), start), names(&[]));
+        assert_eq!(reaper.update(&names(&["a", "b"]), after(30)), names(&[]));
+        assert_eq!(reaper.update(&names(&["a", "b"]), after(60)), names(&["a"]));
+        assert_eq!(reaper.update(&names(&["b"]), after(89)), names(&[]));
+        assert_eq!(reaper.update(&names(&["b"]), after(90)), names(&["b"]));
+    }
+}
@@ -0,0 +1,627 @@
+// relabel: This module implements Prometheus style relabeling of the labels
+//          of each jail, renaming jails and deriving additional labels from
+//          their parameters.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::jailconf::ConfParams;
+use crate::jailsource::Jail;
+use prometheus_client::encoding::{
+    EncodeLabel,
+    EncodeLabelSet,
+    LabelSetEncoder,
+};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+// Jail parameters are available to the relabeling rules as meta labels with
+// this prefix, such as __param_host_hostname. Labels starting with __ are
+// removed once relabeling has finished.
+const PARAM_PREFIX: &str = "__param_";
+
+/// Labels of the exporter's own time series. Constant labels, and the labels
+/// added to the time series of jails, can't use these names as they'd be
+/// duplicated within those label sets.
+pub const RESERVED_LABELS: [&str; 26] = [
+    "action",
+    "collector",
+    "dataset",
+    "hostname",
+    "interface",
+    "ip4",
+    "ip6",
+    "jid",
+    "le",
+    "manager",
+    "name",
+    "osrelease",
+    "parent",
+    "path",
+    "per",
+    "persist",
+    "release",
+    "resource",
+    "result",
+    "rustversion",
+    "stage",
+    "state",
+    "tags",
+    "template",
+    "version",
+    "vnet",
+];
+
+/// Labels of a jail, keyed on label name.
+pub type Labels = BTreeMap<String, String>;
+
+/// The action taken by a relabeling rule.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Don't export the jail if the regex matches the source labels.
+    Drop,
+
+    /// Only export the jail if the regex matches the source labels.
+    Keep,
+
+    /// Remove the labels with names matching the regex.
+    LabelDrop,
+
+    /// Remove the labels with names not matching the regex.
+    LabelKeep,
+
+    /// Copy the labels with names matching the regex to the label named by
+    /// the replacement.
+    LabelMap,
+
+    /// Set the target label to the replacement if the regex matches the
+    /// source labels.
+    #[default]
+    Replace,
+}
+
+// A relabeling rule as written in the configuration.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    #[serde(default)]
+    action: Action,
+
+    #[serde(default)]
+    source_labels: Vec<String>,
+
+    #[serde(default = "default_separator")]
+    separator: String,
+
+    #[serde(default = "default_regex")]
+    regex: String,
+
+    target_label: Option<String>,
+
+    #[serde(default = "default_replacement")]
+    replacement: String,
+}
+
+fn default_separator() -> String {
+    ";".to_string()
+}
+
+fn default_regex() -> String {
+    "(.*)".to_string()
+}
+
+fn default_replacement() -> String {
+    "$1".to_string()
+}
+
+/// Returns true if `name` is a valid Prometheus label name.
+pub fn is_valid_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Returns true if `name` can be added to the time series of a jail, either
+// as its name or alongside the exporter's own labels.
+fn is_jail_label_name(name: &str) -> bool {
+    name == "name" || !RESERVED_LABELS.contains(&name)
+}
+
+/// Returns `name` with any characters other than ASCII letters and digits
+/// replaced by `_`.
+pub fn sanitize_label_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// A relabeling rule, applied to the labels of each jail in turn.
+#[derive(Clone, Debug)]
+pub struct RelabelRule {
+    action:        Action,
+    source_labels: Vec<String>,
+    separator:     String,
+    regex:         Regex,
+    target_label:  String,
+    replacement:   String,
+}
+
+impl TryFrom<RawRule> for RelabelRule {
+    type Error = String;
+
+    fn try_from(raw: RawRule) -> Result<Self, Self::Error> {
+        // As in Prometheus, the regex must match the whole value.
+        let regex = Regex::new(&format!("^(?:{})$", raw.regex))
+            .map_err(|e| format!("invalid regex '{}': {e}", raw.regex))?;
+
+        let target_label = raw.target_label.unwrap_or_default();
+
+        if raw.action == Action::Replace {
+            if target_label.is_empty() {
+                return Err("replace requires a target_label".to_string());
+            }
+
+            if !is_valid_label_name(&target_label) {
+                return Err(format!("invalid target_label '{target_label}'"));
+            }
+
+            if !is_jail_label_name(&target_label) {
+                return Err(format!("reserved target_label '{target_label}'"));
+            }
+        }
+
+        let rule = Self {
+            action:        raw.action,
+            source_labels: raw.source_labels,
+            separator:     raw.separator,
+            regex:         regex,
+            target_label:  target_label,
+            replacement:   raw.replacement,
+        };
+
+        Ok(rule)
+    }
+}
+
+impl<'de> Deserialize<'de> for RelabelRule {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let raw = RawRule::deserialize(deserializer)?;
+
+        Self::try_from(raw).map_err(serde::de::Error::custom)
+    }
+}
+
+impl RelabelRule {
+    // Applies the rule to the labels, returning false if the jail should be
+    // dropped.
+    fn apply(&self, labels: &mut Labels) -> bool {
+        let value = || {
+            self.source_labels.iter()
+                .map(|name| labels.get(name).map_or("", String::as_str))
+                .collect::<Vec<_>>()
+                .join(&self.separator)
+        };
+
+        match self.action {
+            Action::Drop => !self.regex.is_match(&value()),
+            Action::Keep => self.regex.is_match(&value()),
+            Action::LabelDrop => {
+                labels.retain(|name, _| !self.regex.is_match(name));
+                true
+            },
+            Action::LabelKeep => {
+                labels.retain(|name, _| self.regex.is_match(name));
+                true
+            },
+            Action::LabelMap => {
+                let mapped: Vec<(String, String)> = labels.iter()
+                    .filter(|(name, _)| self.regex.is_match(name))
+                    .map(|(name, value)| {
+                        let name = self.regex
+                            .replace(name, self.replacement.as_str())
+                            .into_owned();
+
+                        (name, value.clone())
+                    })
+                    .filter(|(name, _)| is_valid_label_name(name))
+                    .filter(|(name, _)| is_jail_label_name(name))
+                    .collect();
+
+                labels.extend(mapped);
+                true
+            },
+            Action::Replace => {
+                let value = value();
+
+                if let Some(captures) = self.regex.captures(&value) {
+                    let mut replacement = String::new();
+                    captures.expand(&self.replacement, &mut replacement);
+
+                    // An empty replacement removes the label.
+                    if replacement.is_empty() {
+                        labels.remove(&self.target_label);
+                    }
+                    else {
+                        labels.insert(self.target_label.clone(), replacement);
+                    }
+                }
+
+                true
+            },
+        }
+    }
+}
+
+/// The labels of a jail after relabeling.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JailLabels {
+    /// The name that the jail is exported under.
+    pub name: String,
+
+    /// Labels added to every time series of the jail, other than `name`.
+    pub labels: Labels,
+}
+
+/// A list of relabeling rules.
+///
+/// Each jail starts with its `name` label and a `__param_<param>` label for
+/// each of its parameters, with any characters that aren't valid in a label
+/// name replaced by `_`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Relabeler {
+    rules: Vec<RelabelRule>,
+
+    // Names of the constant labels, which relabeling can't add.
+    #[serde(skip)]
+    const_labels: Vec<String>,
+}
+
+impl Relabeler {
+    /// Reserves the names of the constant labels, which are already on every
+    /// time series. Rules setting one of them are an error, and labelmap
+    /// rules never map labels to them.
+    pub fn reserve<'a, I>(&mut self, names: I) -> Result<(), String>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        for name in names {
+            let target = self.rules.iter()
+                .filter(|rule| rule.action == Action::Replace)
+                .any(|rule| rule.target_label == name);
+
+            if target {
+                return Err(format!(
+                    "the {name} label can't be both a constant label and a \
+                     target_label"
+                ));
+            }
+
+            self.const_labels.push(name.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Returns the labels of the running `jail` after relabeling, or None if
+    /// the jail was dropped.
+    pub fn relabel(&self, jail: &Jail) -> Option<JailLabels> {
+        if self.rules.is_empty() {
+            return Some(JailLabels {
+                name:   jail.name.clone(),
+                labels: Labels::new(),
+            });
+        }
+
+        let params = jail.params.iter()
+            .map(|(param, value)| (param.as_str(), value.clone()));
+
+        self.apply(&jail.name, params)
+    }
+
+    /// Returns the labels of the named jail, configured with `params` in
+    /// jail.conf(5), after relabeling, or None if the jail was dropped.
+    pub fn relabel_conf(&self, name: &str, params: &ConfParams)
+    -> Option<JailLabels> {
+        let params = params.iter()
+            .map(|(param, values)| (param.as_str(), values.join(",")));
+
+        self.apply(name, params)
+    }
+
+    fn apply<'a, I>(&self, name: &str, params: I) -> Option<JailLabels>
+    where
+        I: Iterator<Item = (&'a str, String)>,
+    {
+        let mut labels: Labels = params
+            .map(|(param, value)| {
+                let param = sanitize_label_name(param);
+
+                (format!("{PARAM_PREFIX}{param}"), value)
+            })
+            .collect();
+
+        labels.insert("name".to_string(), name.to_string());
+
+        for rule in &self.rules {
+            if !rule.apply(&mut labels) {
+                return None;
+            }
+        }
+
+        labels.retain(|name, _| {
+            !name.starts_with("__") && !self.const_labels.contains(name)
+        });
+
+        // Jails that lost their name can't be exported.
+        let name = labels.remove("name").filter(|name| !name.is_empty())?;
+
+        Some(JailLabels {
+            name:   name,
+            labels: labels,
+        })
+    }
+}
+
+/// The label set of every time series of a jail: its `name` label, followed
+/// by the labels added to the jail in order of label name. The exporter's own
+/// labels follow these.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct JailLabelSet(Vec<(String, String)>);
+
+impl JailLabelSet {
+    /// Returns the label set of the named jail, with the given `labels` added.
+    pub fn new(name: &str, labels: Labels) -> Self {
+        let mut label_set = vec![("name".to_string(), name.to_string())];
+        label_set.extend(labels);
+
+        Self(label_set)
+    }
+
+    /// Returns the name of the jail.
+    pub fn name(&self) -> &str {
+        &self.0[0].1
+    }
+
+    /// Returns this label set with the given `labels` added after it, other
+    /// than those already present.
+    pub fn with_labels(&self, labels: Labels) -> Self {
+        let mut label_set = self.clone();
+
+        for (name, value) in labels {
+            if !label_set.0.iter().any(|(present, _)| *present == name) {
+                label_set.0.push((name, value));
+            }
+        }
+
+        label_set
+    }
+}
+
+// A label set with only the jail name.
+impl From<&str> for JailLabelSet {
+    fn from(name: &str) -> Self {
+        Self::new(name, Labels::new())
+    }
+}
+
+// prometheus_client writes label values as they are, so they're escaped here,
+// as jail names and parameters may hold any character.
+impl EncodeLabelSet for JailLabelSet {
+    fn encode(&self, encoder: &mut LabelSetEncoder) -> Result<(), fmt::Error> {
+        for (name, value) in &self.0 {
+            (name.as_str(), escape(value)).encode(encoder.encode_label())?;
+        }
+
+        Ok(())
+    }
+}
+
+// Escapes a label value for the text exposition format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use prometheus_client::encoding::text::encode;
+    use prometheus_client::metrics::{
+        family::Family,
+        gauge::Gauge,
+    };
+    use prometheus_client::registry::Registry;
+
+    fn relabeler(yaml: &str) -> Relabeler {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn label_set(pairs: &[(&str, &str)]) -> Labels {
+        pairs.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn relabel_default() {
+        let jail = Jail::with_params(1, "www", &[
+            ("host.hostname", "www.example"),
+        ]);
+
+        let labels = Relabeler::default().relabel(&jail);
+        let ok = JailLabels {
+            name:   "www".into(),
+            labels: Labels::new(),
+        };
+
+        assert_eq!(labels, Some(ok));
+    }
+
+    #[test]
+    fn relabel_replace() {
+        let relabeler = relabeler(r#"
+- source_labels: [name]
+  regex: "prod-(.*)"
+  target_label: name
+- source_labels: [__param_host_hostname]
+  regex: "[^.]+\\.([^.]+)\\..*"
+  target_label: team
+"#);
+
+        let jail = Jail::with_params(1, "prod-www", &[
+            ("host.hostname", "www.web.example.org"),
+        ]);
+
+        let labels = relabeler.relabel(&jail);
+        let ok = JailLabels {
+            name:   "www".into(),
+            labels: label_set(&[("team", "web")]),
+        };
+
+        assert_eq!(labels, Some(ok));
+
+        // Rules that don't match leave the labels alone.
+        let jail = Jail::with_params(1, "db", &[("host.hostname", "db")]);
+
+        let labels = relabeler.relabel(&jail);
+        let ok = JailLabels {
+            name:   "db".into(),
+            labels: Labels::new(),
+        };
+
+        assert_eq!(labels, Some(ok));
+    }
+
+    #[test]
+    fn relabel_keep_drop() {
+        let relabeler = relabeler(r#"
+- action: keep
+  source_labels: [__param_path]
+  regex: "/jails/.*"
+- action: drop
+  source_labels: [name]
+  regex: "ci-.*"
+"#);
+
+        let www = Jail::with_params(1, "www", &[("path", "/jails/www")]);
+        let ci = Jail::with_params(2, "ci-1234", &[("path", "/jails/ci-1234")]);
+
+        assert!(relabeler.relabel(&www).is_some());
+        assert!(relabeler.relabel(&ci).is_none());
+        assert!(relabeler.relabel(&Jail::new(1, "nopath")).is_none());
+    }
+
+    #[test]
+    fn relabel_labelmap_labeldrop() {
+        let relabeler = relabeler(r#"
+- action: labelmap
+  regex: "__param_(.+)"
+- action: labeldrop
+  regex: "devfs_ruleset"
+"#);
+
+        let jail = Jail::with_params(1, "www", &[
+            ("devfs_ruleset", "4"),
+            ("host.hostname", "www.example"),
+            ("path", "/jails/www"),
+        ]);
+
+        let labels = relabeler.relabel(&jail).unwrap();
+
+        // path is a label of the exporter's own, so it's never mapped.
+        assert_eq!(labels.labels, label_set(&[("host_hostname", "www.example")]));
+    }
+
+    #[test]
+    fn relabel_reserve() {
+        let mut relabeler = relabeler(r#"
+- action: labelmap
+  regex: "__param_(.+)"
+- source_labels: [name]
+  target_label: team
+"#);
+
+        // Constant labels can't be set by the rules.
+        assert!(relabeler.clone().reserve(["team"]).is_err());
+        relabeler.reserve(["datacenter", "host_hostname"]).unwrap();
+
+        let jail = Jail::with_params(1, "www", &[
+            ("datacenter", "dc1"),
+            ("host.hostname", "www.example"),
+        ]);
+
+        // Nor are labels mapped to them.
+        let labels = relabeler.relabel(&jail).unwrap();
+        assert_eq!(labels.labels, label_set(&[("team", "www")]));
+    }
+
+    #[test]
+    fn relabel_removing_name_drops_jail() {
+        let relabeler = relabeler(r#"
+- action: labelkeep
+  regex: "team"
+"#);
+
+        assert!(relabeler.relabel(&Jail::new(1, "www")).is_none());
+    }
+
+    #[test]
+    fn relabel_conf() {
+        let relabeler = relabeler(r#"
+- source_labels: [__param_path]
+  regex: "/jails/(.*)"
+  target_label: name
+"#);
+
+        let params = ConfParams::from([
+            ("path".into(), vec!["/jails/web".into()]),
+        ]);
+
+        let labels = relabeler.relabel_conf("www", &params).unwrap();
+        assert_eq!(labels.name, "web");
+    }
+
+    #[test]
+    fn relabel_rule_invalid() {
+        let tests = [
+            "- regex: \"(\"\n  target_label: a",
+            "- source_labels: [name]",
+            "- target_label: \"1abc\"",
+            "- target_label: state",
+            "- action: nope",
+            "- unknown: field",
+        ];
+
+        for yaml in tests {
+            assert!(serde_yaml::from_str::<Relabeler>(yaml).is_err(), "{yaml}");
+        }
+    }
+
+    #[test]
+    fn jail_label_set_encode() {
+        let mut registry = Registry::default();
+        let family = Family::<JailLabelSet, Gauge>::default();
+        registry.register("test", "Test", family.clone());
+
+        let labels = label_set(&[("team", "web"), ("owner", "a\"b")]);
+        let label_set = JailLabelSet::new("w\\w", labels);
+        family.get_or_create(&label_set).set(1);
+
+        let mut output = String::new();
+        encode(&mut output, &registry).unwrap();
+
+        assert_eq!(label_set.name(), "w\\w");
+        assert!(output.contains(
+            "test{name=\"w\\\\w\",owner=\"a\\\"b\",team=\"web\"} 1\n"
+        ));
+    }
+}
@@ -0,0 +1,733 @@
+// rusage: This module holds the table of rctl(8) resources, from which the
+//         time series of the resource usage of jails are registered, set and
+//         removed.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::collectors::{
+    Collector,
+    Collectors,
+    RCTL,
+};
+use crate::created::CreatedCounter;
+use crate::jailsource::Rusage;
+use crate::options::NamingScheme;
+use crate::rctltypes::Resource;
+use crate::relabel::JailLabelSet;
+use prometheus_client::metrics::{
+    family::Family,
+    gauge::Gauge,
+};
+use prometheus_client::registry::{
+    Metric,
+    Registry,
+    Unit,
+};
+use std::collections::HashMap;
+use std::sync::atomic::{
+    AtomicU64,
+    Ordering,
+};
+
+/// How the usage of a resource is exported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// A value kept by the OS that only increases while the jail runs,
+    /// exported as a counter.
+    Counter,
+
+    /// A value that can go up and down, exported as a gauge.
+    Gauge,
+
+    /// A percentage, exported as a gauge in percents under the legacy naming
+    /// scheme, and as a ratio with the given help under the conventional
+    /// naming scheme.
+    Percent(&'static str),
+}
+
+/// An rctl(8) resource, along with how its usage is exported.
+#[derive(Debug)]
+pub struct ResourceMetric {
+    /// The resource.
+    pub resource: Resource,
+
+    /// The collector enabling and disabling the resource.
+    pub collector: Collector,
+
+    /// Name of the time series under the legacy naming scheme.
+    pub legacy: &'static str,
+
+    /// Name of the time series under the conventional naming scheme.
+    pub conventional: &'static str,
+
+    /// Help of the time series, taken from rctl(8) where possible.
+    pub help: &'static str,
+
+    /// Unit of the time series, if it has one.
+    pub unit: Option<Unit>,
+
+    /// How the usage is exported.
+    pub kind: Kind,
+}
+
+impl ResourceMetric {
+    /// Returns the name of the time series under the given `naming` scheme,
+    /// without the prefix.
+    pub fn name(&self, naming: NamingScheme) -> &'static str {
+        naming.pick(self.legacy, self.conventional)
+    }
+}
+
+// Defines the collector of a single rctl resource, part of the rctl group.
+// The ID is the upper case name of the resource.
+macro_rules! rctl_collector {
+    ($name:literal, $id:literal $(,)?) => {
+        Collector {
+            name:    concat!("rctl.", $name),
+            help:    concat!("Export the ", $name, " rctl(8) resource."),
+            default: true,
+            group:   Some(RCTL),
+            id:      concat!("COLLECTOR_RCTL_", $id),
+            long:    concat!("collector.rctl.", $name),
+            no_id:   concat!("NO_COLLECTOR_RCTL_", $id),
+            no_long: concat!("no-collector.rctl.", $name),
+        }
+    };
+}
+
+/// Every rctl(8) resource, in order of rctl name, keyed on the rctl crate's
+/// `Resource` on FreeBSD. Adding a resource here is all that's needed to
+/// export it.
+pub static RESOURCES: [ResourceMetric; 25] = [
+    ResourceMetric {
+        resource:     Resource::CoreDumpSize,
+        collector:    rctl_collector!("coredumpsize", "COREDUMPSIZE"),
+        legacy:       "coredumpsize",
+        conventional: "core_dump_size",
+        help:         "core dump size, in bytes",
+        unit:         Some(Unit::Bytes),
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::CpuTime,
+        collector:    rctl_collector!("cputime", "CPUTIME"),
+        legacy:       "cputime",
+        conventional: "cpu",
+        help:         "CPU time, in seconds",
+        unit:         Some(Unit::Seconds),
+        kind:         Kind::Counter,
+    },
+    ResourceMetric {
+        resource:     Resource::DataSize,
+        collector:    rctl_collector!("datasize", "DATASIZE"),
+        legacy:       "datasize",
+        conventional: "data_size",
+        help:         "data size, in bytes",
+        unit:         Some(Unit::Bytes),
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::MaxProcesses,
+        collector:    rctl_collector!("maxproc", "MAXPROC"),
+        legacy:       "maxproc",
+        conventional: "process_count",
+        help:         "number of processes",
+        unit:         None,
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::MemoryLocked,
+        collector:    rctl_collector!("memorylocked", "MEMORYLOCKED"),
+        legacy:       "memorylocked",
+        conventional: "memory_locked",
+        help:         "locked memory, in bytes",
+        unit:         Some(Unit::Bytes),
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::MemoryUse,
+        collector:    rctl_collector!("memoryuse", "MEMORYUSE"),
+        legacy:       "memoryuse",
+        conventional: "memory_resident",
+        help:         "resident set size, in bytes",
+        unit:         Some(Unit::Bytes),
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::MsgqQueued,
+        collector:    rctl_collector!("msgqqueued", "MSGQQUEUED"),
+        legacy:       "msgqqueued",
+        conventional: "sysv_messages_queued",
+        help:         "number of queued SysV messages",
+        unit:         None,
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::MsgqSize,
+        collector:    rctl_collector!("msgqsize", "MSGQSIZE"),
+        legacy:       "msgqsize",
+        conventional: "sysv_message_queue_size",
+        help:         "SysV message queue size, in bytes",
+        unit:         Some(Unit::Bytes),
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::NMsgq,
+        collector:    rctl_collector!("nmsgq", "NMSGQ"),
+        legacy:       "nmsgq",
+        conventional: "sysv_message_queues",
+        help:         "number of SysV message queues",
+        unit:         None,
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::Nsem,
+        collector:    rctl_collector!("nsem", "NSEM"),
+        legacy:       "nsem",
+        conventional: "sysv_semaphores",
+        help:         "number of SysV semaphores",
+        unit:         None,
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::NSemop,
+        collector:    rctl_collector!("nsemop", "NSEMOP"),
+        legacy:       "nsemop",
+        conventional: "sysv_semaphore_operations",
+        help:         "number of SysV semaphores modified in a single \
+                       semop(2) call",
+        unit:         None,
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::NShm,
+        collector:    rctl_collector!("nshm", "NSHM"),
+        legacy:       "nshm",
+        conventional: "sysv_shared_memory_segments",
+        help:         "number of SysV shared memory segments",
+        unit:         None,
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::NThreads,
+        collector:    rctl_collector!("nthr", "NTHR"),
+        legacy:       "nthr",
+        conventional: "thread_count",
+        help:         "number of threads",
+        unit:         None,
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::OpenFiles,
+        collector:    rctl_collector!("openfiles", "OPENFILES"),
+        legacy:       "openfiles",
+        conventional: "open_files",
+        help:         "file descriptor table size",
+        unit:         None,
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::PercentCpu,
+        collector:    rctl_collector!("pcpu", "PCPU"),
+        legacy:       "pcpu_used",
+        conventional: "cpu_usage_ratio",
+        help:         "%CPU, in percents of a single CPU core",
+        unit:         None,
+        kind:         Kind::Percent(
+            "CPU usage, as a ratio of a single CPU core",
+        ),
+    },
+    ResourceMetric {
+        resource:     Resource::PseudoTerminals,
+        collector:    rctl_collector!("pseudoterminals", "PSEUDOTERMINALS"),
+        legacy:       "pseudoterminals",
+        conventional: "pseudo_terminals",
+        help:         "number of PTYs",
+        unit:         None,
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::ReadBps,
+        collector:    rctl_collector!("readbps", "READBPS"),
+        legacy:       "readbps",
+        conventional: "read_bytes_per_second",
+        help:         "filesystem reads, in bytes per second",
+        unit:         None,
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::ReadIops,
+        collector:    rctl_collector!("readiops", "READIOPS"),
+        legacy:       "readiops",
+        conventional: "read_operations_per_second",
+        help:         "filesystem reads, in operations per second",
+        unit:         None,
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::ShmSize,
+        collector:    rctl_collector!("shmsize", "SHMSIZE"),
+        legacy:       "shmsize",
+        conventional: "sysv_shared_memory_size",
+        help:         "SysV shared memory size, in bytes",
+        unit:         Some(Unit::Bytes),
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::StackSize,
+        collector:    rctl_collector!("stacksize", "STACKSIZE"),
+        legacy:       "stacksize",
+        conventional: "stack_size",
+        help:         "stack size, in bytes",
+        unit:         Some(Unit::Bytes),
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::SwapUse,
+        collector:    rctl_collector!("swapuse", "SWAPUSE"),
+        legacy:       "swapuse",
+        conventional: "swap",
+        help:         "swap space that may be reserved or used, in bytes",
+        unit:         Some(Unit::Bytes),
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::VMemoryUse,
+        collector:    rctl_collector!("vmemoryuse", "VMEMORYUSE"),
+        legacy:       "vmemoryuse",
+        conventional: "virtual_memory",
+        help:         "address space limit, in bytes",
+        unit:         Some(Unit::Bytes),
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::Wallclock,
+        collector:    rctl_collector!("wallclock", "WALLCLOCK"),
+        legacy:       "wallclock",
+        conventional: "wallclock",
+        help:         "wallclock time, in seconds",
+        unit:         Some(Unit::Seconds),
+        kind:         Kind::Counter,
+    },
+    ResourceMetric {
+        resource:     Resource::WriteBps,
+        collector:    rctl_collector!("writebps", "WRITEBPS"),
+        legacy:       "writebps",
+        conventional: "write_bytes_per_second",
+        help:         "filesystem writes, in bytes per second",
+        unit:         None,
+        kind:         Kind::Gauge,
+    },
+    ResourceMetric {
+        resource:     Resource::WriteIops,
+        collector:    rctl_collector!("writeiops", "WRITEIOPS"),
+        legacy:       "writeiops",
+        conventional: "write_operations_per_second",
+        help:         "filesystem writes, in operations per second",
+        unit:         None,
+        kind:         Kind::Gauge,
+    },
+];
+
+/// Returns the table entry of the given `resource`.
+pub fn find(resource: Resource) -> Option<&'static ResourceMetric> {
+    RESOURCES.iter().find(|metric| metric.resource == resource)
+}
+
+// Registers the `metric` with the `registry`, along with its unit if it has
+// one.
+fn register(
+    registry: &mut Registry,
+    name: &str,
+    help: &str,
+    unit: Option<&Unit>,
+    metric: impl Metric,
+) {
+    match unit {
+        Some(unit) => registry.register_with_unit(
+            name,
+            help,
+            unit.clone(),
+            metric,
+        ),
+        None => registry.register(name, help, metric),
+    }
+}
+
+// The family that the usage of a resource is exported with.
+#[derive(Debug)]
+enum ResourceFamily {
+    Counter(Family<JailLabelSet, CreatedCounter>),
+    Gauge(Family<JailLabelSet, Gauge>),
+    Ratio(Family<JailLabelSet, Gauge<f64, AtomicU64>>),
+}
+
+impl ResourceFamily {
+    // Registers a family for the resource with the `registry`, under the
+    // given `naming` scheme.
+    fn new(
+        registry: &mut Registry,
+        metric: &ResourceMetric,
+        naming: NamingScheme,
+    ) -> Self {
+        let name = metric.name(naming);
+        let unit = metric.unit.as_ref();
+
+        match (metric.kind, naming) {
+            (Kind::Counter, _) => {
+                let family = Family::default();
+                register(registry, name, metric.help, unit, family.clone());
+                Self::Counter(family)
+            },
+            (Kind::Percent(help), NamingScheme::Conventional) => {
+                let family = Family::default();
+                register(registry, name, help, unit, family.clone());
+                Self::Ratio(family)
+            },
+            (Kind::Gauge | Kind::Percent(_), _) => {
+                let family = Family::default();
+                register(registry, name, metric.help, unit, family.clone());
+                Self::Gauge(family)
+            },
+        }
+    }
+
+    // Sets the time series with the given `labels` to the `value` from the
+    // OS.
+    fn set(&self, labels: &JailLabelSet, value: usize) {
+        // The original value comes in as a usize. Convert it to a u64 for
+        // our Counter values, and clamp it down to an i64 for our Gauge
+        // values.
+        let unsigned = value as u64;
+
+        // Since we come from a usize, we can't get any negative values,
+        // so clamping to a 0 min is fine.
+        let signed = i64::try_from(unsigned.clamp(0, i64::MAX as u64))
+            .expect("i64 from u64");
+
+        match self {
+            Self::Counter(family) => {
+                // Counters should only ever increase. Store the value from
+                // the OS directly.
+                family.get_or_create(labels)
+                    .inner()
+                    .store(unsigned, Ordering::Relaxed);
+            },
+            Self::Gauge(family) => {
+                family.get_or_create(labels).set(signed);
+            },
+            Self::Ratio(family) => {
+                let ratio = unsigned as f64 / 100.0;
+                family.get_or_create(labels).set(ratio);
+            },
+        }
+    }
+
+    // Removes the time series with the given `labels`.
+    fn remove(&self, labels: &JailLabelSet) {
+        match self {
+            Self::Counter(family) => family.remove(labels),
+            Self::Gauge(family)   => family.remove(labels),
+            Self::Ratio(family)   => family.remove(labels),
+        };
+    }
+}
+
+/// Time series of the rctl(8) resource usage of jails, with a family for
+/// each enabled resource in [`RESOURCES`].
+pub struct RusageMetrics {
+    families: HashMap<Resource, ResourceFamily>,
+}
+
+impl RusageMetrics {
+    /// Registers the time series of the resources enabled in `collectors`
+    /// with the given `registry`, named according to the `naming` scheme.
+    pub fn new(
+        registry: &mut Registry,
+        naming: NamingScheme,
+        collectors: &Collectors,
+    ) -> Self {
+        let families = RESOURCES.iter()
+            .filter(|metric| collectors.is_resource_enabled(metric.resource))
+            .map(|metric| {
+                let family = ResourceFamily::new(registry, metric, naming);
+                (metric.resource, family)
+            })
+            .collect();
+
+        Self {
+            families: families,
+        }
+    }
+
+    /// Sets the time series of the jail with the given `labels` from its
+    /// `rusage`. Disabled resources are skipped.
+    pub fn update(&self, labels: &JailLabelSet, rusage: &Rusage) {
+        for (resource, value) in rusage {
+            if let Some(family) = self.families.get(resource) {
+                family.set(labels, *value);
+            }
+        }
+    }
+
+    /// Removes the time series of the jail with the given `labels`.
+    pub fn remove(&self, labels: &JailLabelSet) {
+        for family in self.families.values() {
+            family.remove(labels);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use prometheus_client::encoding::text::encode;
+    use std::collections::HashSet;
+
+    // Every variant of Resource.
+    const VARIANTS: [Resource; 25] = [
+        Resource::CoreDumpSize,
+        Resource::CpuTime,
+        Resource::DataSize,
+        Resource::MaxProcesses,
+        Resource::MemoryLocked,
+        Resource::MemoryUse,
+        Resource::MsgqQueued,
+        Resource::MsgqSize,
+        Resource::NMsgq,
+        Resource::Nsem,
+        Resource::NSemop,
+        Resource::NShm,
+        Resource::NThreads,
+        Resource::OpenFiles,
+        Resource::PercentCpu,
+        Resource::PseudoTerminals,
+        Resource::ReadBps,
+        Resource::ReadIops,
+        Resource::ShmSize,
+        Resource::StackSize,
+        Resource::SwapUse,
+        Resource::VMemoryUse,
+        Resource::Wallclock,
+        Resource::WriteBps,
+        Resource::WriteIops,
+    ];
+
+    // Returns the value of the counter of the named jail.
+    fn counter(metrics: &RusageMetrics, resource: Resource, name: &str)
+    -> u64 {
+        let labels = &JailLabelSet::from(name);
+
+        match &metrics.families[&resource] {
+            ResourceFamily::Counter(family) => {
+                family.get_or_create(labels).get()
+            },
+            other => panic!("{other:?} isn't a counter"),
+        }
+    }
+
+    fn encoded(registry: &Registry) -> String {
+        let mut buffer = String::new();
+        encode(&mut buffer, registry).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn resources_cover_every_variant() {
+        for resource in VARIANTS {
+            // This match is exhaustive. On FreeBSD, Resource is the rctl
+            // crate's own, so a new resource there fails to compile until
+            // it's added to VARIANTS.
+            match resource {
+                Resource::CoreDumpSize
+                | Resource::CpuTime
+                | Resource::DataSize
+                | Resource::MaxProcesses
+                | Resource::MemoryLocked
+                | Resource::MemoryUse
+                | Resource::MsgqQueued
+                | Resource::MsgqSize
+                | Resource::NMsgq
+                | Resource::Nsem
+                | Resource::NSemop
+                | Resource::NShm
+                | Resource::NThreads
+                | Resource::OpenFiles
+                | Resource::PercentCpu
+                | Resource::PseudoTerminals
+                | Resource::ReadBps
+                | Resource::ReadIops
+                | Resource::ShmSize
+                | Resource::StackSize
+                | Resource::SwapUse
+                | Resource::VMemoryUse
+                | Resource::Wallclock
+                | Resource::WriteBps
+                | Resource::WriteIops => {},
+            }
+
+            assert!(find(resource).is_some(), "{resource} isn't in RESOURCES");
+        }
+
+        let resources: HashSet<_> = RESOURCES.iter()
+            .map(|metric| metric.resource)
+            .collect();
+
+        assert_eq!(resources.len(), RESOURCES.len());
+    }
+
+    #[test]
+    fn resources_collectors() {
+        for metric in &RESOURCES {
+            let name = metric.resource.as_str();
+            let id = name.to_uppercase();
+
+            assert_eq!(metric.collector.name, format!("rctl.{name}"));
+            assert_eq!(metric.collector.id, format!("COLLECTOR_RCTL_{id}"));
+        }
+    }
+
+    #[test]
+    fn resources_names_unique() {
+        for naming in [NamingScheme::Legacy, NamingScheme::Conventional] {
+            let names: HashSet<_> = RESOURCES.iter()
+                .map(|metric| metric.name(naming))
+                .collect();
+
+            assert_eq!(names.len(), RESOURCES.len(), "{naming}");
+        }
+    }
+
+    #[test]
+    fn rusage_metrics_register() {
+        let mut registry = Registry::default();
+        let mut collectors = Collectors::default();
+        collectors.set("rctl.nmsgq", false).unwrap();
+
+        let metrics = RusageMetrics::new(
+            &mut registry,
+            NamingScheme::Legacy,
+            &collectors,
+        );
+
+        assert_eq!(metrics.families.len(), RESOURCES.len() - 1);
+        assert!(!metrics.families.contains_key(&Resource::NMsgq));
+
+        let output = encoded(&registry);
+        assert!(output.contains("# TYPE cputime_seconds counter\n"));
+        assert!(output.contains("# TYPE memoryuse_bytes gauge\n"));
+        assert!(output.contains("# HELP pcpu_used %CPU"));
+        assert!(!output.contains("nmsgq"));
+    }
+
+    #[test]
+    fn rusage_metrics_update() {
+        let mut registry = Registry::default();
+        let metrics = RusageMetrics::new(
+            &mut registry,
+            NamingScheme::Conventional,
+            &Collectors::default(),
+        );
+
+        let rusage = Rusage::from([
+            (Resource::MemoryUse, 2048),
+            (Resource::PercentCpu, 150),
+        ]);
+        metrics.update(&"test".into(), &rusage);
+
+        let output = encoded(&registry);
+        assert!(output.contains("memory_resident_bytes{name=\"test\"} 2048\n"));
+        assert!(output.contains("cpu_usage_ratio{name=\"test\"} 1.5\n"));
+
+        metrics.remove(&"test".into());
+
+        let output = encoded(&registry);
+        assert!(!output.contains("{name=\"test\"}"));
+    }
+
+    #[test]
+    fn cputime_counter_increase() {
+        let names = ["test", "test2"];
+        let mut registry = Registry::default();
+        let metrics = RusageMetrics::new(
+            &mut registry,
+            NamingScheme::Legacy,
+            &Collectors::default(),
+        );
+
+        for name in names {
+            let mut hash = Rusage::new();
+
+            // Initial check, should be zero. We didn't set anything yet.
+            assert_eq!(counter(&metrics, Resource::CpuTime, name), 0);
+
+            // First run, adds 1000, total 1000.
+            hash.insert(Resource::CpuTime, 1000);
+            metrics.update(&name.into(), &hash);
+            assert_eq!(counter(&metrics, Resource::CpuTime, name), 1000);
+
+            // Second, adds 20, total 1020
+            hash.insert(Resource::CpuTime, 1020);
+            metrics.update(&name.into(), &hash);
+            assert_eq!(counter(&metrics, Resource::CpuTime, name), 1020);
+
+            // Third, counter was reset. Adds 10, total 1030.
+            hash.insert(Resource::CpuTime, 10);
+            metrics.update(&name.into(), &hash);
+            assert_eq!(counter(&metrics, Resource::CpuTime, name), 10);
+
+            // Fourth, adds 40, total 1070.
+            hash.insert(Resource::CpuTime, 50);
+            metrics.update(&name.into(), &hash);
+            assert_eq!(counter(&metrics, Resource::CpuTime, name), 50);
+
+            // Fifth, add 0, total 1070
+            hash.insert(Resource::CpuTime, 50);
+            metrics.update(&name.into(), &hash);
+            assert_eq!(counter(&metrics, Resource::CpuTime, name), 50);
+        }
+    }
+
+    #[test]
+    fn wallclock_counter_increase() {
+        let names = ["test", "test2"];
+        let mut registry = Registry::default();
+        let metrics = RusageMetrics::new(
+            &mut registry,
+            NamingScheme::Legacy,
+            &Collectors::default(),
+        );
+
+        for name in names {
+            let mut hash = Rusage::new();
+
+            // Initial check, should be zero. We didn't set anything yet.
+            assert_eq!(counter(&metrics, Resource::Wallclock, name), 0);
+
+            // First run, adds 1000, total 1000.
+            hash.insert(Resource::Wallclock, 1000);
+            metrics.update(&name.into(), &hash);
+            assert_eq!(counter(&metrics, Resource::Wallclock, name), 1000);
+
+            // Second, adds 20, total 1020
+            hash.insert(Resource::Wallclock, 1020);
+            metrics.update(&name.into(), &hash);
+            assert_eq!(counter(&metrics, Resource::Wallclock, name), 1020);
+
+            // Third, counter was reset. Adds 10, total 1030.
+            hash.insert(Resource::Wallclock, 10);
+            metrics.update(&name.into(), &hash);
+            assert_eq!(counter(&metrics, Resource::Wallclock, name), 10);
+
+            // Fourth, adds 40, total 1070.
+            hash.insert(Resource::Wallclock, 50);
+            metrics.update(&name.into(), &hash);
+            assert_eq!(counter(&metrics, Resource::Wallclock, name), 50);
+
+            // Fifth, add 0, total 1070
+            hash.insert(Resource::Wallclock, 50);
+            metrics.update(&name.into(), &hash);
+            assert_eq!(counter(&metrics, Resource::Wallclock, name), 50);
+        }
+    }
+}
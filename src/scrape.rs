@@ -0,0 +1,199 @@
+// scrape: This module instruments the exporter itself, timing each scrape
+//         along with the collectors run during it.
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::{
+    register_counter_with_registry,
+    register_float_gauge_with_registry,
+};
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::encoding::text::encode;
+use prometheus_client::metrics::{
+    counter::Counter,
+    family::Family,
+    gauge::Gauge,
+    histogram::Histogram,
+};
+use prometheus_client::registry::{
+    Registry,
+    Unit,
+};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::atomic::AtomicU64;
+use std::time::{
+    Duration,
+    Instant,
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+// Buckets of the scrape duration histogram, the Prometheus client defaults.
+const DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct CollectorLabel {
+    // Name of the collector.
+    collector: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ResultLabel {
+    // Whether the scrape succeeded or failed.
+    result: String,
+}
+
+/// Time taken by each collector during a scrape, added up across the jails.
+#[derive(Debug, Default)]
+pub struct CollectorTimes {
+    times: BTreeMap<&'static str, Duration>,
+}
+
+impl CollectorTimes {
+    /// Runs `f`, adding the time that it took to the named collector.
+    pub fn time<T>(&mut self, collector: &'static str, f: impl FnOnce() -> T)
+    -> T {
+        let start = Instant::now();
+        let res = f();
+
+        *self.times.entry(collector).or_default() += start.elapsed();
+
+        res
+    }
+}
+
+/// Time series describing the scrapes of the exporter.
+///
+/// These live in their own registry, which is encoded after the time series
+/// of the jails.
+pub struct ScrapeMetrics {
+    registry: Registry,
+
+    collector_duration: Family<CollectorLabel, Gauge<f64, AtomicU64>>,
+    duration:           Histogram,
+    last_scrape:        Gauge<f64, AtomicU64>,
+    scrapes:            Family<ResultLabel, Counter>,
+}
+
+impl ScrapeMetrics {
+    /// Registers the scrape time series under `<prefix>_exporter`, with the
+    /// given constant labels.
+    pub fn new<L>(prefix: &str, labels: L) -> Self
+    where
+        L: Iterator<Item = (Cow<'static, str>, Cow<'static, str>)>,
+    {
+        let prefix = format!("{prefix}_exporter");
+        let mut registry = Registry::with_prefix_and_labels(prefix, labels);
+
+        let duration = Histogram::new(DURATION_BUCKETS);
+        registry.register_with_unit(
+            "scrape_duration",
+            "Time taken to collect the time series of a scrape",
+            Unit::Seconds,
+            duration.clone(),
+        );
+
+        let last_scrape = Gauge::<f64, AtomicU64>::default();
+        registry.register_with_unit(
+            "last_scrape_timestamp",
+            "Time of the end of the last scrape since the Unix epoch",
+            Unit::Seconds,
+            last_scrape.clone(),
+        );
+
+        Self {
+            collector_duration: register_float_gauge_with_registry!(
+                "collector_duration",
+                "Time taken by the named collector during the last scrape",
+                CollectorLabel,
+                Unit::Seconds,
+                registry,
+            ),
+
+            scrapes: register_counter_with_registry!(
+                "scrapes",
+                "Number of scrapes, by whether they succeeded",
+                ResultLabel,
+                registry,
+            ),
+
+            duration:    duration,
+            last_scrape: last_scrape,
+            registry:    registry,
+        }
+    }
+
+    /// Records a scrape that started at `start`, with the `times` of its
+    /// collectors.
+    pub fn observe(&self, start: Instant, times: &CollectorTimes, ok: bool) {
+        self.duration.observe(start.elapsed().as_secs_f64());
+
+        for (collector, time) in &times.times {
+            let labels = &CollectorLabel {
+                collector: (*collector).to_string(),
+            };
+
+            self.collector_duration
+                .get_or_create(labels)
+                .set(time.as_secs_f64());
+        }
+
+        let labels = &ResultLabel {
+            result: if ok { "success" } else { "failure" }.to_string(),
+        };
+        self.scrapes.get_or_create(labels).inc();
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |now| now.as_secs_f64());
+        self.last_scrape.set(now);
+    }
+
+    /// Returns the encoded time series, ending the exposition.
+    pub fn encode(&self) -> String {
+        let mut buffer = String::new();
+        encode(&mut buffer, &self.registry).expect("encode");
+
+        buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn scrape_metrics_observe() {
+        let labels = [("dc".into(), "dc1".into())].into_iter();
+        let metrics = ScrapeMetrics::new("jail", labels);
+
+        let mut times = CollectorTimes::default();
+        let value = times.time("zfs", || 1);
+        assert_eq!(value, 1);
+
+        metrics.observe(Instant::now(), &times, true);
+        metrics.observe(Instant::now(), &CollectorTimes::default(), false);
+
+        let output = metrics.encode();
+        assert!(output.contains(
+            "jail_exporter_scrapes_total{dc=\"dc1\",result=\"success\"} 1\n"
+        ));
+        assert!(output.contains(
+            "jail_exporter_scrapes_total{dc=\"dc1\",result=\"failure\"} 1\n"
+        ));
+        assert!(output.contains(
+            "jail_exporter_scrape_duration_seconds_count{dc=\"dc1\"} 2\n"
+        ));
+        assert!(output.contains(
+            "jail_exporter_collector_duration_seconds{dc=\"dc1\",\
+             collector=\"zfs\"}"
+        ));
+        assert!(output.contains(
+            "# TYPE jail_exporter_last_scrape_timestamp_seconds gauge\n"
+        ));
+        assert!(output.ends_with("# EOF\n"));
+    }
+}
@@ -0,0 +1,448 @@
+// zfs: This module exports the usage of the ZFS datasets holding each jail's
+//      root, as reported by zfs(8).
+#![forbid(unsafe_code)]
+#![deny(missing_docs)]
+use crate::{
+    register_float_gauge_with_registry,
+    register_gauge_with_registry,
+};
+use crate::command::CommandRunner;
+use crate::errors::ExporterError;
+use crate::jailsource::Jail;
+use crate::relabel::JailLabelSet;
+use parking_lot::Mutex;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::{
+    family::Family,
+    gauge::Gauge,
+};
+use prometheus_client::registry::{
+    Registry,
+    Unit,
+};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::AtomicU64;
+use tracing::warn;
+
+// Properties requested from `zfs list`, in the order that they're output.
+const ZFS_PROPERTIES: &str = "name,mountpoint,used,available,referenced,\
+                              quota,refquota,compressratio";
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct DatasetLabels {
+    // Jail name, along with the labels added to the jail.
+    #[prometheus(flatten)]
+    jail: JailLabelSet,
+
+    // Name of the ZFS dataset holding the jail's path.
+    dataset: String,
+}
+
+/// A ZFS dataset, as output by `zfs list -Hp`.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Dataset {
+    name:          String,
+    mountpoint:    String,
+    used:          u64,
+    available:     u64,
+    referenced:    u64,
+    quota:         u64,
+    refquota:      u64,
+    compressratio: f64,
+}
+
+// Parses a tab separated line of `zfs list -Hp -o ZFS_PROPERTIES`.
+fn parse_dataset(line: &str) -> Result<Dataset, ExporterError> {
+    let invalid = || {
+        ExporterError::ZfsError(format!("invalid zfs list output: {line:?}"))
+    };
+
+    let fields: Vec<&str> = line.split('\t').collect();
+
+    let [
+        name,
+        mountpoint,
+        used,
+        available,
+        referenced,
+        quota,
+        refquota,
+        compressratio,
+    ] = fields[..] else {
+        return Err(invalid());
+    };
+
+    let bytes = |value: &str| value.parse::<u64>().map_err(|_| invalid());
+
+    // Depending on the version of ZFS, the ratio may have a trailing 'x' even
+    // in parsable output.
+    let compressratio = compressratio.trim_end_matches('x')
+        .parse::<f64>()
+        .map_err(|_| invalid())?;
+
+    Ok(Dataset {
+        name:          name.to_string(),
+        mountpoint:    mountpoint.to_string(),
+        used:          bytes(used)?,
+        available:     bytes(available)?,
+        referenced:    bytes(referenced)?,
+        quota:         bytes(quota)?,
+        refquota:      bytes(refquota)?,
+        compressratio: compressratio,
+    })
+}
+
+// Parses the output of `zfs list -Hp -o ZFS_PROPERTIES`. Lines that can't be
+// parsed are skipped, leaving the jails on those datasets without time series.
+fn parse_zfs_list(output: &str) -> Vec<Dataset> {
+    output.lines()
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| {
+            parse_dataset(line)
+                .inspect_err(|e| warn!("Skipping ZFS dataset: {e}"))
+                .ok()
+        })
+        .collect()
+}
+
+// Finds the dataset holding `path`, which is the dataset with the longest
+// mountpoint containing the path.
+fn dataset_for_path<'a>(
+    datasets: &'a [Dataset],
+    path: &Path,
+) -> Option<&'a Dataset> {
+    datasets.iter()
+        // Skips legacy, none and "-" mountpoints.
+        .filter(|dataset| dataset.mountpoint.starts_with('/'))
+        .filter(|dataset| path.starts_with(&dataset.mountpoint))
+        .max_by_key(|dataset| {
+            Path::new(&dataset.mountpoint).components().count()
+        })
+}
+
+/// Time series describing the ZFS datasets holding jails.
+pub struct ZfsMetrics {
+    available:     Family<DatasetLabels, Gauge>,
+    compressratio: Family<DatasetLabels, Gauge<f64, AtomicU64>>,
+    quota:         Family<DatasetLabels, Gauge>,
+    referenced:    Family<DatasetLabels, Gauge>,
+    refquota:      Family<DatasetLabels, Gauge>,
+    used:          Family<DatasetLabels, Gauge>,
+
+    // The collector is only enabled once it has a runner.
+    runner: Option<Box<dyn CommandRunner + Send + Sync>>,
+
+    // The label set of each jail, kept so that we can remove the old label
+    // set if a jail moves to another dataset.
+    label_sets: Mutex<HashMap<String, DatasetLabels>>,
+}
+
+impl ZfsMetrics {
+    /// Registers the ZFS time series with the given `registry`.
+    pub fn new(registry: &mut Registry) -> Self {
+        Self {
+            available: register_gauge_with_registry!(
+                "zfs_available",
+                "Space available to the ZFS dataset holding the named jail, \
+                 in bytes",
+                DatasetLabels,
+                Unit::Bytes,
+                registry,
+            ),
+
+            compressratio: register_float_gauge_with_registry!(
+                "zfs_compressratio",
+                "Compression ratio achieved for the ZFS dataset holding the \
+                 named jail",
+                DatasetLabels,
+                registry,
+            ),
+
+            quota: register_gauge_with_registry!(
+                "zfs_quota",
+                "Quota of the ZFS dataset holding the named jail, in bytes, \
+                 0 if there is no quota",
+                DatasetLabels,
+                Unit::Bytes,
+                registry,
+            ),
+
+            referenced: register_gauge_with_registry!(
+                "zfs_referenced",
+                "Data referenced by the ZFS dataset holding the named jail, \
+                 in bytes",
+                DatasetLabels,
+                Unit::Bytes,
+                registry,
+            ),
+
+            refquota: register_gauge_with_registry!(
+                "zfs_refquota",
+                "Reference quota of the ZFS dataset holding the named jail, \
+                 in bytes, 0 if there is no quota",
+                DatasetLabels,
+                Unit::Bytes,
+                registry,
+            ),
+
+            used: register_gauge_with_registry!(
+                "zfs_used",
+                "Space used by the ZFS dataset holding the named jail and \
+                 its descendents, in bytes",
+                DatasetLabels,
+                Unit::Bytes,
+                registry,
+            ),
+
+            runner:     None,
+            label_sets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Enables the collector, listing datasets with the given `runner`.
+    pub fn set_runner<R>(&mut self, runner: R)
+    where
+        R: CommandRunner + Send + Sync + 'static,
+    {
+        self.runner = Some(Box::new(runner));
+    }
+
+    /// Sets the ZFS time series for the given jails, from their `path`
+    /// parameters, with the label set of each jail.
+    pub fn update(&self, jails: &[(Jail, JailLabelSet)]) {
+        let Some(runner) = &self.runner else {
+            return;
+        };
+
+        // Without the datasets, there's nothing current to export, but the
+        // rest of the scrape can continue.
+        let args = ["list", "-Hp", "-t", "filesystem", "-o", ZFS_PROPERTIES];
+        let output = match runner.run("zfs", &args) {
+            Ok(output) => output,
+            Err(e) => {
+                warn!("Couldn't list ZFS datasets: {e}");
+
+                for (_, labels) in jails {
+                    self.remove(labels);
+                }
+
+                return;
+            },
+        };
+
+        let datasets = parse_zfs_list(&output);
+
+        for (jail, jail_labels) in jails {
+            let dataset = jail.param("path")
+                .filter(|path| !path.is_empty())
+                .and_then(|path| dataset_for_path(&datasets, Path::new(path)));
+
+            let Some(dataset) = dataset else {
+                self.remove(jail_labels);
+                continue;
+            };
+
+            let labels = DatasetLabels {
+                jail:    jail_labels.clone(),
+                dataset: dataset.name.clone(),
+            };
+
+            let mut label_sets = self.label_sets.lock();
+
+            if let Some(old) = label_sets.get(&jail.name) {
+                if *old != labels {
+                    self.remove_labels(old);
+                }
+            }
+
+            let values = [
+                (&self.available, dataset.available),
+                (&self.quota, dataset.quota),
+                (&self.referenced, dataset.referenced),
+                (&self.refquota, dataset.refquota),
+                (&self.used, dataset.used),
+            ];
+
+            for (family, value) in values {
+                let value = i64::try_from(value).unwrap_or(i64::MAX);
+                family.get_or_create(&labels).set(value);
+            }
+
+            self.compressratio
+                .get_or_create(&labels)
+                .set(dataset.compressratio);
+
+            label_sets.insert(jail.name.clone(), labels);
+        }
+    }
+
+    fn remove_labels(&self, labels: &DatasetLabels) {
+        self.available.remove(labels);
+        self.compressratio.remove(labels);
+        self.quota.remove(labels);
+        self.referenced.remove(labels);
+        self.refquota.remove(labels);
+        self.used.remove(labels);
+    }
+
+    /// Removes the ZFS time series for the jail with the given `labels`.
+    pub fn remove(&self, labels: &JailLabelSet) {
+        if let Some(labels) = self.label_sets.lock().remove(labels.name()) {
+            self.remove_labels(&labels);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const ZFS_LIST: &str = include_str!("../test-data/zfs/list.txt");
+
+    // Returns captured output instead of running commands.
+    struct FixtureRunner(String);
+
+    impl CommandRunner for FixtureRunner {
+        fn run(&self, program: &str, args: &[&str]) -> Result<String, ExporterError> {
+            assert_eq!(program, "zfs");
+            assert_eq!(args[0], "list");
+
+            Ok(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn parse_zfs_list_ok() {
+        let datasets = parse_zfs_list(ZFS_LIST);
+        assert_eq!(datasets.len(), 7);
+
+        let ok = Dataset {
+            name:          "zroot/jails/www".into(),
+            mountpoint:    "/jails/www".into(),
+            used:          1_288_490_188,
+            available:     9_448_928_051,
+            referenced:    1_073_741_824,
+            quota:         10_737_418_240,
+            refquota:      0,
+            compressratio: 1.87,
+        };
+
+        assert_eq!(datasets[5], ok);
+    }
+
+    #[test]
+    fn parse_dataset_invalid() {
+        let tests = [
+            "zroot\t/zroot\t1\t2\t3\t4\t5",
+            "zroot\t/zroot\t1\t2\t3\t4\t5\tnope",
+            "zroot\t/zroot\tnope\t2\t3\t4\t5\t1.00",
+        ];
+
+        for test in tests {
+            assert!(parse_dataset(test).is_err());
+        }
+
+        // A trailing 'x' on the ratio is fine.
+        let dataset = parse_dataset("zroot\t/zroot\t1\t2\t3\t4\t5\t1.50x")
+            .unwrap();
+        assert_eq!(dataset.compressratio, 1.5);
+    }
+
+    #[test]
+    fn parse_zfs_list_skips_invalid() {
+        let output = "zroot\t/zroot\tnope\t2\t3\t4\t5\t1.00\n\
+                      zroot/jails\t/jails\t1\t2\t3\t4\t5\t1.00\n";
+        let datasets = parse_zfs_list(output);
+
+        assert_eq!(datasets.len(), 1);
+        assert_eq!(datasets[0].name, "zroot/jails");
+    }
+
+    #[test]
+    fn dataset_for_path_ok() {
+        let datasets = parse_zfs_list(ZFS_LIST);
+        let name = |path: &str| {
+            dataset_for_path(&datasets, Path::new(path))
+                .map(|dataset| dataset.name.as_str())
+        };
+
+        assert_eq!(name("/jails/www"), Some("zroot/jails/www"));
+        assert_eq!(name("/jails/www/root"), Some("zroot/jails/www"));
+
+        // Not a dataset of its own, so held by the parent.
+        assert_eq!(name("/jails/wwwtest"), Some("zroot/jails"));
+
+        // Datasets with legacy mountpoints are never matched.
+        assert_eq!(name("/usr/local"), Some("zroot/ROOT/default"));
+        assert_eq!(name("legacy"), None);
+    }
+
+    #[test]
+    fn zfs_metrics_update() {
+        let mut registry = Registry::default();
+        let mut metrics = ZfsMetrics::new(&mut registry);
+        metrics.set_runner(FixtureRunner(ZFS_LIST.into()));
+
+        let jail = |jid, name: &str, path| {
+            (Jail::with_params(jid, name, &[("path", path)]), name.into())
+        };
+
+        let jails = [
+            jail(1, "www", "/jails/www"),
+            jail(2, "db", "/jails/db"),
+        ];
+
+        metrics.update(&jails);
+
+        let labels = DatasetLabels {
+            jail:    "db".into(),
+            dataset: "zroot/jails/db".into(),
+        };
+
+        assert_eq!(metrics.refquota.get_or_create(&labels).get(), 5_368_709_120);
+        assert_eq!(metrics.compressratio.get_or_create(&labels).get(), 2.05);
+
+        metrics.remove(&"db".into());
+        assert!(metrics.used.get(&labels).is_none());
+    }
+
+    #[test]
+    fn zfs_metrics_disabled() {
+        let mut registry = Registry::default();
+        let metrics = ZfsMetrics::new(&mut registry);
+
+        let jail = Jail::with_params(1, "www", &[("path", "/jails/www")]);
+
+        metrics.update(&[(jail, "www".into())]);
+        assert!(metrics.label_sets.lock().is_empty());
+    }
+
+    #[test]
+    fn zfs_metrics_update_failed() {
+        // zfs(8) that can't be run.
+        struct FailingRunner;
+
+        impl CommandRunner for FailingRunner {
+            fn run(&self, _program: &str, _args: &[&str])
+            -> Result<String, ExporterError> {
+                Err(ExporterError::ZfsError("zfs not found".into()))
+            }
+        }
+
+        let mut registry = Registry::default();
+        let mut metrics = ZfsMetrics::new(&mut registry);
+        let jail = Jail::with_params(1, "www", &[("path", "/jails/www")]);
+        let jails = [(jail, "www".into())];
+
+        metrics.set_runner(FixtureRunner(ZFS_LIST.into()));
+        metrics.update(&jails);
+        assert!(metrics.label_sets.lock().contains_key("www"));
+
+        // The stale time series are removed.
+        metrics.set_runner(FailingRunner);
+        metrics.update(&jails);
+        assert!(metrics.label_sets.lock().is_empty());
+    }
+}